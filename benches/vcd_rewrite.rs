@@ -0,0 +1,120 @@
+//! Throughput of the [VcdRewriter] on a synthetic trace.
+//!
+//! The size of the trace is set by `TYWAVES_BENCH_SIZE_MB` (16 MB by default), for example:
+//! `TYWAVES_BENCH_SIZE_MB=4096 cargo bench --bench vcd_rewrite` rewrites a 4 GB trace.
+//! The trace is generated in the temporary directory and removed at the end.
+
+use std::fs::File;
+use std::io::BufWriter;
+use std::time::Instant;
+
+use tywaves_rs::tyvcd::spec::{Scope, TypeInfo, Variable, VariableKind};
+use tywaves_rs::tyvcd::trace_pointer::{TraceGetter, TraceValue};
+use tywaves_rs::vcd_rewrite::VcdRewriter;
+
+const MODULES: usize = 16;
+const STRUCTS: usize = 32;
+const FIELDS: usize = 8;
+const WIDTH: u32 = 16;
+
+// Write a trace where every timestamp changes one field of each module.
+fn generate_trace(path: &str, size: u64) -> std::io::Result<()> {
+    let mut writer = vcd::Writer::new(BufWriter::new(File::create(path)?));
+    writer.timescale(1, vcd::TimescaleUnit::NS)?;
+    let mut id_codes = Vec::new();
+    for m in 0..MODULES {
+        writer.add_module(&format!("m{}", m))?;
+        for s in 0..STRUCTS {
+            for f in 0..FIELDS {
+                let name = format!("s{}_f{}", s, f);
+                id_codes.push(writer.add_var(vcd::VarType::Wire, WIDTH, &name, None)?);
+            }
+        }
+        writer.upscope()?;
+    }
+    writer.enddefinitions()?;
+
+    let per_module = STRUCTS * FIELDS;
+    let mut time = 0;
+    let mut written = 0;
+    while written < size {
+        writer.timestamp(time)?;
+        for m in 0..MODULES {
+            let idx = m * per_module + (time as usize * 7) % per_module;
+            let value = (time as u32).wrapping_mul(2654435761) >> (32 - WIDTH);
+            let bits = (0..WIDTH).rev().map(|i| ((value >> i) & 1 == 1).into());
+            writer.change_vector(id_codes[idx], bits)?;
+            written += WIDTH as u64 + 6;
+        }
+        time += 1;
+    }
+    Ok(())
+}
+
+// Build the typed scopes of the synthetic trace: a struct for each group of fields.
+fn typed_scopes() -> Vec<Scope> {
+    (0..MODULES)
+        .map(|m| {
+            let name = format!("m{}", m);
+            let mut scope = Scope::empty(
+                name.clone(),
+                "Module".to_string(),
+                TypeInfo::new("Module".to_string(), vec![]),
+                &[],
+            );
+            for s in 0..STRUCTS {
+                let fields = (0..FIELDS)
+                    .map(|f| {
+                        Variable::new(
+                            TraceValue::RefTraceName(format!("s{}_f{}", s, f)),
+                            format!("f{}", f),
+                            TypeInfo::new(format!("UInt<{}>", WIDTH), vec![]),
+                            VariableKind::Ground(WIDTH as u128),
+                        )
+                    })
+                    .collect::<Vec<_>>();
+                let trace_values = fields.iter().map(|f| f.get_trace_value().clone());
+                scope.variables.push(Variable::new(
+                    TraceValue::RefTraceValues(trace_values.collect()),
+                    format!("s{}", s),
+                    TypeInfo::new("Bundle".to_string(), vec![]),
+                    VariableKind::Struct { fields },
+                ));
+            }
+            scope
+        })
+        .collect()
+}
+
+fn main() {
+    let size_mb: u64 = std::env::var("TYWAVES_BENCH_SIZE_MB")
+        .ok()
+        .and_then(|size| size.parse().ok())
+        .unwrap_or(16);
+    let dir = std::env::temp_dir();
+    let input = dir.join("tywaves_bench_input.vcd");
+    let output = dir.join("tywaves_bench_output.vcd");
+
+    generate_trace(input.to_str().unwrap(), size_mb << 20).expect("failed to generate the trace");
+    let input_size = std::fs::metadata(&input).unwrap().len();
+
+    let start = Instant::now();
+    let mut rewriter =
+        VcdRewriter::new(&input, typed_scopes(), output.to_string_lossy().to_string())
+            .expect("failed to create the rewriter");
+    rewriter.rewrite().expect("failed to rewrite");
+    let elapsed = start.elapsed();
+
+    let output_size = std::fs::metadata(&output).unwrap().len();
+    let mb = |bytes: u64| bytes as f64 / (1 << 20) as f64;
+    println!(
+        "rewrite {:.1} MB -> {:.1} MB in {:.2?} ({:.1} MB/s)",
+        mb(input_size),
+        mb(output_size),
+        elapsed,
+        mb(input_size) / elapsed.as_secs_f64()
+    );
+
+    let _ = std::fs::remove_file(input);
+    let _ = std::fs::remove_file(output);
+}
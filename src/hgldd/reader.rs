@@ -82,8 +82,7 @@ pub fn parse_hgldd_dir(hgldd_dir_path: &Path) -> HglddResult {
     }
 
     if hgldds.is_empty() {
-        Err(HglddReaderError::IoError(std::io::Error::new(
-            std::io::ErrorKind::Other,
+        Err(HglddReaderError::IoError(std::io::Error::other(
             "No HGLDD files found in the directory",
         )))
     } else {
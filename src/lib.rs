@@ -9,3 +9,8 @@ pub mod vcd_rewrite;
 /// and links variables/scopes to their trace paths (i.e. associating generic representation
 /// to a value in a trace file).
 pub mod tyvcd;
+
+/// Typed waveform queries.
+/// This module combines the value changes of a trace file with the TyVcd IR format to
+/// retrieve the typed values of variables over time.
+pub mod waveform;
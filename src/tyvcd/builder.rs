@@ -160,7 +160,7 @@ impl TyVcdBuilder<hgldd::Hgldd> {
         parent_scope: &[String],
     ) -> Scope {
         // Identify among the traces
-        let trace_name = if let Some(hdl_obj_name) = &hgldd_inst.hdl_obj_name {
+        let trace_name = if let Some(_hdl_obj_name) = &hgldd_inst.hdl_obj_name {
             // hdl_obj_name // TODO: this wasn't working, no idea why
             &hgldd_inst.name_id
         } else {
@@ -487,14 +487,16 @@ mod helper {
                 }
                 // This variable contains an operator, this means it contains the "values" of all its child variables (to be added in kind)
                 hgldd::Expression::Operator { opcode, operands } => {
-                    // TODO: check how to use the opcode here to calculate the right value
-                    let mut v = Vec::with_capacity(operands.len());
-                    for o in operands {
-                        if let Some(x) = get_trace_value_from_expression(Some(o)) {
-                            v.push(x);
-                        }
+                    // An operand that cannot be converted would shift the position of the others
+                    let v = operands
+                        .iter()
+                        .map(|o| get_trace_value_from_expression(Some(o)))
+                        .collect::<Option<Vec<_>>>()?;
+                    // A struct links its fields, the other operators compute a value
+                    match opcode {
+                        hgldd::Opcode::Struct => Some(TraceValue::RefTraceValues(v)),
+                        _ => Some(TraceValue::Operation(opcode.clone(), v)),
                     }
-                    Some(TraceValue::RefTraceValues(v))
                 }
             }
         } else {
@@ -539,7 +541,10 @@ mod helper {
                 | hgldd::Expression::BitVector(_)
                 | hgldd::Expression::IntegerNum(_) => std::slice::from_ref(expression),
                 // This variable contains an operator, this means it contains the "values" of all its child variables (to be added in kind)
-                hgldd::Expression::Operator { opcode, operands } => {
+                hgldd::Expression::Operator {
+                    opcode: _,
+                    operands,
+                } => {
                     operands.as_slice() // TODO: check if the opcode is needed here
                 }
             }
@@ -623,7 +628,7 @@ mod test {
         assert!(tyvcd.is_some());
         let tyvcd = tyvcd.unwrap();
         // tyvcd contain the top scope D
-        assert!(tyvcd.scopes.get("D").is_some());
+        assert!(tyvcd.scopes.contains_key("D"));
         // Check the hierarchy
         // D
         // |_ c1: C
@@ -703,7 +708,7 @@ mod test {
         // New hierarchy is:
         // TOP_TB
         // |_ dut: A
-        assert!(tyvcd.scopes.get("TOP_TB").is_some());
+        assert!(tyvcd.scopes.contains_key("TOP_TB"));
         let top_tb = tyvcd.scopes.get("TOP_TB").unwrap().read().unwrap();
         assert_eq!(top_tb.subscopes.len(), 1);
         let dut = top_tb.subscopes.get("dut").unwrap().read().unwrap();
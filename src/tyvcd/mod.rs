@@ -4,3 +4,5 @@ pub mod builder;
 pub mod spec;
 /// Module containing trait definitions to link elements with a trace path.
 pub mod trace_pointer;
+/// Typed values of the variables in the tyvcd IR format and their decoding from raw trace values.
+pub mod value;
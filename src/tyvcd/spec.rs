@@ -1,6 +1,9 @@
 use crate::hgldd::spec::EnumValMap;
 
+use serde::{Deserialize, Serialize};
+
 use super::trace_pointer::{TraceFinder, TraceGetter, TraceValue};
+use super::value::{DecodedValue, EncodeError, SignalAssignment};
 use std::{
     collections::HashMap,
     sync::{Arc, RwLock},
@@ -12,7 +15,7 @@ pub type ScopeId = String;
 pub type Scope = ScopeDef;
 
 /// Represents the TyVcd format.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct TyVcd {
     /// List of top level scopes in the TyVcd format, stored as a hash map of shared references.
     pub scopes: HashMap<ScopeId, Arc<RwLock<ScopeDef>>>,
@@ -81,9 +84,10 @@ impl TraceFinder for TyVcd {
 }
 
 /// Represent a scope (i.e. a module instance) in the TyVcd format.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ScopeDef {
     /// The name of the scope in the trace
+    #[serde(rename = "trace_value")]
     _id_trace_value: TraceValue,
 
     /// The subscopes of this scope
@@ -96,6 +100,7 @@ pub struct ScopeDef {
     /// High level information of the scope
     pub high_level_info: TypeInfo,
     /// The path of this scope
+    #[serde(rename = "scope_path")]
     _scope_path: Vec<String>,
 }
 
@@ -186,10 +191,12 @@ impl TraceGetter for ScopeDef {
 }
 
 /// Represent a variable in the TyVcd format.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Variable {
     /// The value of the variable in the trace.
+    #[serde(rename = "trace_value")]
     _trace_value: TraceValue,
+    #[serde(rename = "is_top")]
     _is_top: bool,
 
     /// The name of the variable.
@@ -198,6 +205,8 @@ pub struct Variable {
     pub high_level_info: TypeInfo,
     /// The kind of the variable.
     pub kind: VariableKind,
+    /// The numeric interpretation of the raw bits of the variable (meaningful for ground variables).
+    pub numeric_kind: NumericKind,
     /// The reference enum type if any.
     pub enum_val_map: Option<Arc<RwLock<EnumValMap>>>,
 }
@@ -207,6 +216,7 @@ impl PartialEq for Variable {
         if self.name != other.name
             || self.high_level_info != other.high_level_info
             || self.kind != other.kind
+            || self.numeric_kind != other.numeric_kind
             || self._trace_value != other._trace_value
             || self._is_top != other._is_top
         {
@@ -232,17 +242,25 @@ impl Variable {
         high_level_info: TypeInfo,
         kind: VariableKind,
     ) -> Self {
+        let numeric_kind = NumericKind::from(&high_level_info);
         Self {
             // _id_trace_name: trace_name,
             _trace_value: trace_value,
             name,
             high_level_info,
             kind,
+            numeric_kind,
             enum_val_map: None,
             _is_top: false,
         }
     }
 
+    /// Override the numeric interpretation inferred from the type information.
+    pub fn with_numeric_kind(mut self, numeric_kind: NumericKind) -> Self {
+        self.numeric_kind = numeric_kind;
+        self
+    }
+
     pub fn with_enum_val_map(mut self, enum_val_map: EnumValMap) -> Self {
         if !enum_val_map.is_empty() {
             self.enum_val_map = Some(Arc::new(RwLock::new(enum_val_map)));
@@ -260,7 +278,7 @@ impl Variable {
     //     self._id_trace_name = trace_name;
     // }
 
-    pub(in crate::tyvcd) fn update_trace_value(&mut self, trace_value: TraceValue) {
+    pub(crate) fn update_trace_value(&mut self, trace_value: TraceValue) {
         self._trace_value = trace_value;
     }
 
@@ -314,6 +332,277 @@ impl Variable {
         ground_variables
     }
 
+    /// Decode the raw value of this variable into a [DecodedValue].
+    ///
+    /// The `raw_val_vcd` is the binary string of the variable as stored in a trace (MSB first).
+    /// For aggregates it is the ***ordered*** concatenation of the values of their fields, where
+    /// the first field occupies the most significant bits. Values shorter than the width of the
+    /// variable are extended as in VCD (with `0`, or `x`/`z` when that is the leading bit).
+    pub fn decode_value(&self, raw_val_vcd: &str) -> DecodedValue {
+        let width = self.kind.find_width() as usize;
+        let raw_val_vcd = super::value::extend_bits(raw_val_vcd, width);
+        self.decode_value_impl(&raw_val_vcd)
+    }
+
+    fn decode_value_impl(&self, raw_val_vcd: &str) -> DecodedValue {
+        // Constants do not depend on the trace
+        if let TraceValue::Constant(c) = &self._trace_value {
+            match c {
+                super::trace_pointer::ConstValue::String(s) => {
+                    return DecodedValue::String(s.clone())
+                }
+                super::trace_pointer::ConstValue::Real(float_value) => {
+                    return DecodedValue::Real(*float_value)
+                }
+                super::trace_pointer::ConstValue::Binary(bv, _)
+                | super::trace_pointer::ConstValue::FourValue(bv, _) => {
+                    if let VariableKind::Ground(width) = self.kind {
+                        let bv = String::from_utf8_lossy(bv);
+                        return self.decode_ground(&super::value::extend_bits(&bv, width as usize));
+                    }
+                }
+            }
+        }
+
+        match &self.kind {
+            VariableKind::Ground(_) => self.decode_ground(raw_val_vcd),
+            VariableKind::Struct { fields } | VariableKind::Vector { fields } => {
+                let mut values = Vec::with_capacity(fields.len());
+                let mut start_idx = 0;
+                for field in fields {
+                    let end_idx =
+                        (start_idx + field.kind.find_width() as usize).min(raw_val_vcd.len());
+                    values.push((
+                        field.name.clone(),
+                        field.decode_value_impl(&raw_val_vcd[start_idx..end_idx]),
+                    ));
+                    start_idx = end_idx;
+                }
+                if let VariableKind::Struct { .. } = self.kind {
+                    DecodedValue::Struct(values)
+                } else {
+                    DecodedValue::Vector(values.into_iter().map(|(_, v)| v).collect())
+                }
+            }
+            VariableKind::External => DecodedValue::Bits(raw_val_vcd.to_string()),
+        }
+    }
+
+    // Decode a ground value: try the enum variants first, then the numeric interpretation.
+    fn decode_ground(&self, raw_val_vcd: &str) -> DecodedValue {
+        let value = self.numeric_kind.decode(raw_val_vcd);
+        if let Some(enum_val_map) = &self.enum_val_map {
+            if let Some(key) = value.as_i64() {
+                if let Some(variant) = enum_val_map.read().unwrap().get(&key) {
+                    return DecodedValue::Enum(variant.clone());
+                }
+            }
+        }
+        value
+    }
+
+    /// Encode a typed value into the raw values of the signals referenced by this variable.
+    ///
+    /// It is the inverse of [Variable::decode_value]: each signal referenced through the
+    /// [TraceValue] of the variable (or of its fields) gets its raw bits. Struct values can be
+    /// partial when the fields are stored in separate signals: only the given fields are assigned.
+    /// Constant fields are not assigned, but the value must match them.
+    pub fn encode_value(&self, value: &DecodedValue) -> Result<Vec<SignalAssignment>, EncodeError> {
+        let mut assignments = Vec::new();
+        self.encode_value_impl(value, &mut assignments)?;
+        Ok(assignments)
+    }
+
+    fn encode_value_impl(
+        &self,
+        value: &DecodedValue,
+        assignments: &mut Vec<SignalAssignment>,
+    ) -> Result<(), EncodeError> {
+        match &self._trace_value {
+            // The whole value is stored in a signal
+            TraceValue::RefTraceName(trace_name) => assignments.push(SignalAssignment {
+                trace_name: trace_name.clone(),
+                bits: self.encode_bits(value)?,
+            }),
+            // Only check the value
+            TraceValue::Constant(_) => {
+                self.encode_bits(value)?;
+            }
+            // The value is computed from other signals
+            TraceValue::Operation(..) => return Err(EncodeError::NotAssignable(self.name.clone())),
+            // The value is stored in the fields
+            TraceValue::RefTraceValues(_) => match (&self.kind, value) {
+                (VariableKind::Struct { fields }, DecodedValue::Struct(values)) => {
+                    for (field_name, field_value) in values {
+                        let field =
+                            fields
+                                .iter()
+                                .find(|f| &f.name == field_name)
+                                .ok_or_else(|| EncodeError::UnknownField {
+                                    variable: self.name.clone(),
+                                    field: field_name.clone(),
+                                })?;
+                        field.encode_value_impl(field_value, assignments)?;
+                    }
+                }
+                (VariableKind::Vector { fields }, DecodedValue::Vector(values)) => {
+                    if fields.len() != values.len() {
+                        return Err(EncodeError::VectorLength {
+                            variable: self.name.clone(),
+                            expected: fields.len(),
+                            found: values.len(),
+                        });
+                    }
+                    for (field, field_value) in fields.iter().zip(values) {
+                        field.encode_value_impl(field_value, assignments)?;
+                    }
+                }
+                (VariableKind::Ground(_) | VariableKind::External, _) => {
+                    return Err(EncodeError::NotAssignable(self.name.clone()))
+                }
+                _ => {
+                    return Err(EncodeError::KindMismatch {
+                        variable: self.name.clone(),
+                        value: value.to_string(),
+                    })
+                }
+            },
+        }
+        Ok(())
+    }
+
+    /// Encode a typed value into the raw bits (MSB first) of this variable.
+    ///
+    /// For aggregates it returns the ***ordered*** concatenation of the fields as expected by
+    /// [Variable::decode_value], so the value must contain all the fields.
+    pub fn encode_bits(&self, value: &DecodedValue) -> Result<String, EncodeError> {
+        let bits = match (&self.kind, value) {
+            (VariableKind::Ground(width), _) => self.encode_ground(value, *width)?,
+            (VariableKind::Struct { fields }, DecodedValue::Struct(values)) => {
+                if let Some((field_name, _)) = values
+                    .iter()
+                    .find(|(name, _)| !fields.iter().any(|f| &f.name == name))
+                {
+                    return Err(EncodeError::UnknownField {
+                        variable: self.name.clone(),
+                        field: field_name.clone(),
+                    });
+                }
+                let mut bits = String::new();
+                for field in fields {
+                    let field_value =
+                        value
+                            .field(&field.name)
+                            .ok_or_else(|| EncodeError::MissingField {
+                                variable: self.name.clone(),
+                                field: field.name.clone(),
+                            })?;
+                    bits.push_str(&field.encode_bits(field_value)?);
+                }
+                bits
+            }
+            (VariableKind::Vector { fields }, DecodedValue::Vector(values)) => {
+                if fields.len() != values.len() {
+                    return Err(EncodeError::VectorLength {
+                        variable: self.name.clone(),
+                        expected: fields.len(),
+                        found: values.len(),
+                    });
+                }
+                let mut bits = String::new();
+                for (field, field_value) in fields.iter().zip(values) {
+                    bits.push_str(&field.encode_bits(field_value)?);
+                }
+                bits
+            }
+            (VariableKind::External, _) => {
+                return Err(EncodeError::NotAssignable(self.name.clone()))
+            }
+            _ => {
+                return Err(EncodeError::KindMismatch {
+                    variable: self.name.clone(),
+                    value: value.to_string(),
+                })
+            }
+        };
+
+        // Constants cannot change
+        if let TraceValue::Constant(c) = &self._trace_value {
+            let is_same = match c {
+                super::trace_pointer::ConstValue::Binary(bv, _)
+                | super::trace_pointer::ConstValue::FourValue(bv, _) => {
+                    let bv = String::from_utf8_lossy(bv);
+                    super::value::extend_bits(&bv, bits.len()) == bits
+                }
+                super::trace_pointer::ConstValue::Real(_)
+                | super::trace_pointer::ConstValue::String(_) => {
+                    self.decode_value_impl(&bits) == *value
+                }
+            };
+            if !is_same {
+                return Err(EncodeError::ConstantMismatch {
+                    variable: self.name.clone(),
+                    value: value.to_string(),
+                });
+            }
+        }
+        Ok(bits)
+    }
+
+    // Encode a ground value: enum variants, raw bits or numbers.
+    fn encode_ground(&self, value: &DecodedValue, width: u128) -> Result<String, EncodeError> {
+        let invalid_value = || EncodeError::InvalidValue {
+            variable: self.name.clone(),
+            value: value.to_string(),
+            width,
+        };
+        match value {
+            DecodedValue::Enum(variant) => {
+                let key = self.enum_val_map.as_ref().and_then(|enum_val_map| {
+                    let enum_val_map = enum_val_map.read().unwrap();
+                    enum_val_map
+                        .iter()
+                        .find_map(|(key, name)| (name == variant).then_some(*key))
+                });
+                let key = key.ok_or_else(|| EncodeError::UnknownEnumVariant {
+                    variable: self.name.clone(),
+                    variant: variant.clone(),
+                })?;
+                // Negative keys are stored in two's complement
+                let numeric_kind = if key < 0 {
+                    NumericKind::Signed
+                } else {
+                    NumericKind::Unsigned
+                };
+                numeric_kind
+                    .encode(&DecodedValue::Signed(key.into()), width as u32)
+                    .ok_or_else(invalid_value)
+            }
+            DecodedValue::Bits(bits) => {
+                if bits.len() as u128 > width || !bits.chars().all(|c| "01xzXZ".contains(c)) {
+                    return Err(invalid_value());
+                }
+                Ok(super::value::extend_bits(
+                    &bits.to_ascii_lowercase(),
+                    width as usize,
+                ))
+            }
+            DecodedValue::Unsigned(_)
+            | DecodedValue::Signed(_)
+            | DecodedValue::FixedPoint(_)
+            | DecodedValue::Real(_) => self
+                .numeric_kind
+                .encode(value, width as u32)
+                .ok_or_else(invalid_value),
+            DecodedValue::String(_) | DecodedValue::Struct(_) | DecodedValue::Vector(_) => {
+                Err(EncodeError::KindMismatch {
+                    variable: self.name.clone(),
+                    value: value.to_string(),
+                })
+            }
+        }
+    }
+
     #[deprecated = "Should be removed. A better version from trace value should be used instead"]
     pub fn create_val_repr(
         &self,
@@ -325,7 +614,9 @@ impl Variable {
         //     return String::from("---");
         // }
         let raw_val_vcd = match &self._trace_value {
-            TraceValue::RefTraceName(_) | TraceValue::RefTraceValues(_) => raw_val_vcd.to_string(),
+            TraceValue::RefTraceName(_)
+            | TraceValue::RefTraceValues(_)
+            | TraceValue::Operation(..) => raw_val_vcd.to_string(),
             TraceValue::Constant(c) => match c {
                 super::trace_pointer::ConstValue::Binary(bv, _width)
                 | super::trace_pointer::ConstValue::FourValue(bv, _width) => {
@@ -340,24 +631,14 @@ impl Variable {
         let raw_val_vcd = raw_val_vcd.as_str();
         let render_result = match &self.kind {
             // If the variable is a ground type: use the raw value directly
-            VariableKind::Ground(width) => {
-                let mut render_result = None;
-
-                if let Some(enum_val_map) = &self.enum_val_map {
-                    let enum_val_map = enum_val_map.read().unwrap();
-                    if let Ok(intval) = i64::from_str_radix(raw_val_vcd, 2) {
-                        let render = enum_val_map.get(&intval);
-                        if let Some(render) = render {
-                            render_result = Some(render.clone());
-                        }
-                    }
-                }
-                if let Some(r) = render_result {
-                    r
-                } else {
-                    render_fn(*width as u64, raw_val_vcd)
-                }
-            }
+            VariableKind::Ground(width) => match self.decode_ground(raw_val_vcd) {
+                // Enum variants and non-unsigned numbers are rendered from their decoded value
+                value @ DecodedValue::Enum(_) => value.to_string(),
+                value @ (DecodedValue::Signed(_)
+                | DecodedValue::FixedPoint(_)
+                | DecodedValue::Real(_)) => value.to_string(),
+                _ => render_fn(*width as u64, raw_val_vcd),
+            },
             // Otherwise, encode the fields recursively {x, {y, z}} or [x, y, z]
             VariableKind::Vector { fields } | VariableKind::Struct { fields } => {
                 // Encode the fields recursively {x, {y, z}} or [x, y, z]
@@ -415,7 +696,7 @@ impl TraceGetter for Variable {
 }
 
 /// Structure to store the type information of a variable.
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct TypeInfo {
     /// The type name of the variable
     pub type_name: String,
@@ -427,10 +708,29 @@ impl TypeInfo {
     pub fn new(type_name: String, params: Vec<ConstructorParams>) -> Self {
         Self { type_name, params }
     }
+
+    /// Return the type name without the binding (i.e. `IO[...]`, `Wire[...]`) and the
+    /// vector dimensions (i.e. `[4]`): `IO[SInt<8>[4]]` becomes `SInt<8>`.
+    pub fn base_type_name(&self) -> &str {
+        let mut name = self.type_name.trim();
+        // Remove the bindings
+        while let (Some(open), true) = (name.find('['), name.ends_with(']')) {
+            let inner = &name[open + 1..name.len() - 1];
+            if inner.chars().all(|c| c.is_ascii_digit()) {
+                break;
+            }
+            name = inner;
+        }
+        // Remove the vector dimensions
+        while let (Some(open), true) = (name.rfind('['), name.ends_with(']')) {
+            name = &name[..open];
+        }
+        name
+    }
 }
 
 /// The constructor parameters in a source language type
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct ConstructorParams {
     /// The name of the parameter
     pub name: String,
@@ -440,8 +740,60 @@ pub struct ConstructorParams {
     pub value: Option<String>,
 }
 
+/// The numeric interpretation of the raw bits of a ground variable.
+///
+/// It is inferred from the source language type name (i.e. `SInt<8>` or `IO[FixedPoint<8><<4>>]`)
+/// and from a `binaryPoint` constructor parameter, if any.
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+pub enum NumericKind {
+    /// An unsigned integer (i.e. `UInt`, `Bool`, `Clock`)
+    #[default]
+    Unsigned,
+    /// A signed integer in two's complement (i.e. `SInt`)
+    Signed,
+    /// A signed fixed-point number in two's complement with `binary_point` fractional bits
+    FixedPoint { binary_point: u32 },
+    /// A double precision floating-point number (IEEE 754)
+    Real,
+}
+
+impl From<&TypeInfo> for NumericKind {
+    fn from(type_info: &TypeInfo) -> Self {
+        // A binary point in the constructor parameters always defines a fixed-point
+        let binary_point = type_info
+            .params
+            .iter()
+            .find(|p| p.name == "binaryPoint")
+            .and_then(|p| p.value.as_ref()?.trim().parse().ok());
+        if let Some(binary_point) = binary_point {
+            return NumericKind::FixedPoint { binary_point };
+        }
+
+        let base_name = type_info.base_type_name();
+        let is_type = |name: &str| {
+            base_name
+                .strip_prefix(name)
+                .is_some_and(|rest| rest.is_empty() || rest.starts_with('<'))
+        };
+        if is_type("SInt") {
+            NumericKind::Signed
+        } else if is_type("FixedPoint") {
+            // Chisel renders a fixed-point as `FixedPoint<width><<binaryPoint>>`
+            let binary_point = base_name
+                .split_once("<<")
+                .and_then(|(_, bp)| bp.strip_suffix(">>")?.parse().ok())
+                .unwrap_or(0);
+            NumericKind::FixedPoint { binary_point }
+        } else if is_type("Real") || is_type("DspReal") {
+            NumericKind::Real
+        } else {
+            NumericKind::Unsigned
+        }
+    }
+}
+
 /// Represents the kind of a variable in the TyVcd format.
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum VariableKind {
     /// A ground type with a defined range of width
     Ground(u128),
@@ -1,5 +1,8 @@
+use serde::{Deserialize, Serialize};
 use std::sync::{Arc, RwLock};
 
+use crate::hgldd::spec::Opcode;
+
 /// Trait with methods to return the name and path of an object in the trace file.
 ///
 /// Any type that implements this trait is able to return a [TraceValue].
@@ -31,7 +34,7 @@ pub trait TraceFinder {
     fn find_trace(&self, path: &[String]) -> Option<Arc<RwLock<dyn TraceGetter>>>;
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum TraceValue {
     /// The trace value has a reference name.
     /// It means that the actual value is stored in a reference indicated by a trace name.
@@ -40,19 +43,23 @@ pub enum TraceValue {
     /// The trace value contains the value itself.
     Constant(ConstValue),
     /// A reference to multiple trace values.
-    /// The actual value is the result of an operation between multiple trace values.
+    /// The actual value is the concatenation of the trace values (i.e. the fields of a struct).
     RefTraceValues(Vec<TraceValue>),
+    /// The value is computed by an operator (i.e. `+` or `?:`) from its operands.
+    Operation(Opcode, Vec<TraceValue>),
 }
 
 impl TraceValue {
     pub fn is_const(&self) -> bool {
         match self {
-            TraceValue::RefTraceName(_) | TraceValue::RefTraceValues(_) => false,
+            TraceValue::RefTraceName(_)
+            | TraceValue::RefTraceValues(_)
+            | TraceValue::Operation(..) => false,
             TraceValue::Constant(_) => true,
         }
     }
 }
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum ConstValue {
     Binary(Vec<u8>, u32),
     FourValue(Vec<u8>, u32),
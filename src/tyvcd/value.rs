@@ -0,0 +1,285 @@
+use serde::ser::{Serialize, SerializeMap, Serializer};
+use std::fmt;
+
+use super::spec::NumericKind;
+
+/// Error raised when a typed value cannot be encoded into the raw values of a variable.
+#[derive(Debug, PartialEq)]
+pub enum EncodeError {
+    /// The value does not match the kind of the variable (i.e. a vector for a struct variable)
+    KindMismatch { variable: String, value: String },
+    /// The value cannot be represented with the width and numeric kind of the variable
+    InvalidValue {
+        variable: String,
+        value: String,
+        width: u128,
+    },
+    /// The enum variant is not defined for the variable
+    UnknownEnumVariant { variable: String, variant: String },
+    /// A field of the struct variable is missing in the value
+    MissingField { variable: String, field: String },
+    /// The value contains a field that does not exist in the struct variable
+    UnknownField { variable: String, field: String },
+    /// The number of elements in the value differs from the size of the vector variable
+    VectorLength {
+        variable: String,
+        expected: usize,
+        found: usize,
+    },
+    /// The variable is a constant and the value differs from it
+    ConstantMismatch { variable: String, value: String },
+    /// The variable is not linked to any signal that can be assigned (i.e. it is computed)
+    NotAssignable(String),
+}
+
+/// The raw value to assign to a signal in a trace.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SignalAssignment {
+    /// The name of the signal in the trace
+    pub trace_name: String,
+    /// The binary value of the signal (MSB first)
+    pub bits: String,
+}
+
+/// The value of a [super::spec::Variable] decoded from its raw bits in a trace.
+#[derive(Debug, Clone, PartialEq)]
+pub enum DecodedValue {
+    /// An unsigned integer
+    Unsigned(u128),
+    /// A signed integer (two's complement)
+    Signed(i128),
+    /// A fixed-point number converted to a floating-point one
+    FixedPoint(f64),
+    /// A floating-point number
+    Real(f64),
+    /// The name of an enum variant
+    Enum(String),
+    /// A string value
+    String(String),
+    /// Raw bits that cannot be interpreted as a number.
+    /// For example, when they contain `x` or `z` or when they are wider than 128 bits.
+    Bits(String),
+    /// A struct-like value: the ordered list of its fields with their names
+    Struct(Vec<(String, DecodedValue)>),
+    /// A vector-like value: the ordered list of its elements
+    Vector(Vec<DecodedValue>),
+}
+
+impl DecodedValue {
+    /// Return the integer value of a decoded number, if it can be represented as [i64].
+    pub fn as_i64(&self) -> Option<i64> {
+        match self {
+            DecodedValue::Unsigned(v) => i64::try_from(*v).ok(),
+            DecodedValue::Signed(v) => i64::try_from(*v).ok(),
+            _ => None,
+        }
+    }
+
+    /// Return the decoded value of a field in a struct.
+    pub fn field(&self, name: &str) -> Option<&DecodedValue> {
+        match self {
+            DecodedValue::Struct(fields) => fields.iter().find(|(n, _)| n == name).map(|(_, v)| v),
+            _ => None,
+        }
+    }
+
+    /// Return the decoded value of an element in a vector.
+    pub fn index(&self, idx: usize) -> Option<&DecodedValue> {
+        match self {
+            DecodedValue::Vector(elements) => elements.get(idx),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for DecodedValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DecodedValue::Unsigned(v) => write!(f, "{}", v),
+            DecodedValue::Signed(v) => write!(f, "{}", v),
+            DecodedValue::FixedPoint(v) | DecodedValue::Real(v) => write!(f, "{}", v),
+            DecodedValue::Enum(s) | DecodedValue::String(s) | DecodedValue::Bits(s) => {
+                write!(f, "{}", s)
+            }
+            // Encode the fields recursively {x: .., y: {z: ..}}
+            DecodedValue::Struct(fields) => {
+                write!(f, "{{")?;
+                for (i, (name, value)) in fields.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}: {}", name, value)?;
+                }
+                write!(f, "}}")
+            }
+            // Encode the elements recursively [x, [y, z]]
+            DecodedValue::Vector(elements) => {
+                write!(f, "[")?;
+                for (i, value) in elements.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", value)?;
+                }
+                write!(f, "]")
+            }
+        }
+    }
+}
+
+// Serialize the values as plain JSON-like data: numbers, strings, objects (structs)
+// and arrays (vectors). Enums are serialized with the name of their variant.
+impl Serialize for DecodedValue {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            DecodedValue::Unsigned(v) => serializer.serialize_u128(*v),
+            DecodedValue::Signed(v) => serializer.serialize_i128(*v),
+            DecodedValue::FixedPoint(v) | DecodedValue::Real(v) => serializer.serialize_f64(*v),
+            DecodedValue::Enum(s) | DecodedValue::String(s) | DecodedValue::Bits(s) => {
+                serializer.serialize_str(s)
+            }
+            DecodedValue::Struct(fields) => {
+                let mut map = serializer.serialize_map(Some(fields.len()))?;
+                for (name, value) in fields {
+                    map.serialize_entry(name, value)?;
+                }
+                map.end()
+            }
+            DecodedValue::Vector(elements) => serializer.collect_seq(elements),
+        }
+    }
+}
+
+impl NumericKind {
+    /// Decode the raw bits (MSB first) of a ground value according to this interpretation.
+    pub fn decode(&self, raw_bits: &str) -> DecodedValue {
+        let raw_bits = raw_bits.to_ascii_lowercase();
+        // Not a number: x, z or wider than the supported integers
+        let Some(uint) = parse_bits(&raw_bits) else {
+            return DecodedValue::Bits(raw_bits);
+        };
+        let width = raw_bits.len() as u32;
+
+        match self {
+            NumericKind::Unsigned => DecodedValue::Unsigned(uint),
+            NumericKind::Signed => DecodedValue::Signed(sign_extend(uint, width)),
+            NumericKind::FixedPoint { binary_point } => DecodedValue::FixedPoint(
+                sign_extend(uint, width) as f64 / 2f64.powi(*binary_point as i32),
+            ),
+            NumericKind::Real => {
+                if width == 64 {
+                    DecodedValue::Real(f64::from_bits(uint as u64))
+                } else {
+                    DecodedValue::Bits(raw_bits)
+                }
+            }
+        }
+    }
+}
+
+impl NumericKind {
+    /// Encode a decoded number into `width` raw bits (MSB first) according to this interpretation.
+    ///
+    /// Return `None` if the value is not a number or it cannot be represented exactly in `width`
+    /// bits (i.e. a fixed-point value that is not a multiple of its resolution).
+    pub fn encode(&self, value: &DecodedValue, width: u32) -> Option<String> {
+        let int_value: Option<i128> = match value {
+            DecodedValue::Unsigned(v) => i128::try_from(*v).ok(),
+            DecodedValue::Signed(v) => Some(*v),
+            DecodedValue::FixedPoint(v) | DecodedValue::Real(v) => {
+                (v.fract() == 0.0).then_some(*v as i128)
+            }
+            _ => None,
+        };
+
+        let uint = match self {
+            NumericKind::Unsigned => {
+                let v = match value {
+                    // Keep the full range of the unsigned numbers
+                    DecodedValue::Unsigned(v) => *v,
+                    _ => u128::try_from(int_value?).ok()?,
+                };
+                (width >= u128::BITS || v >> width == 0).then_some(v)?
+            }
+            NumericKind::Signed => to_twos_complement(int_value?, width)?,
+            NumericKind::FixedPoint { binary_point } => {
+                let scale = 2f64.powi(*binary_point as i32);
+                let scaled = match value {
+                    DecodedValue::FixedPoint(v) | DecodedValue::Real(v) => {
+                        // Do not quantize: the value must be a multiple of the resolution
+                        let scaled = v * scale;
+                        let rounded = scaled.round();
+                        ((scaled - rounded).abs() <= FIXED_POINT_EPSILON).then_some(rounded)?
+                    }
+                    _ => int_value? as f64 * scale,
+                };
+                to_twos_complement(scaled as i128, width)?
+            }
+            NumericKind::Real => {
+                let v = match value {
+                    DecodedValue::FixedPoint(v) | DecodedValue::Real(v) => *v,
+                    _ => int_value? as f64,
+                };
+                if width != 64 {
+                    return None;
+                }
+                v.to_bits() as u128
+            }
+        };
+        Some(format!("{:0>width$b}", uint, width = width as usize))
+    }
+}
+
+// The tolerance on the scaled fixed-point values, to accept the floating-point rounding errors
+const FIXED_POINT_EPSILON: f64 = 1e-9;
+
+// Represent a signed integer in `width` bits (two's complement), if it fits.
+fn to_twos_complement(v: i128, width: u32) -> Option<u128> {
+    if width == 0 {
+        return None;
+    }
+    if width < u128::BITS {
+        let (min, max) = (-(1i128 << (width - 1)), (1i128 << (width - 1)) - 1);
+        if v < min || v > max {
+            return None;
+        }
+        Some((v as u128) & ((1u128 << width) - 1))
+    } else {
+        Some(v as u128)
+    }
+}
+
+/// Parse a binary string as an unsigned integer.
+/// Return `None` if it contains non-binary values (`x`, `z`, ...) or it is wider than 128 bits.
+pub(crate) fn parse_bits(raw_bits: &str) -> Option<u128> {
+    if raw_bits.is_empty() || raw_bits.len() > u128::BITS as usize {
+        return None;
+    }
+    u128::from_str_radix(raw_bits, 2).ok()
+}
+
+/// Interpret the lowest `width` bits of `uint` as a two's complement number.
+pub(crate) fn sign_extend(uint: u128, width: u32) -> i128 {
+    if width == 0 || width >= u128::BITS {
+        return uint as i128;
+    }
+    let shift = u128::BITS - width;
+    ((uint << shift) as i128) >> shift
+}
+
+/// Extend a binary string to `width` bits as in VCD: the value is left-extended with `0`,
+/// unless the leading bit is `x` or `z`, in which case that value is used.
+pub(crate) fn extend_bits(raw_bits: &str, width: usize) -> String {
+    if raw_bits.len() >= width {
+        return raw_bits.to_string();
+    }
+    let fill = raw_bits
+        .chars()
+        .next()
+        .filter(|c| matches!(c, 'x' | 'X' | 'z' | 'Z'))
+        .unwrap_or('0');
+    let mut extended = String::with_capacity(width);
+    extended.extend(std::iter::repeat_n(fill, width - raw_bits.len()));
+    extended.push_str(raw_bits);
+    extended
+}
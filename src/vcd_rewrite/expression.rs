@@ -0,0 +1,394 @@
+use vcd::{Header, IdCode, Value};
+
+use crate::hgldd::spec::Opcode;
+use crate::tyvcd::trace_pointer::{ConstValue, TraceValue};
+
+// The bits of a value: the first one is the least significant bit
+type Bits = Vec<Value>;
+
+// The widest replication or extraction: the wider ones (i.e. from a malformed HGLDD) are unknown
+const MAX_WIDTH: usize = 1 << 16;
+
+/// An HGLDD operator expression computed from the values of the original variables.
+///
+/// The expression follows the Verilog rules for the widths: the operands of the arithmetic and
+/// bitwise operators are extended to the width of the result, the other operands keep their own
+/// width. An unknown (`x` or `z`) operand makes the arithmetic and comparison results unknown.
+/// Arithmetic is supported up to 128 bits and all the values are unsigned, except for `>>>`.
+/// Replications and extractions wider than 65536 bits are unknown.
+#[derive(Debug, Clone)]
+pub enum Expression {
+    /// An original variable and its last value
+    Signal { id_code: IdCode, value: vcd::Vector },
+    /// A constant value
+    Constant(vcd::Vector),
+    /// An operator and its operands
+    Operation {
+        opcode: Opcode,
+        operands: Vec<Expression>,
+    },
+}
+
+impl Expression {
+    /// Create the expression of a trace value. The signals are the variables of the original VCD
+    /// file in `scope_path`.
+    ///
+    /// Return `None` if a signal is not in the original VCD file.
+    pub fn create(
+        trace_value: &TraceValue,
+        scope_path: &[String],
+        header: &Header,
+    ) -> Option<Self> {
+        match trace_value {
+            TraceValue::RefTraceName(name) => {
+                let path = [scope_path, std::slice::from_ref(name)].concat();
+                let var = header.find_var(&path)?;
+                Some(Expression::Signal {
+                    id_code: var.code,
+                    value: vcd::Vector::filled(Value::X, var.size as usize),
+                })
+            }
+            TraceValue::Constant(ConstValue::Binary(bits, _) | ConstValue::FourValue(bits, _)) => {
+                let value: Vec<Value> = bits
+                    .iter()
+                    .map(|bit| match bit {
+                        b'0' => Value::V0,
+                        b'1' => Value::V1,
+                        b'z' | b'Z' => Value::Z,
+                        _ => Value::X,
+                    })
+                    .collect();
+                Some(Expression::Constant(value.into()))
+            }
+            TraceValue::Constant(ConstValue::Real(_) | ConstValue::String(_)) => None,
+            // A struct literal is the concatenation of its fields
+            TraceValue::RefTraceValues(operands) => Some(Expression::Operation {
+                opcode: Opcode::Struct,
+                operands: Self::create_all(operands, scope_path, header)?,
+            }),
+            TraceValue::Operation(opcode, operands) => Some(Expression::Operation {
+                opcode: opcode.clone(),
+                operands: Self::create_all(operands, scope_path, header)?,
+            }),
+        }
+    }
+
+    fn create_all(
+        trace_values: &[TraceValue],
+        scope_path: &[String],
+        header: &Header,
+    ) -> Option<Vec<Self>> {
+        trace_values
+            .iter()
+            .map(|trace_value| Self::create(trace_value, scope_path, header))
+            .collect()
+    }
+
+    /// Return the id codes of the original variables used by the expression.
+    pub fn get_id_codes(&self) -> Vec<IdCode> {
+        match self {
+            Expression::Signal { id_code, .. } => vec![*id_code],
+            Expression::Constant(_) => Vec::new(),
+            Expression::Operation { operands, .. } => {
+                operands.iter().flat_map(Self::get_id_codes).collect()
+            }
+        }
+    }
+
+    /// Update the value of the signals with `id_code`. Return true if the expression uses it.
+    pub fn update_value(&mut self, id_code: &IdCode, value: &vcd::Vector) -> bool {
+        match self {
+            Expression::Signal {
+                id_code: signal_id_code,
+                value: signal_value,
+            } if signal_id_code == id_code => {
+                signal_value.clone_from(value);
+                true
+            }
+            Expression::Signal { .. } | Expression::Constant(_) => false,
+            Expression::Operation { operands, .. } => {
+                operands.iter_mut().fold(false, |used, operand| {
+                    operand.update_value(id_code, value) | used
+                })
+            }
+        }
+    }
+
+    /// Compute the value of the expression with `width` bits.
+    pub fn evaluate(&self, width: usize) -> vcd::Vector {
+        let mut bits = self.eval(width);
+        bits.reverse();
+        bits.into()
+    }
+
+    // The width of the expression when it is not extended by its context.
+    fn self_width(&self) -> usize {
+        match self {
+            Expression::Signal { value, .. } | Expression::Constant(value) => value.len(),
+            Expression::Operation { opcode, operands } => {
+                let widths = || operands.iter().map(Self::self_width);
+                match opcode {
+                    Opcode::Struct | Opcode::Concat => widths().sum(),
+                    Opcode::Replicate => match operands.as_slice() {
+                        [count, value] => {
+                            Self::replicate_count(count, value).unwrap_or(1) * value.self_width()
+                        }
+                        _ => 0,
+                    },
+                    Opcode::Extract => match Self::extract_range(operands) {
+                        Some((high, low)) => high - low + 1,
+                        None => 1,
+                    },
+                    Opcode::Mux => widths().skip(1).max().unwrap_or(0),
+                    Opcode::And | Opcode::Or | Opcode::UnaryOrXor if operands.len() == 1 => 1,
+                    Opcode::ShiftLeft | Opcode::ShiftRight | Opcode::ShiftRightSigned => {
+                        widths().next().unwrap_or(0)
+                    }
+                    Opcode::Eq
+                    | Opcode::NotEq
+                    | Opcode::CEq
+                    | Opcode::CNotEq
+                    | Opcode::WEq
+                    | Opcode::WNotEq
+                    | Opcode::LessThan
+                    | Opcode::GreaterThan
+                    | Opcode::LessEq
+                    | Opcode::GreaterEq => 1,
+                    _ => widths().max().unwrap_or(0),
+                }
+            }
+        }
+    }
+
+    // Compute the bits of the expression extended (or truncated) to `width`.
+    fn eval(&self, width: usize) -> Bits {
+        let (opcode, operands) = match self {
+            Expression::Signal { value, .. } | Expression::Constant(value) => {
+                let mut bits: Bits = value.iter().collect();
+                bits.reverse();
+                return resize(bits, width);
+            }
+            Expression::Operation { opcode, operands } => (opcode, operands.as_slice()),
+        };
+        let self_eval = |operand: &Expression| operand.eval(operand.self_width());
+
+        let bits = match opcode {
+            // The first operand is the most significant
+            Opcode::Struct | Opcode::Concat => operands.iter().rev().flat_map(self_eval).collect(),
+            Opcode::Replicate => match operands {
+                [count, value] => match Self::replicate_count(count, value) {
+                    Some(count) => std::iter::repeat_n(self_eval(value), count)
+                        .flatten()
+                        .collect(),
+                    None => unknown(width),
+                },
+                _ => unknown(width),
+            },
+            Opcode::Extract => match (operands.first(), Self::extract_range(operands)) {
+                (Some(value), Some((high, low))) => {
+                    let value = self_eval(value);
+                    (low..=high)
+                        .map(|idx| value.get(idx).copied().unwrap_or(Value::X))
+                        .collect()
+                }
+                _ => unknown(width),
+            },
+            Opcode::Mux => match operands {
+                [condition, if_true, if_false] => match to_u128(&self_eval(condition)) {
+                    Some(0) => if_false.eval(width),
+                    Some(_) => if_true.eval(width),
+                    None => unknown(width),
+                },
+                _ => unknown(width),
+            },
+            // Reductions
+            Opcode::And | Opcode::Or | Opcode::UnaryOrXor if operands.len() == 1 => {
+                let value = self_eval(&operands[0]);
+                let bit = match value.split_first() {
+                    Some((first, rest)) => rest
+                        .iter()
+                        .fold(*first, |acc, bit| bitwise(opcode, acc, *bit)),
+                    None => Value::X,
+                };
+                vec![bit]
+            }
+            Opcode::And | Opcode::Or | Opcode::UnaryOrXor => {
+                let mut values = operands.iter().map(|operand| operand.eval(width));
+                let first = values.next().unwrap_or_else(|| unknown(width));
+                values.fold(first, |acc, value| {
+                    acc.iter()
+                        .zip(value)
+                        .map(|(a, b)| bitwise(opcode, *a, b))
+                        .collect()
+                })
+            }
+            Opcode::Add | Opcode::Sub | Opcode::Mul | Opcode::Div | Opcode::Mod => {
+                let values: Option<Vec<u128>> = operands
+                    .iter()
+                    .map(|operand| to_u128(&operand.eval(width)))
+                    .collect();
+                let result = values.and_then(|values| {
+                    let (first, rest) = values.split_first()?;
+                    rest.iter().try_fold(*first, |acc, value| match opcode {
+                        Opcode::Add => Some(acc.wrapping_add(*value)),
+                        Opcode::Sub => Some(acc.wrapping_sub(*value)),
+                        Opcode::Mul => Some(acc.wrapping_mul(*value)),
+                        Opcode::Div => acc.checked_div(*value),
+                        _ => acc.checked_rem(*value),
+                    })
+                });
+                match result {
+                    Some(result) if width <= 128 => from_u128(result, width),
+                    _ => unknown(width),
+                }
+            }
+            Opcode::ShiftLeft | Opcode::ShiftRight | Opcode::ShiftRightSigned => match operands {
+                [value, amount] => {
+                    let value = match opcode {
+                        // Extend the sign of the value
+                        Opcode::ShiftRightSigned => {
+                            let value = self_eval(value);
+                            let sign = value.last().copied().unwrap_or(Value::V0);
+                            let extension = width.saturating_sub(value.len());
+                            let mut value = resize(value, width);
+                            value[width - extension..].fill(sign);
+                            value
+                        }
+                        _ => value.eval(width),
+                    };
+                    match to_u128(&self_eval(amount)) {
+                        Some(amount) => shift(opcode, value, amount.min(width as u128) as usize),
+                        None => unknown(width),
+                    }
+                }
+                _ => unknown(width),
+            },
+            // Comparisons
+            _ => match operands {
+                [a, b] => {
+                    let operand_width = a.self_width().max(b.self_width());
+                    vec![compare(
+                        opcode,
+                        &a.eval(operand_width),
+                        &b.eval(operand_width),
+                    )]
+                }
+                _ => unknown(1),
+            },
+        };
+        resize(bits, width)
+    }
+
+    // The number of copies of a replicate operation, if it is known and not too wide.
+    fn replicate_count(count: &Expression, value: &Expression) -> Option<usize> {
+        let count = usize::try_from(to_u128(&count.eval(count.self_width()))?).ok()?;
+        let width = count.checked_mul(value.self_width())?;
+        (width <= MAX_WIDTH).then_some(count)
+    }
+
+    // The range `[high:low]` of an extract operation, if it is valid and not too wide.
+    fn extract_range(operands: &[Expression]) -> Option<(usize, usize)> {
+        let index = |operand: &Expression| {
+            usize::try_from(to_u128(&operand.eval(operand.self_width()))?).ok()
+        };
+        let high = index(operands.get(1)?)?;
+        let low = match operands.get(2) {
+            Some(low) => index(low)?,
+            None => high,
+        };
+        (high >= low && high - low < MAX_WIDTH).then_some((high, low))
+    }
+}
+
+fn unknown(width: usize) -> Bits {
+    vec![Value::X; width]
+}
+
+// Zero-extend or truncate the bits.
+fn resize(mut bits: Bits, width: usize) -> Bits {
+    bits.resize(width, Value::V0);
+    bits
+}
+
+// Return the unsigned value of the bits, if they are known and fit.
+fn to_u128(bits: &[Value]) -> Option<u128> {
+    bits.iter()
+        .enumerate()
+        .try_fold(0u128, |acc, (idx, bit)| match bit {
+            Value::V0 => Some(acc),
+            Value::V1 if idx < 128 => Some(acc | 1 << idx),
+            _ => None,
+        })
+}
+
+fn from_u128(value: u128, width: usize) -> Bits {
+    (0..width)
+        .map(|idx| match idx < 128 && value >> idx & 1 == 1 {
+            true => Value::V1,
+            false => Value::V0,
+        })
+        .collect()
+}
+
+fn bitwise(opcode: &Opcode, a: Value, b: Value) -> Value {
+    let is_known = |bit| matches!(bit, Value::V0 | Value::V1);
+    match opcode {
+        Opcode::And if a == Value::V0 || b == Value::V0 => Value::V0,
+        Opcode::Or if a == Value::V1 || b == Value::V1 => Value::V1,
+        _ if !is_known(a) || !is_known(b) => Value::X,
+        Opcode::UnaryOrXor => (a != b).into(),
+        Opcode::And => Value::V1,
+        _ => Value::V0,
+    }
+}
+
+fn shift(opcode: &Opcode, value: Bits, amount: usize) -> Bits {
+    let width = value.len();
+    match opcode {
+        Opcode::ShiftLeft => std::iter::repeat_n(Value::V0, amount)
+            .chain(value)
+            .take(width)
+            .collect(),
+        _ => {
+            let fill = match opcode {
+                Opcode::ShiftRightSigned => value.last().copied().unwrap_or(Value::V0),
+                _ => Value::V0,
+            };
+            value[amount..]
+                .iter()
+                .copied()
+                .chain(std::iter::repeat_n(fill, amount))
+                .collect()
+        }
+    }
+}
+
+fn compare(opcode: &Opcode, a: &[Value], b: &[Value]) -> Value {
+    match opcode {
+        // The `x` and `z` are compared as values
+        Opcode::CEq => (a == b).into(),
+        Opcode::CNotEq => (a != b).into(),
+        // The `x` and `z` of the second operand match any bit
+        Opcode::WEq | Opcode::WNotEq => {
+            let is_wildcard = |bit: &Value| matches!(bit, Value::X | Value::Z);
+            let pairs = || a.iter().zip(b).filter(|(_, b)| !is_wildcard(b));
+            if pairs().any(|(a, _)| is_wildcard(a)) {
+                return Value::X;
+            }
+            let equal = pairs().all(|(a, b)| a == b);
+            (equal == (*opcode == Opcode::WEq)).into()
+        }
+        _ => match (to_u128(a), to_u128(b)) {
+            (Some(a), Some(b)) => match opcode {
+                Opcode::Eq => a == b,
+                Opcode::NotEq => a != b,
+                Opcode::LessThan => a < b,
+                Opcode::GreaterThan => a > b,
+                Opcode::LessEq => a <= b,
+                _ => a >= b,
+            }
+            .into(),
+            _ => Value::X,
+        },
+    }
+}
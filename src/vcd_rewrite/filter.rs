@@ -0,0 +1,57 @@
+/// Select the scopes and variables of a trace by their path.
+///
+/// A path is the list of the trace names from the top scope to a scope or a variable, joined by
+/// `.` (i.e. `TOP.Foo.io_a`). A pattern selects the paths that it matches and everything below
+/// them. Patterns can contain the wildcards `*` (any sequence of characters, `.` included) and `?`
+/// (any single character).
+#[derive(Debug, Clone, Default)]
+pub struct PathFilter {
+    patterns: Vec<String>,
+}
+
+impl PathFilter {
+    pub fn new<S: Into<String>>(patterns: impl IntoIterator<Item = S>) -> Self {
+        Self {
+            patterns: patterns.into_iter().map(Into::into).collect(),
+        }
+    }
+
+    /// Return true if the path or one of its parents matches a pattern.
+    pub fn is_selected(&self, path: &[String]) -> bool {
+        (1..=path.len()).any(|len| {
+            let path = path[..len].join(".");
+            self.patterns
+                .iter()
+                .any(|pattern| glob_match(pattern.as_bytes(), path.as_bytes()))
+        })
+    }
+}
+
+// Match a text against a pattern with `*` and `?` wildcards.
+fn glob_match(pattern: &[u8], text: &[u8]) -> bool {
+    let (mut p, mut t) = (0, 0);
+    // The position of the last `*` and of the text when it was found
+    let mut backtrack = None;
+    while t < text.len() {
+        match pattern.get(p) {
+            Some(b'*') => {
+                backtrack = Some((p, t));
+                p += 1;
+            }
+            Some(&c) if c == b'?' || c == text[t] => {
+                p += 1;
+                t += 1;
+            }
+            _ => match backtrack {
+                // Let the last `*` match one more character
+                Some((star_p, star_t)) => {
+                    backtrack = Some((star_p, star_t + 1));
+                    p = star_p + 1;
+                    t = star_t + 1;
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|&c| c == b'*')
+}
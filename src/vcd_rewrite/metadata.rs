@@ -0,0 +1,59 @@
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::{Arc, RwLock};
+
+use vcd::{Header, ScopeItem};
+
+use super::{Result, TyScope, VcdRewriteError};
+use crate::tyvcd::spec::TyVcd;
+use crate::waveform::source;
+
+/// The prefix of the `$comment` sections containing the type information of a top scope.
+pub const TYPE_METADATA_PREFIX: &str = "tywaves-scope";
+
+/// Encode a top scope of a [TyVcd] (type names, params, enum maps and layout of the fields) in the
+/// text of a `$comment` section.
+pub fn encode_scope(scope: &TyScope) -> Result<String> {
+    let json = serde_json::to_string(scope)?;
+    // `$` cannot appear outside of the JSON strings: escape it so that `$end` cannot close the
+    // comment
+    Ok(format!(
+        "{} {}",
+        TYPE_METADATA_PREFIX,
+        json.replace('$', "\\u0024")
+    ))
+}
+
+/// Decode the top scope encoded by [encode_scope] in a comment.
+/// Return `None` if the comment does not contain type information.
+pub fn decode_scope(comment: &str) -> Option<Result<TyScope>> {
+    let json = comment.strip_prefix(TYPE_METADATA_PREFIX)?;
+    Some(serde_json::from_str(json.trim()).map_err(VcdRewriteError::from))
+}
+
+/// Reconstruct the [TyVcd] from the `$comment` sections of the header of a rewritten trace.
+/// Its trace values refer to the variables of the rewritten trace.
+pub fn tyvcd_from_header(header: &Header) -> Result<TyVcd> {
+    let mut scopes = HashMap::new();
+    for item in &header.items {
+        if let ScopeItem::Comment(comment) = item {
+            if let Some(scope) = decode_scope(comment) {
+                let scope = scope?;
+                scopes.insert(scope.name.clone(), Arc::new(RwLock::new(scope)));
+            }
+        }
+    }
+    if scopes.is_empty() {
+        return Err(VcdRewriteError::Other(
+            "The trace does not contain type information".to_string(),
+        ));
+    }
+    Ok(TyVcd { scopes })
+}
+
+/// Reconstruct the [TyVcd] of a self-describing VCD file, rewritten with
+/// [super::VcdRewriter::with_type_metadata].
+pub fn read_tyvcd(vcd_path: &Path) -> Result<TyVcd> {
+    let header = source::open(vcd_path)?.parse_header()?;
+    tyvcd_from_header(&header)
+}
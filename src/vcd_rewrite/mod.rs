@@ -0,0 +1,1712 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::io::{BufRead, Write};
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::{Arc, RwLock};
+use vcd::{Command, Header, IdCode, ReferenceIndex, TimescaleUnit, Value, VarType};
+
+use crate::tyvcd::spec::{self as tyvcd};
+use crate::tyvcd::trace_pointer::{ConstValue, TraceGetter, TraceValue};
+use crate::tyvcd::value::DecodedValue;
+use crate::waveform::source::{self, WaveformSource};
+
+/// The operator expressions of the HGLDD computed from the original variables.
+pub mod expression;
+/// Select the parts of the hierarchy to rewrite.
+pub mod filter;
+/// The type information embedded in the rewritten trace.
+pub mod metadata;
+/// The writers of the rewritten trace: VCD and FST.
+pub mod output;
+/// The verification of a rewritten trace against its original trace.
+pub mod verify;
+use expression::Expression;
+use filter::PathFilter;
+use output::{FstTraceWriter, OutputFormat, TraceWriter, VcdTraceWriter};
+
+type TyScope = tyvcd::Scope;
+type TyVariable = tyvcd::Variable;
+type TyVarKind = tyvcd::VariableKind;
+
+type Result<T> = std::result::Result<T, VcdRewriteError>;
+
+#[derive(Debug)]
+pub enum VcdRewriteError {
+    /// An IO error occurred
+    IoError(std::io::Error),
+    /// A VCD parsing error occurred
+    VcdError(vcd::ParseError),
+    /// Failed to update the value of a variable
+    UpdateValueError {
+        id_code: IdCode,
+        len_diff: (usize, usize),
+        values_diff: (String, String),
+    },
+    /// Another kind of error occurred. For example, while writing
+    Other(String),
+    /// The specified file name is not a valid vcd or fst
+    InvalidFileExtension(String),
+    /// An error occurred while writing an FST file
+    FstError(fst_writer::FstWriteError),
+    /// The type information embedded in a trace cannot be encoded or decoded
+    MetadataError(serde_json::Error),
+    /// An error occurred while reading a trace to verify it
+    WaveformError(crate::waveform::db::WaveformError),
+}
+
+impl From<std::io::Error> for VcdRewriteError {
+    fn from(err: std::io::Error) -> Self {
+        VcdRewriteError::IoError(err)
+    }
+}
+
+impl From<fst_writer::FstWriteError> for VcdRewriteError {
+    fn from(err: fst_writer::FstWriteError) -> Self {
+        VcdRewriteError::FstError(err)
+    }
+}
+
+impl From<serde_json::Error> for VcdRewriteError {
+    fn from(err: serde_json::Error) -> Self {
+        VcdRewriteError::MetadataError(err)
+    }
+}
+
+impl From<crate::waveform::db::WaveformError> for VcdRewriteError {
+    fn from(err: crate::waveform::db::WaveformError) -> Self {
+        VcdRewriteError::WaveformError(err)
+    }
+}
+
+/// How the struct and vector variables are written in the rewritten trace.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum AggregateMode {
+    /// Each variable is a single `$var` with the concatenation of its fields
+    #[default]
+    Flatten,
+    /// Each struct and vector is a `$scope struct` with a member for each field (named after the
+    /// Chisel field) or element (named `[i]`)
+    Hierarchy,
+}
+
+/// How the ground variables with an `enum_val_map` are written in the rewritten trace.
+///
+/// FST traces have no string variables: the enums are always written as bits.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum EnumMode {
+    /// Only the bits of the enum
+    #[default]
+    Bits,
+    /// The bits and a companion `$var string` named `<name>_variant` with the variant names
+    Companion,
+    /// A `$var string` with the variant names instead of the bits
+    Replace,
+}
+
+/// What the rewriter does when the width of a value in the original trace differs from the width
+/// of the typed variable it belongs to.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum WidthMismatchPolicy {
+    /// Stop the rewrite with [VcdRewriteError::UpdateValueError]
+    #[default]
+    Abort,
+    /// Extend or truncate (keeping the least significant bits) the value, and report it.
+    /// As in VCD, a value is extended with `x` or `z` if its most significant bit is `x` or `z`,
+    /// with `0` otherwise.
+    Resize,
+    /// Replace the value with `x`, and report it
+    MarkX,
+}
+
+/// A width disagreement between a typed variable and a variable of the original trace.
+#[derive(Debug, Clone, PartialEq)]
+pub struct WidthMismatch {
+    /// The path of the typed variable in the rewritten trace (i.e. `Top.io.a`)
+    pub variable: String,
+    /// The id code of the variable in the original trace
+    pub id_code: IdCode,
+    /// The width of the field of the typed variable
+    pub expected_width: usize,
+    /// The width of the value in the original trace
+    pub actual_width: usize,
+    /// The first timestamp with this mismatch
+    pub first_time: u64,
+    /// The number of values with this mismatch
+    pub count: usize,
+}
+
+// The clock whose cycles number the timestamps of the rewritten trace
+struct ClockCycles {
+    /// The path of the typed clock variable
+    path: String,
+    /// The index of the clock in the rewrite variables
+    idx: usize,
+    /// The number of rising edges so far
+    cycles: u64,
+    /// The last value of the clock
+    level: Option<Value>,
+    /// The changes of the current cycle, not written yet
+    changes: Option<CycleChanges>,
+}
+
+// The last values of the variables changed in a clock cycle
+struct CycleChanges {
+    /// The timestamp of the cycle in the rewritten trace
+    time: u64,
+    /// The last value of each rewrite variable, in order of the header
+    values: BTreeMap<usize, vcd::Vector>,
+    /// The last real and string change of each original signal
+    commands: Vec<Command>,
+}
+
+impl ClockCycles {
+    // Count a rising edge when the clock changes from 0 to 1
+    fn update(&mut self, level: Option<Value>) {
+        if self.level == Some(Value::V0) && level == Some(Value::V1) {
+            self.cycles += 1;
+        }
+        self.level = level;
+    }
+}
+
+// A string variable with the variant names of an enum
+struct EnumName {
+    id_code: IdCode,
+    /// The bits of the enum are written as well
+    write_bits: bool,
+    /// The typed variable used to decode the variant
+    variable: TyVariable,
+}
+
+pub struct VcdRewriter<'a> {
+    /// The reader of the original trace (VCD or FST)
+    reader: Box<dyn WaveformSource + 'a>,
+    /// The writer of the rewritten trace (VCD or FST)
+    writer: Box<dyn TraceWriter + 'a>,
+    /// The name of the rewritten VCD file
+    output_vcd_name: String,
+    /// The header of the original VCD file
+    vcd_header: Header,
+    /// The header of the original trace has already been read
+    is_header_parsed: bool,
+
+    /// The scopes of the tywaves state
+    tywaves_scopes: Vec<TyScope>,
+
+    /// A list of the variables that are going to be written in the rewritten VCD file
+    rewrite_variables: Vec<VcdRewriteVariable>,
+    /// The indexes of the rewrite variables that depend on each id code of the original VCD file
+    dependents: HashMap<IdCode, Vec<usize>>,
+    /// The last value written for each rewrite variable
+    written_values: Vec<vcd::Vector>,
+    /// Copy the variables and scopes of the original VCD file that are not in the tywaves scopes
+    keep_untyped_signals: bool,
+    /// How the struct and vector variables are written
+    aggregate_mode: AggregateMode,
+    /// How the enum variables are written
+    enum_mode: EnumMode,
+    /// The string variables with the variant names of the enums, by index of rewrite variable
+    enum_names: HashMap<usize, EnumName>,
+    /// The real and string variables: the id codes of the rewritten variables that copy the
+    /// values of each original variable
+    value_variables: HashMap<IdCode, Vec<IdCode>>,
+    /// The values of the real and string constants, written at the beginning of the trace
+    constant_values: Vec<Command>,
+    /// The first timestamp to write: the values at this time are written as `$dumpvars`
+    start_time: Option<u64>,
+    /// The last timestamp to write
+    end_time: Option<u64>,
+    /// The scopes and variables to write
+    path_filter: Option<PathFilter>,
+    /// What to do when a value does not have the width of its typed variable
+    width_policy: WidthMismatchPolicy,
+    /// The path of each rewrite variable
+    rewrite_names: Vec<String>,
+    /// The report of the width mismatches found
+    width_mismatches: Vec<WidthMismatch>,
+    /// Embed the type information of the scopes in the header
+    type_metadata: bool,
+    /// The timescale of the rewritten trace, if different from the original one
+    timescale: Option<(u32, TimescaleUnit)>,
+    /// The factor (numerator and denominator) from the original to the rewritten timescale
+    timescale_ratio: Option<(u128, u128)>,
+    /// The offset added to the timestamps of the rewritten trace
+    time_offset: i64,
+    /// Number the timestamps with the cycles of a clock
+    clock_cycles: Option<ClockCycles>,
+}
+
+impl<'a> VcdRewriter<'a> {
+    /// Get the full path of the rewritten VCD file (empty if the rewriter is not writing a file)
+    pub fn get_final_file(&self) -> &String {
+        &self.output_vcd_name
+    }
+
+    /// Create a rewriter of the trace `vcd_path` (VCD or FST) to `out_vcd_name`.
+    /// The format of the output is selected by its extension: `.vcd` (the default) or `.fst`.
+    pub fn new(
+        vcd_path: &Path,
+        tywaves_scopes: Vec<TyScope>,
+        out_vcd_name: String,
+    ) -> Result<Self> {
+        // Raise an error if out_vcd_name does not end with `.vcd` or `.fst`
+        let format = match Path::new(&out_vcd_name).extension() {
+            Some(_) => OutputFormat::from_path(Path::new(&out_vcd_name))
+                .ok_or_else(|| VcdRewriteError::InvalidFileExtension(out_vcd_name.clone()))?,
+            None => OutputFormat::Vcd,
+        };
+        Self::new_with_format(vcd_path, tywaves_scopes, out_vcd_name, format)
+    }
+
+    /// Create a rewriter of `vcd_path` to `out_vcd_name` in the given format, whatever the
+    /// extension of the output.
+    ///
+    /// FST traces have no real or string variables: an FST output is rejected, before it is
+    /// created, if the original trace has real or string signals or the typed scopes have real or
+    /// string constants.
+    pub fn new_with_format(
+        vcd_path: &Path,
+        tywaves_scopes: Vec<TyScope>,
+        out_vcd_name: String,
+        format: OutputFormat,
+    ) -> Result<Self> {
+        let mut reader = source::open(vcd_path)?;
+        let vcd_header = reader.parse_header()?;
+
+        let out_path = Path::new(&out_vcd_name);
+        let writer: Box<dyn TraceWriter> = match format {
+            OutputFormat::Vcd => Box::new(VcdTraceWriter::create(out_path)?),
+            OutputFormat::Fst => {
+                if has_value_vars(&vcd_header.items)
+                    || tywaves_scopes.iter().any(has_value_constants)
+                {
+                    return Err(VcdRewriteError::Other(
+                        "Real and string values cannot be written to an FST trace".to_string(),
+                    ));
+                }
+                Box::new(FstTraceWriter::create(out_path)?)
+            }
+        };
+
+        let mut vcd_rw = Self::from_parts(reader, writer, tywaves_scopes);
+        vcd_rw.output_vcd_name = out_vcd_name;
+        vcd_rw.vcd_header = vcd_header;
+        vcd_rw.is_header_parsed = true;
+        Ok(vcd_rw)
+    }
+
+    /// Create a rewriter of the VCD trace read from `reader` (i.e. a pipe from the simulator) to
+    /// a VCD trace written to `writer` (i.e. a socket or an in-memory buffer).
+    pub fn from_io<R: BufRead + 'a, W: Write + 'a>(
+        reader: R,
+        writer: W,
+        tywaves_scopes: Vec<TyScope>,
+    ) -> Self {
+        Self::from_parts(
+            Box::new(source::vcd_parser(reader)),
+            Box::new(VcdTraceWriter::new(writer)),
+            tywaves_scopes,
+        )
+    }
+
+    /// Create a rewriter from any source of the original trace to any trace writer.
+    pub fn from_parts(
+        reader: Box<dyn WaveformSource + 'a>,
+        writer: Box<dyn TraceWriter + 'a>,
+        tywaves_scopes: Vec<TyScope>,
+    ) -> Self {
+        Self {
+            reader,
+            writer,
+            output_vcd_name: String::new(),
+            tywaves_scopes,
+            vcd_header: Header::default(),
+            is_header_parsed: false,
+            rewrite_variables: vec![],
+            dependents: HashMap::new(),
+            written_values: vec![],
+            keep_untyped_signals: false,
+            aggregate_mode: AggregateMode::default(),
+            enum_mode: EnumMode::default(),
+            enum_names: HashMap::new(),
+            value_variables: HashMap::new(),
+            constant_values: vec![],
+            start_time: None,
+            end_time: None,
+            path_filter: None,
+            width_policy: WidthMismatchPolicy::default(),
+            rewrite_names: vec![],
+            width_mismatches: vec![],
+            type_metadata: false,
+            timescale: None,
+            timescale_ratio: None,
+            time_offset: 0,
+            clock_cycles: None,
+        }
+    }
+
+    /// Copy the signals of the original VCD file that are not covered by the tywaves scopes
+    /// (i.e. `_GEN_*` and temporary signals, or untyped scopes) with their values.
+    pub fn with_untyped_signals(mut self, keep_untyped_signals: bool) -> Self {
+        self.keep_untyped_signals = keep_untyped_signals;
+        self
+    }
+
+    /// Set how the struct and vector variables are written in the rewritten trace.
+    pub fn with_aggregate_mode(mut self, aggregate_mode: AggregateMode) -> Self {
+        self.aggregate_mode = aggregate_mode;
+        self
+    }
+
+    /// Set how the ground enum variables are written in the rewritten trace.
+    /// It has no effect on FST traces, where the enums are written as bits.
+    pub fn with_enum_mode(mut self, enum_mode: EnumMode) -> Self {
+        self.enum_mode = enum_mode;
+        self
+    }
+
+    /// Write only the timestamps between `start_time` and `end_time` (both included).
+    /// The values at `start_time` are written as a `$dumpvars` snapshot.
+    pub fn with_time_window(mut self, start_time: Option<u64>, end_time: Option<u64>) -> Self {
+        self.start_time = start_time;
+        self.end_time = end_time;
+        self
+    }
+
+    /// Write only the scopes and variables selected by the path `patterns` (see [PathFilter]).
+    /// The scopes containing a selected variable or scope are written as well.
+    pub fn with_path_filter<S: Into<String>>(
+        mut self,
+        patterns: impl IntoIterator<Item = S>,
+    ) -> Self {
+        self.path_filter = Some(PathFilter::new(patterns));
+        self
+    }
+
+    /// Set what to do when a value does not have the width of its typed variable.
+    pub fn with_width_policy(mut self, width_policy: WidthMismatchPolicy) -> Self {
+        self.width_policy = width_policy;
+        self
+    }
+
+    /// Get the width mismatches found during the rewrite, one for each typed variable and
+    /// original id code. It is always empty with [WidthMismatchPolicy::Abort].
+    pub fn get_width_mismatches(&self) -> &[WidthMismatch] {
+        &self.width_mismatches
+    }
+
+    /// Embed the type information of each top scope in a `$comment` section of the header,
+    /// so that [metadata::read_tyvcd] can reconstruct the [tyvcd::TyVcd] from the rewritten VCD.
+    /// FST traces have no comments: the information is not written.
+    ///
+    /// The scopes are embedded as rewritten: only the selected variables and scopes, and the trace
+    /// values refer to the variables of the rewritten trace. In the hierarchy aggregate mode, the
+    /// trace names of the structs and vectors refer to their struct scopes.
+    pub fn with_type_metadata(mut self, type_metadata: bool) -> Self {
+        self.type_metadata = type_metadata;
+        self
+    }
+
+    /// Convert the timestamps to the timescale `ts` `unit` (rounded down).
+    /// The rewrite fails if the original trace has no timescale.
+    pub fn with_timescale(mut self, ts: u32, unit: TimescaleUnit) -> Self {
+        self.timescale = Some((ts, unit));
+        self
+    }
+
+    /// Add `offset` to the timestamps of the rewritten trace (after any conversion).
+    /// A negative timestamp makes the rewrite fail.
+    pub fn with_time_offset(mut self, offset: i64) -> Self {
+        self.time_offset = offset;
+        self
+    }
+
+    /// Number the timestamps with the rising edges of the typed clock at `clock_path`
+    /// (i.e. `Top.clock`): all the changes of a clock cycle are written at the same timestamp,
+    /// with the last value of the cycle.
+    pub fn with_clock_cycles(mut self, clock_path: impl Into<String>) -> Self {
+        self.clock_cycles = Some(ClockCycles {
+            path: clock_path.into(),
+            idx: 0,
+            cycles: 0,
+            level: None,
+            changes: None,
+        });
+        self
+    }
+
+    /// Verify the rewritten file against the original trace `vcd_path` after [Self::rewrite]:
+    /// the values of every typed ground variable must match its original signal.
+    ///
+    /// The time window and the path filter of the rewriter are applied. The times cannot be
+    /// converted (timescale, offset or clock cycles).
+    pub fn verify(&self, vcd_path: &Path) -> Result<verify::RewriteReport> {
+        if self.output_vcd_name.is_empty() {
+            return Err(VcdRewriteError::Other(
+                "Only a rewritten file can be verified".to_string(),
+            ));
+        }
+        if self.timescale.is_some() || self.time_offset != 0 || self.clock_cycles.is_some() {
+            return Err(VcdRewriteError::Other(
+                "The times of the rewritten trace differ from the original ones".to_string(),
+            ));
+        }
+        verify::RewriteCheck::new(&self.tywaves_scopes)
+            .with_time_window(self.start_time, self.end_time)
+            .with_path_filter(self.path_filter.clone())
+            .check_files(vcd_path, Path::new(&self.output_vcd_name))
+    }
+
+    /// Rewrite the VCD file
+    pub fn rewrite(&mut self) -> Result<()> {
+        self.rewrite_header()?;
+
+        // Find the clock that numbers the timestamps
+        if let Some(clock) = &mut self.clock_cycles {
+            clock.idx = self
+                .rewrite_names
+                .iter()
+                .position(|name| *name == clock.path)
+                .ok_or_else(|| {
+                    VcdRewriteError::Other(format!("The clock {} is not rewritten", clock.path))
+                })?;
+        }
+
+        // Initialize the variables:
+        // this will prevent some errors due to some missing variables in the original VCD file
+        self.written_values = self
+            .rewrite_variables
+            .iter()
+            .map(|variable| variable.get_value())
+            .collect();
+        if self.start_time.is_none() {
+            self.write_all_values(&[])?;
+        }
+
+        self.rewrite_commands()?;
+        self.writer.finish()?;
+        Ok(())
+    }
+
+    /// Rewrite the header of the VCD file with the tywaves scopes
+    fn rewrite_header(&mut self) -> Result<()> {
+        if !self.is_header_parsed {
+            self.vcd_header = self.reader.parse_header()?;
+            self.is_header_parsed = true;
+        }
+        let vcd_header = &self.vcd_header;
+        if self.timescale.is_some() && vcd_header.timescale.is_none() {
+            return Err(VcdRewriteError::Other(
+                "The timescale cannot be converted: the original trace has no timescale"
+                    .to_string(),
+            ));
+        }
+        if let Some(date) = &vcd_header.date {
+            self.writer.date(date)?;
+        }
+        if let Some(version) = &vcd_header.version {
+            self.writer.version(version)?;
+        }
+        if let Some((ts, unit)) = self.timescale.or(vcd_header.timescale) {
+            self.writer.timescale(ts, unit)?;
+        }
+        // The times in femtoseconds of the original and rewritten timescales
+        let femtoseconds =
+            |(ts, unit): (u32, TimescaleUnit)| ts as u128 * 10u128.pow(15) / unit.divisor() as u128;
+        self.timescale_ratio = vcd_header
+            .timescale
+            .zip(self.timescale)
+            .map(|(from, to)| (femtoseconds(from), femtoseconds(to)));
+
+        // Parse the scopes of the tywave state
+        for scope in &self.tywaves_scopes.clone() {
+            if self.has_selection(scope, &[]) {
+                if self.type_metadata {
+                    let rewritten_scope = self.rewritten_scope(scope, &[]);
+                    self.writer
+                        .comment(&metadata::encode_scope(&rewritten_scope)?)?;
+                }
+                self.add_scope_to_header(scope, &[])?;
+            }
+        }
+
+        // Copy the top scopes that are not typed
+        if self.keep_untyped_signals {
+            let typed_scopes: HashSet<String> = self
+                .tywaves_scopes
+                .iter()
+                .filter_map(|scope| scope.get_trace_name().cloned())
+                .collect();
+            for item in &self.vcd_header.items.clone() {
+                if let vcd::ScopeItem::Scope(scope) = item {
+                    if !typed_scopes.contains(&scope.identifier)
+                        && self.has_original_selection(scope, &[])
+                    {
+                        self.add_original_scope_to_header(scope, &[])?;
+                    }
+                }
+            }
+        }
+
+        // Finish the definitions of the header
+        self.writer.enddefinitions()?;
+
+        Ok(())
+    }
+
+    /// Return a [TyScope] as it is written in the rewritten trace: with the selected variables and
+    /// child scopes only, and the trace values of the variables referring to the rewritten ones.
+    fn rewritten_scope(&self, scope: &TyScope, path_scope: &[String]) -> TyScope {
+        let mut rewritten_scope = scope.clone();
+        let Some(scope_name) = scope.get_trace_name() else {
+            return rewritten_scope;
+        };
+        let child_path_scope = &[path_scope, std::slice::from_ref(scope_name)].concat();
+
+        rewritten_scope.variables = scope
+            .variables
+            .iter()
+            .filter(|variable| self.is_selected(child_path_scope, Self::reference_name(variable)))
+            .map(|variable| {
+                let mut rewritten = variable.clone();
+                rewritten.update_trace_value(TraceValue::RefTraceName(
+                    Self::reference_name(variable).clone(),
+                ));
+                match self.aggregate_mode {
+                    // The fields are slices of the rewritten variable
+                    AggregateMode::Flatten => clear_field_trace_values(&mut rewritten),
+                    // The fields are variables of the struct scope of the rewritten variable
+                    AggregateMode::Hierarchy => set_field_trace_names(&mut rewritten),
+                }
+                rewritten
+            })
+            .collect();
+
+        rewritten_scope.subscopes = scope
+            .subscopes
+            .iter()
+            .filter_map(|(scope_id, child_scope)| {
+                let child_scope = child_scope.read().unwrap();
+                self.has_selection(&child_scope, child_path_scope)
+                    .then(|| self.rewritten_scope(&child_scope, child_path_scope))
+                    .map(|child_scope| (scope_id.clone(), Arc::new(RwLock::new(child_scope))))
+            })
+            .collect();
+        rewritten_scope
+    }
+
+    /// Add one [TyScope] to the header of the rewritten VCD file.
+    /// Search for child [TyVariable] and [TyScope]s and add them to the header.
+    fn add_scope_to_header(&mut self, scope: &TyScope, path_scope: &[String]) -> Result<()> {
+        // Add the scopes to the header
+        let scope_name = scope.get_trace_name().ok_or_else(|| {
+            VcdRewriteError::Other(format!("Failed to get the scope from {}", scope.name))
+        })?;
+
+        self.writer.add_module(scope_name)?;
+
+        // The scope of the children of the current scope
+        let child_path_scope = &[path_scope, std::slice::from_ref(scope_name)].concat();
+
+        // Add the variables to the header
+        let mut covered_vars = HashSet::new();
+        for variable in &scope.variables {
+            if self.is_selected(child_path_scope, Self::reference_name(variable)) {
+                self.add_variable_to_header(variable, child_path_scope)?;
+            }
+            covered_vars.extend(variable.get_trace_name().cloned());
+            #[allow(deprecated)]
+            for ground_variable in variable.collect_ground_variables() {
+                covered_vars.extend(ground_variable.get_trace_name().cloned());
+            }
+        }
+
+        // Add the variables of the original scope that are not covered by the typed variables
+        let original_scope = match self.keep_untyped_signals {
+            true => self.vcd_header.find_scope(child_path_scope).cloned(),
+            false => None,
+        };
+        if let Some(original_scope) = &original_scope {
+            for item in &original_scope.items {
+                if let vcd::ScopeItem::Var(var) = item {
+                    if !covered_vars.contains(&var.reference)
+                        && self.is_selected(child_path_scope, &var.reference)
+                    {
+                        self.add_original_variable_to_header(var, child_path_scope)?;
+                    }
+                }
+            }
+        }
+
+        // Add the child scopes to the header
+        for child_scope in scope.subscopes.values() {
+            let child_scope = child_scope.read().unwrap();
+            if self.has_selection(&child_scope, child_path_scope) {
+                self.add_scope_to_header(&child_scope, child_path_scope)?;
+            }
+        }
+
+        // Add the child scopes of the original scope that are not typed
+        if let Some(original_scope) = &original_scope {
+            let typed_scopes: HashSet<String> = scope
+                .subscopes
+                .values()
+                .filter_map(|child_scope| child_scope.read().unwrap().get_trace_name().cloned())
+                .collect();
+            for item in &original_scope.items {
+                if let vcd::ScopeItem::Scope(child_scope) = item {
+                    if !typed_scopes.contains(&child_scope.identifier)
+                        && self.has_original_selection(child_scope, child_path_scope)
+                    {
+                        self.add_original_scope_to_header(child_scope, child_path_scope)?;
+                    }
+                }
+            }
+        }
+
+        // Close the scope
+        self.writer.upscope()?;
+
+        Ok(())
+    }
+
+    /// Add one [TyVariable] to the header of the rewritten VCD file.
+    fn add_variable_to_header(
+        &mut self,
+        ty_variable: &TyVariable,
+        path_scope: &[String],
+    ) -> Result<()> {
+        let reference_name = Self::reference_name(ty_variable);
+        self.add_named_variable_to_header(ty_variable, reference_name, path_scope, &[])
+    }
+
+    /// The name of a [TyVariable] in the rewritten VCD file.
+    fn reference_name(ty_variable: &TyVariable) -> &String {
+        if let Some(trace_name) = ty_variable.get_trace_name() {
+            trace_name
+        } else {
+            &ty_variable.name
+        }
+    }
+
+    /// Return true if the variable or scope `name` in `path_scope` is selected by the path filter.
+    fn is_selected(&self, path_scope: &[String], name: &str) -> bool {
+        match &self.path_filter {
+            Some(filter) => filter.is_selected(&[path_scope, &[name.to_string()]].concat()),
+            None => true,
+        }
+    }
+
+    /// Return true if the [TyScope] or something inside it is selected by the path filter.
+    fn has_selection(&self, scope: &TyScope, path_scope: &[String]) -> bool {
+        let Some(scope_name) = scope.get_trace_name() else {
+            return false;
+        };
+        if self.is_selected(path_scope, scope_name) {
+            return true;
+        }
+        let child_path_scope = &[path_scope, std::slice::from_ref(scope_name)].concat();
+        let original_scope = match self.keep_untyped_signals {
+            true => self.vcd_header.find_scope(child_path_scope),
+            false => None,
+        };
+        scope
+            .variables
+            .iter()
+            .any(|variable| self.is_selected(child_path_scope, Self::reference_name(variable)))
+            || scope.subscopes.values().any(|child_scope| {
+                self.has_selection(&child_scope.read().unwrap(), child_path_scope)
+            })
+            || original_scope.is_some_and(|scope| self.has_original_selection(scope, path_scope))
+    }
+
+    /// Return true if the scope of the original VCD file or something inside it is selected by
+    /// the path filter.
+    fn has_original_selection(&self, scope: &vcd::Scope, path_scope: &[String]) -> bool {
+        if self.is_selected(path_scope, &scope.identifier) {
+            return true;
+        }
+        let child_path_scope = &[path_scope, std::slice::from_ref(&scope.identifier)].concat();
+        scope.items.iter().any(|item| match item {
+            vcd::ScopeItem::Var(var) => self.is_selected(child_path_scope, &var.reference),
+            vcd::ScopeItem::Scope(child_scope) => {
+                self.has_original_selection(child_scope, child_path_scope)
+            }
+            _ => false,
+        })
+    }
+
+    /// Add one [TyVariable] to the header of the rewritten VCD file with the given name.
+    /// `struct_path` contains the names of the struct scopes around it.
+    fn add_named_variable_to_header(
+        &mut self,
+        ty_variable: &TyVariable,
+        reference_name: &str,
+        path_scope: &[String],
+        struct_path: &[String],
+    ) -> Result<()> {
+        // Expand the aggregates in nested scopes
+        if self.aggregate_mode == AggregateMode::Hierarchy {
+            if let TyVarKind::Struct { fields } | TyVarKind::Vector { fields } = &ty_variable.kind {
+                let is_vector = matches!(ty_variable.kind, TyVarKind::Vector { .. });
+                self.writer.add_struct(reference_name)?;
+                let field_struct_path = &[struct_path, &[reference_name.to_string()]].concat();
+                for (idx, field) in fields.iter().enumerate() {
+                    let field_name = match is_vector {
+                        true => format!("[{}]", idx),
+                        false => field.name.clone(),
+                    };
+                    self.add_named_variable_to_header(
+                        field,
+                        &field_name,
+                        path_scope,
+                        field_struct_path,
+                    )?;
+                }
+                self.writer.upscope()?;
+                return Ok(());
+            }
+        }
+
+        // A ground variable keeps the type of its original variable
+        let original_var = match ty_variable.kind {
+            TyVarKind::Ground(_) => ty_variable.get_trace_name().and_then(|trace_name| {
+                let path = [path_scope, std::slice::from_ref(trace_name)].concat();
+                self.vcd_header.find_var(&path).cloned()
+            }),
+            _ => None,
+        };
+
+        // Real and string values are copied as they are
+        if let Some(var) = original_var
+            .as_ref()
+            .filter(|v| is_value_var_type(v.var_type))
+        {
+            let new_id = self
+                .writer
+                .add_var(var.var_type, var.size, reference_name, None)?;
+            self.value_variables
+                .entry(var.code)
+                .or_default()
+                .push(new_id);
+            return Ok(());
+        }
+        if let TyVarKind::Ground(_) = ty_variable.kind {
+            match ty_variable.get_trace_value() {
+                TraceValue::Constant(ConstValue::Real(value)) => {
+                    let new_id = self
+                        .writer
+                        .add_var(VarType::Real, 64, reference_name, None)?;
+                    self.constant_values
+                        .push(Command::ChangeReal(new_id, *value));
+                    return Ok(());
+                }
+                TraceValue::Constant(ConstValue::String(value)) => {
+                    let new_id = self
+                        .writer
+                        .add_var(VarType::String, 1, reference_name, None)?;
+                    self.constant_values
+                        .push(Command::ChangeString(new_id, value.clone()));
+                    return Ok(());
+                }
+                _ => {}
+            }
+        }
+
+        // Get the information for the variable
+        let var_type = original_var.map_or(VarType::Wire, |var| var.var_type);
+        let width = ty_variable.kind.find_width() as u32;
+        let index = (width > 1).then_some(ReferenceIndex::Range(width as i32 - 1, 0));
+
+        // The variant names of a ground enum
+        let is_enum =
+            ty_variable.enum_val_map.is_some() && matches!(ty_variable.kind, TyVarKind::Ground(_));
+        let enum_mode = match self.writer.has_strings() {
+            true => self.enum_mode,
+            false => EnumMode::Bits,
+        };
+        let (new_id, enum_name) = match (is_enum, enum_mode) {
+            (true, EnumMode::Companion) => {
+                let new_id = self
+                    .writer
+                    .add_var(var_type, width, reference_name, index)?;
+                let name_id = self.writer.add_var(
+                    VarType::String,
+                    1,
+                    &format!("{}_variant", reference_name),
+                    None,
+                )?;
+                (new_id, Some((name_id, true)))
+            }
+            (true, EnumMode::Replace) => {
+                let name_id = self
+                    .writer
+                    .add_var(VarType::String, 1, reference_name, None)?;
+                (name_id, Some((name_id, false)))
+            }
+            // Write the variable to the VCD file
+            _ => {
+                let new_id = self
+                    .writer
+                    .add_var(var_type, width, reference_name, index)?;
+                (new_id, None)
+            }
+        };
+
+        // Update the rewrite_variables list
+        let rewrite_variable =
+            VcdRewriteVariable::create(new_id, width, ty_variable, path_scope, &self.vcd_header);
+        if let Some((id_code, write_bits)) = enum_name {
+            self.enum_names.insert(
+                self.rewrite_variables.len(),
+                EnumName {
+                    id_code,
+                    write_bits,
+                    variable: ty_variable.clone(),
+                },
+            );
+        }
+        let name = [path_scope, struct_path, &[reference_name.to_string()]]
+            .concat()
+            .join(".");
+        self.push_rewrite_variable(rewrite_variable, name);
+
+        Ok(())
+    }
+
+    /// Copy a scope of the original VCD file, with all its selected variables and child scopes.
+    fn add_original_scope_to_header(
+        &mut self,
+        scope: &vcd::Scope,
+        path_scope: &[String],
+    ) -> Result<()> {
+        self.writer.add_module(&scope.identifier)?;
+        let child_path_scope = &[path_scope, std::slice::from_ref(&scope.identifier)].concat();
+        for item in &scope.items {
+            match item {
+                vcd::ScopeItem::Var(var) if self.is_selected(child_path_scope, &var.reference) => {
+                    self.add_original_variable_to_header(var, child_path_scope)?
+                }
+                vcd::ScopeItem::Scope(child_scope)
+                    if self.has_original_selection(child_scope, child_path_scope) =>
+                {
+                    self.add_original_scope_to_header(child_scope, child_path_scope)?
+                }
+                _ => {}
+            }
+        }
+        self.writer.upscope()?;
+        Ok(())
+    }
+
+    /// Copy a variable of the original VCD file: its value is the value of the original one.
+    fn add_original_variable_to_header(
+        &mut self,
+        var: &vcd::Var,
+        path_scope: &[String],
+    ) -> Result<()> {
+        let new_id = self
+            .writer
+            .add_var(var.var_type, var.size, &var.reference, var.index)?;
+        if is_value_var_type(var.var_type) {
+            self.value_variables
+                .entry(var.code)
+                .or_default()
+                .push(new_id);
+            return Ok(());
+        }
+        let source = IdCodeWithShift::create(
+            var.code,
+            vcd::Vector::filled(Value::X, var.size as usize),
+            false,
+        );
+        let name = [path_scope, std::slice::from_ref(&var.reference)]
+            .concat()
+            .join(".");
+        self.push_rewrite_variable(
+            VcdRewriteVariable::new(new_id, var.size, vec![source]),
+            name,
+        );
+        Ok(())
+    }
+
+    /// Add a variable with its path to the rewrite_variables list and index its source id codes.
+    fn push_rewrite_variable(&mut self, rewrite_variable: VcdRewriteVariable, name: String) {
+        let idx = self.rewrite_variables.len();
+        for source_id_code in rewrite_variable.get_source_id_codes() {
+            let dependents = self.dependents.entry(source_id_code).or_default();
+            if dependents.last() != Some(&idx) {
+                dependents.push(idx);
+            }
+        }
+        self.rewrite_variables.push(rewrite_variable);
+        self.rewrite_names.push(name);
+    }
+
+    fn rewrite_commands(&mut self) -> Result<()> {
+        // The variables updated since the last timestamp
+        let mut updated: Vec<usize> = Vec::new();
+        let mut is_updated = vec![false; self.rewrite_variables.len()];
+        // The real and string values changed since the last timestamp
+        let mut updated_values: Vec<Command> = Vec::new();
+        // The timestamp of the updated values, written only when they are: the number of clock
+        // cycles is known only after all its changes
+        let mut pending_time: Option<u64> = None;
+        // The last timestamp written in the rewritten trace
+        let mut output_time: Option<u64> = None;
+        // The values are written only after the start of the time window
+        let mut started = self.start_time.is_none();
+        // The last real and string values before the start of the time window
+        let mut start_values: HashMap<IdCode, Command> = HashMap::new();
+        // The index of the report of each width mismatch
+        let mut mismatch_reports: HashMap<(usize, IdCode, usize), usize> = HashMap::new();
+
+        while let Some(command) = self.reader.next() {
+            let (original_id, value) = match command? {
+                Command::ChangeScalar(original_id, value) => {
+                    (original_id, vcd::Vector::from([value]))
+                }
+                Command::ChangeVector(original_id, value) => (original_id, value),
+                Command::Timestamp(ts) => {
+                    if self.end_time.is_some_and(|end_time| ts > end_time) {
+                        break;
+                    }
+                    if let (false, Some(start_time)) = (started, self.start_time) {
+                        // The changes at start_time are part of the snapshot
+                        if ts <= start_time {
+                            continue;
+                        }
+                        let values = std::mem::take(&mut start_values);
+                        self.write_start_snapshot(
+                            start_time,
+                            values,
+                            &mut output_time,
+                            &mut updated,
+                            &mut is_updated,
+                        )?;
+                        started = true;
+                    } else {
+                        self.write_pending(
+                            pending_time,
+                            &mut output_time,
+                            &mut updated,
+                            &mut is_updated,
+                            &mut updated_values,
+                        )?;
+                    }
+                    pending_time = Some(ts);
+                    continue;
+                }
+                command @ (Command::ChangeString(..) | Command::ChangeReal(..)) => {
+                    match started {
+                        true => updated_values.push(command),
+                        false => {
+                            if let Command::ChangeString(id, _) | Command::ChangeReal(id, _) =
+                                command
+                            {
+                                start_values.insert(id, command);
+                            }
+                        }
+                    }
+                    continue;
+                }
+                _ => continue, // ignore the other commands
+            };
+
+            // Update only the variables that depend on the changed id code
+            let Some(dependents) = self.dependents.get(&original_id) else {
+                continue;
+            };
+            for &idx in dependents {
+                let variable = &mut self.rewrite_variables[idx];
+                if variable.update_value_with_policy(&original_id, &value, self.width_policy)?
+                    && !is_updated[idx]
+                {
+                    is_updated[idx] = true;
+                    updated.push(idx);
+                }
+                if variable.has_width_mismatches() {
+                    for (id_code, expected_width) in variable.take_width_mismatches() {
+                        let key = (idx, id_code, expected_width);
+                        let report = *mismatch_reports.entry(key).or_insert_with(|| {
+                            self.width_mismatches.push(WidthMismatch {
+                                variable: self.rewrite_names[idx].clone(),
+                                id_code,
+                                expected_width,
+                                actual_width: value.len(),
+                                first_time: pending_time.unwrap_or(0),
+                                count: 0,
+                            });
+                            self.width_mismatches.len() - 1
+                        });
+                        self.width_mismatches[report].count += 1;
+                    }
+                }
+                // Count the rising edges of the clock
+                if let Some(clock) = self.clock_cycles.as_mut().filter(|clock| clock.idx == idx) {
+                    clock.update(variable.get_value().iter().last());
+                }
+            }
+        }
+        match (started, self.start_time) {
+            // The trace ends before the time window: write the last values
+            (false, Some(start_time)) => {
+                self.write_start_snapshot(
+                    start_time,
+                    start_values,
+                    &mut output_time,
+                    &mut updated,
+                    &mut is_updated,
+                )?;
+            }
+            _ => self.write_pending(
+                pending_time,
+                &mut output_time,
+                &mut updated,
+                &mut is_updated,
+                &mut updated_values,
+            )?,
+        }
+        self.write_cycle(&mut output_time)?;
+
+        Ok(())
+    }
+
+    /// Write the changes of the original timestamp `pending_time` (or of the beginning of the
+    /// trace if it is `None`) at its time in the rewritten trace.
+    fn write_pending(
+        &mut self,
+        pending_time: Option<u64>,
+        output_time: &mut Option<u64>,
+        updated: &mut Vec<usize>,
+        is_updated: &mut [bool],
+        updated_values: &mut Vec<Command>,
+    ) -> Result<()> {
+        if let Some(pending_time) = pending_time {
+            if self.clock_cycles.is_some() {
+                return self.buffer_cycle(
+                    pending_time,
+                    output_time,
+                    updated,
+                    is_updated,
+                    updated_values,
+                );
+            }
+            self.write_timestamp(pending_time, output_time)?;
+        }
+        self.write_updated(updated, is_updated)?;
+        for command in updated_values.drain(..) {
+            self.write_value_command(&command)?;
+        }
+        Ok(())
+    }
+
+    /// Keep the changes of the original timestamp `pending_time` until the end of its clock
+    /// cycle, so that only the last value of each variable in a cycle is written.
+    fn buffer_cycle(
+        &mut self,
+        pending_time: u64,
+        output_time: &mut Option<u64>,
+        updated: &mut Vec<usize>,
+        is_updated: &mut [bool],
+        updated_values: &mut Vec<Command>,
+    ) -> Result<()> {
+        let time = self.convert_time(pending_time)?;
+        // A new cycle starts: write the changes of the previous one
+        let is_new_cycle = self
+            .clock_cycles
+            .as_ref()
+            .and_then(|clock| clock.changes.as_ref())
+            .is_some_and(|changes| changes.time != time);
+        if is_new_cycle {
+            self.write_cycle(output_time)?;
+        }
+
+        let values: Vec<(usize, vcd::Vector)> = updated
+            .drain(..)
+            .map(|idx| {
+                is_updated[idx] = false;
+                (idx, self.rewrite_variables[idx].get_value())
+            })
+            .collect();
+        let Some(clock) = self.clock_cycles.as_mut() else {
+            return Ok(());
+        };
+        let changes = clock.changes.get_or_insert_with(|| CycleChanges {
+            time,
+            values: BTreeMap::new(),
+            commands: Vec::new(),
+        });
+        changes.values.extend(values);
+        for command in updated_values.drain(..) {
+            let id_code = value_command_id(&command);
+            changes
+                .commands
+                .retain(|other| value_command_id(other) != id_code);
+            changes.commands.push(command);
+        }
+        Ok(())
+    }
+
+    /// Write the last values of the clock cycle kept by [Self::buffer_cycle].
+    fn write_cycle(&mut self, output_time: &mut Option<u64>) -> Result<()> {
+        let Some(changes) = self
+            .clock_cycles
+            .as_mut()
+            .and_then(|clock| clock.changes.take())
+        else {
+            return Ok(());
+        };
+        if *output_time != Some(changes.time) {
+            self.writer.timestamp(changes.time)?;
+            *output_time = Some(changes.time);
+        }
+        for (idx, value) in changes.values {
+            if self.written_values[idx] != value {
+                self.write_variable(idx, &value)?;
+                self.written_values[idx] = value;
+            }
+        }
+        for command in &changes.commands {
+            self.write_value_command(command)?;
+        }
+        Ok(())
+    }
+
+    /// Write the timestamp of the rewritten trace for the original timestamp `ts`, unless it is
+    /// the last one written (i.e. in the same clock cycle).
+    fn write_timestamp(&mut self, ts: u64, output_time: &mut Option<u64>) -> Result<()> {
+        let ts = self.convert_time(ts)?;
+        if *output_time != Some(ts) {
+            self.writer.timestamp(ts)?;
+            *output_time = Some(ts);
+        }
+        Ok(())
+    }
+
+    /// Convert an original timestamp to the timescale, offset and clock cycles of the rewritten
+    /// trace.
+    fn convert_time(&self, ts: u64) -> Result<u64> {
+        let ts = match (&self.clock_cycles, self.timescale_ratio) {
+            (Some(clock), _) => clock.cycles as i128,
+            (None, Some((num, den))) => (ts as u128 * num / den) as i128,
+            (None, None) => ts as i128,
+        };
+        u64::try_from(ts + self.time_offset as i128).map_err(|_| {
+            VcdRewriteError::Other(format!(
+                "The timestamp {} with offset {} is out of range",
+                ts, self.time_offset
+            ))
+        })
+    }
+
+    /// Move to the start of the time window and write the current values of all the variables,
+    /// with the last real and string `values` of the original VCD file.
+    fn write_start_snapshot(
+        &mut self,
+        start_time: u64,
+        values: HashMap<IdCode, Command>,
+        output_time: &mut Option<u64>,
+        updated: &mut Vec<usize>,
+        is_updated: &mut [bool],
+    ) -> Result<()> {
+        for idx in updated.drain(..) {
+            is_updated[idx] = false;
+            self.written_values[idx] = self.rewrite_variables[idx].get_value();
+        }
+        self.write_timestamp(start_time, output_time)?;
+        self.write_all_values(&values.into_values().collect::<Vec<_>>())
+    }
+
+    /// Write the last written value of all the variables, the constants and the real and string
+    /// `values` of the original VCD file.
+    /// Inside a time window, the values are a `$dumpvars` snapshot.
+    fn write_all_values(&mut self, values: &[Command]) -> Result<()> {
+        let is_snapshot = self.start_time.is_some();
+        if is_snapshot {
+            self.writer.begin_dumpvars()?;
+        }
+        for idx in 0..self.rewrite_variables.len() {
+            let value = self.written_values[idx].clone();
+            self.write_variable(idx, &value)?;
+        }
+        for command in &self.constant_values {
+            match command {
+                Command::ChangeReal(id_code, value) => self.writer.change_real(*id_code, *value)?,
+                Command::ChangeString(id_code, value) => {
+                    self.writer.change_string(*id_code, value)?
+                }
+                _ => {}
+            }
+        }
+        for command in values {
+            self.write_value_command(command)?;
+        }
+        if is_snapshot {
+            self.writer.end_dumpvars()?;
+        }
+        Ok(())
+    }
+
+    /// Copy a real or string value change of the original VCD file to its rewritten variables.
+    fn write_value_command(&mut self, command: &Command) -> Result<()> {
+        match command {
+            Command::ChangeString(original_id, value) => {
+                for &new_id in self.value_variables.get(original_id).into_iter().flatten() {
+                    self.writer.change_string(new_id, value)?;
+                }
+            }
+            Command::ChangeReal(original_id, value) => {
+                for &new_id in self.value_variables.get(original_id).into_iter().flatten() {
+                    self.writer.change_real(new_id, *value)?;
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// Write the variables updated in the current timestamp whose value differs from the last
+    /// value written.
+    fn write_updated(&mut self, updated: &mut Vec<usize>, is_updated: &mut [bool]) -> Result<()> {
+        // Keep the order of the header
+        updated.sort_unstable();
+        for idx in updated.drain(..) {
+            is_updated[idx] = false;
+            let value = self.rewrite_variables[idx].get_value();
+            if self.written_values[idx] != value {
+                self.write_variable(idx, &value)?;
+                self.written_values[idx] = value;
+            }
+        }
+        Ok(())
+    }
+
+    /// Write the value of a rewrite variable and, for enums, the name of its variant.
+    fn write_variable(&mut self, idx: usize, value: &vcd::Vector) -> Result<()> {
+        let id_code = self.rewrite_variables[idx].get_id_code();
+        let Some(enum_name) = self.enum_names.get(&idx) else {
+            return self.writer.change_vector(id_code, value);
+        };
+
+        let name = variant_name(&enum_name.variable, &value.to_string());
+        self.writer.change_string(enum_name.id_code, &name)?;
+        if enum_name.write_bits {
+            self.writer.change_vector(id_code, value)?;
+        }
+        Ok(())
+    }
+}
+
+/// Return the name of the variant of an enum variable written for its bits.
+fn variant_name(variable: &TyVariable, bits: &str) -> String {
+    match variable.decode_value(bits) {
+        DecodedValue::Enum(name) => name,
+        // Unknown encodings and undefined values
+        other => format!("unknown({})", other),
+    }
+}
+
+/// Return the original id code of a real or string change.
+fn value_command_id(command: &Command) -> Option<IdCode> {
+    match command {
+        Command::ChangeString(id_code, _) | Command::ChangeReal(id_code, _) => Some(*id_code),
+        _ => None,
+    }
+}
+
+/// Remove the trace values of the fields of a variable and of their fields.
+fn clear_field_trace_values(variable: &mut TyVariable) {
+    if let TyVarKind::Struct { fields } | TyVarKind::Vector { fields } = &mut variable.kind {
+        for field in fields {
+            field.update_trace_value(TraceValue::RefTraceValues(Vec::new()));
+            clear_field_trace_values(field);
+        }
+    }
+}
+
+/// Name the fields of a variable (and their fields) after their members in a struct scope.
+fn set_field_trace_names(variable: &mut TyVariable) {
+    let is_vector = matches!(variable.kind, TyVarKind::Vector { .. });
+    if let TyVarKind::Struct { fields } | TyVarKind::Vector { fields } = &mut variable.kind {
+        for (idx, field) in fields.iter_mut().enumerate() {
+            let field_name = match is_vector {
+                true => format!("[{}]", idx),
+                false => field.name.clone(),
+            };
+            field.update_trace_value(TraceValue::RefTraceName(field_name));
+            set_field_trace_names(field);
+        }
+    }
+}
+
+/// Return true if a scope item or one of its children is a real or string variable.
+fn has_value_vars(items: &[vcd::ScopeItem]) -> bool {
+    items.iter().any(|item| match item {
+        vcd::ScopeItem::Var(var) => is_value_var_type(var.var_type),
+        vcd::ScopeItem::Scope(scope) => has_value_vars(&scope.items),
+        _ => false,
+    })
+}
+
+/// Return true if a typed scope or one of its children has a real or string constant.
+fn has_value_constants(scope: &TyScope) -> bool {
+    fn is_value_constant(variable: &TyVariable) -> bool {
+        match &variable.kind {
+            TyVarKind::Struct { fields } | TyVarKind::Vector { fields } => {
+                fields.iter().any(is_value_constant)
+            }
+            _ => matches!(
+                variable.get_trace_value(),
+                TraceValue::Constant(ConstValue::String(_) | ConstValue::Real(_))
+            ),
+        }
+    }
+    scope.variables.iter().any(is_value_constant)
+        || scope
+            .subscopes
+            .values()
+            .any(|subscope| has_value_constants(&subscope.read().unwrap()))
+}
+
+/// Return true if the values of a variable of this type are not bit vectors.
+fn is_value_var_type(var_type: VarType) -> bool {
+    matches!(var_type, VarType::Real | VarType::String)
+}
+
+/// Represent a variable of the new VCD file that is going to be written.
+///
+/// A variable in the rewritten VCD file is a combination of multiple variables from the original VCD file.
+/// If a variable is a compound type, it is represented as an ***ordered*** concatenation of the variables that compose it.
+#[derive(Debug)]
+pub struct VcdRewriteVariable {
+    /// The id code of the new variable that is going to be written
+    id_code: IdCode,
+    /// The width of this variable
+    width: u32,
+    /// The path of the variable
+    source_id_codes: Vec<IdCodeWithShift>,
+    /// The id codes and the expected widths of the values resized since the last
+    /// [VcdRewriteVariable::take_width_mismatches]
+    width_mismatches: Vec<(IdCode, usize)>,
+}
+
+impl VcdRewriteVariable {
+    pub fn new(id_code: IdCode, width: u32, source_id_codes: Vec<IdCodeWithShift>) -> Self {
+        Self {
+            id_code,
+            width,
+            source_id_codes,
+            width_mismatches: Vec::new(),
+        }
+    }
+
+    /// Create a new VcdRewriteVariable from a TyVariable.
+    ///
+    /// This function inspects the hierarchy of the TyVariable and for each ground variable found, it creates a new IdCodeWithShift.
+    pub fn create(
+        id_code: IdCode,
+        width: u32,
+        ty_variable: &TyVariable,
+        scope_path: &[String],
+        vcd_header: &Header,
+    ) -> Self {
+        if vcd_header.find_scope(scope_path).is_none() {
+            return Self::new(id_code, width, Vec::new());
+        }
+
+        let mut source_id_codes = Vec::with_capacity(ty_variable.kind.find_width() as usize);
+        // Collect first existing vcd_names: all the ground variables
+        #[allow(deprecated)]
+        for ty_ground_variable in ty_variable.collect_ground_variables() {
+            match ty_ground_variable.kind {
+                TyVarKind::Ground(width) => {
+                    if width < 1 {
+                        continue;
+                    }
+                    // Get the actual path of the variable
+                    if let Some(vcd_name) = ty_ground_variable.get_trace_name() {
+                        let path = &[scope_path, std::slice::from_ref(vcd_name)].concat();
+                        // Find the variable in the original VCD file (if it exists)
+                        if let Some(vcd_var) = vcd_header.find_var(path) {
+                            // Prepend it
+                            source_id_codes.insert(
+                                0,
+                                IdCodeWithShift::create(
+                                    vcd_var.code,
+                                    vcd::Vector::filled(Value::X, width as usize),
+                                    false,
+                                ),
+                            );
+                        }
+                    } else if ty_ground_variable.get_trace_value().is_const() {
+                        source_id_codes.insert(
+                            0,
+                            IdCodeWithShift::create(
+                                id_code,
+                                Self::initialize_vector_value(
+                                    ty_ground_variable.get_trace_value(),
+                                    ty_ground_variable.kind.find_width() as usize,
+                                ),
+                                true,
+                            ),
+                        );
+                    } else if let TraceValue::Operation(..) = ty_ground_variable.get_trace_value() {
+                        // Compute the value from the original variables of the expression
+                        let source = match Expression::create(
+                            ty_ground_variable.get_trace_value(),
+                            scope_path,
+                            vcd_header,
+                        ) {
+                            Some(expression) => {
+                                IdCodeWithShift::computed(id_code, expression, width as usize)
+                            }
+                            // Keep the position of the other fields
+                            None => IdCodeWithShift::create(
+                                id_code,
+                                vcd::Vector::filled(Value::X, width as usize),
+                                true,
+                            ),
+                        };
+                        source_id_codes.insert(0, source);
+                    }
+                }
+                TyVarKind::External => {} // Ignore external variables
+                _ => unreachable!(
+                    "Var \"{}\" Should be unreachable. Kind: {:?}",
+                    ty_ground_variable.name, ty_ground_variable.kind
+                ),
+            }
+        }
+
+        Self::new(id_code, width, source_id_codes)
+    }
+
+    // Initialize the value of a vector from a trace_value
+    fn initialize_vector_value(trace_value: &TraceValue, width: usize) -> vcd::Vector {
+        match trace_value {
+            TraceValue::RefTraceName(_)
+            | TraceValue::RefTraceValues(_)
+            | TraceValue::Operation(..) => vcd::Vector::filled(Value::X, width),
+            TraceValue::Constant(const_value) => {
+                let a = match const_value {
+                    ConstValue::Binary(bv, _width) | ConstValue::FourValue(bv, _width) => {
+                        bv.to_owned()
+                    }
+                    ConstValue::String(_) => return vcd::Vector::filled(Value::X, width),
+                    ConstValue::Real(float_value) => {
+                        let a = format!("{:b}", float_value.to_bits());
+                        a.as_bytes().to_vec()
+                    }
+                };
+
+                // Convert each character of
+                let s = std::str::from_utf8(&a).unwrap();
+                match vcd::Vector::from_str(s) {
+                    Ok(v) => v,
+                    Err(_) => vcd::Vector::filled(vcd::Value::Z, width),
+                }
+            }
+        }
+    }
+
+    /// Return the current value of the variable.
+    /// The concatenation of the values of the source_id_codes shifted to the left.
+    pub fn get_value(&self) -> vcd::Vector {
+        // Calculate the value from its original variables
+        let mut value: Vec<Value> = vec![Value::V0; self.width as usize];
+        let mut start_idx = self.width as usize;
+
+        for id_code_with_shift in &self.source_id_codes {
+            let source_value = id_code_with_shift.get_value();
+            // A value wider than the remaining bits keeps its least significant bits
+            let skip = source_value.len().saturating_sub(start_idx);
+            start_idx = start_idx.saturating_sub(source_value.len());
+            for (i, v) in source_value.iter().skip(skip).enumerate() {
+                value[start_idx + i] = v;
+            }
+        }
+
+        value.into()
+    }
+
+    /// Update the value of the variable when an original variable is updated.
+    /// Return true if the value of the variable changed.
+    ///
+    /// An id code can be shared by many original variables (aliases), so all the fields that
+    /// refer to it are updated.
+    pub fn update_value(&mut self, source_id_code: &IdCode, value: &vcd::Vector) -> Result<bool> {
+        self.update_value_with_policy(source_id_code, value, WidthMismatchPolicy::Abort)
+    }
+
+    /// Update the value of the variable as [VcdRewriteVariable::update_value], handling the
+    /// values with a wrong width according to `policy`.
+    pub fn update_value_with_policy(
+        &mut self,
+        source_id_code: &IdCode,
+        value: &vcd::Vector,
+        policy: WidthMismatchPolicy,
+    ) -> Result<bool> {
+        let mut changed = false;
+        // The constants use the id code of the new variable, not of an original one
+        for id_code_with_shift in self
+            .source_id_codes
+            .iter_mut()
+            .filter(|id_code_with_shift| !id_code_with_shift.is_const)
+        {
+            if id_code_with_shift.id_code == *source_id_code {
+                if value.len() != id_code_with_shift.value.len()
+                    && policy != WidthMismatchPolicy::Abort
+                {
+                    self.width_mismatches
+                        .push((*source_id_code, id_code_with_shift.value.len()));
+                }
+                changed |= id_code_with_shift.update_value_with_policy(value.clone(), policy)?;
+            }
+        }
+        // Recompute the expressions that use the original variable
+        for id_code_with_shift in &mut self.source_id_codes {
+            changed |= id_code_with_shift.update_expression(source_id_code, value);
+        }
+
+        Ok(changed)
+    }
+
+    /// Return true if some values were resized since the last
+    /// [VcdRewriteVariable::take_width_mismatches].
+    #[inline]
+    pub fn has_width_mismatches(&self) -> bool {
+        !self.width_mismatches.is_empty()
+    }
+
+    /// Return the id codes and the expected widths of the values resized since the last call.
+    pub fn take_width_mismatches(&mut self) -> Vec<(IdCode, usize)> {
+        std::mem::take(&mut self.width_mismatches)
+    }
+
+    /// Return the id codes of the original variables this variable depends on (constants excluded).
+    pub fn get_source_id_codes(&self) -> impl Iterator<Item = IdCode> + '_ {
+        self.source_id_codes.iter().flat_map(|id_code_with_shift| {
+            match &id_code_with_shift.expression {
+                Some(expression) => expression.get_id_codes(),
+                None if id_code_with_shift.is_const => Vec::new(),
+                None => vec![id_code_with_shift.id_code],
+            }
+        })
+    }
+
+    /// Return the id code of this variable
+    #[inline]
+    pub fn get_id_code(&self) -> IdCode {
+        self.id_code
+    }
+    #[inline]
+    pub fn get_width(&self) -> u32 {
+        self.width
+    }
+}
+
+/// It represents a variable from the original VCD file that is going to be used in the rewritten VCD file.
+#[derive(Debug)]
+pub struct IdCodeWithShift {
+    /// The id code of the original variable
+    id_code: IdCode,
+    /// The number of bits to shift left the value of the original variable in the new variable
+    // shift_left: u64,
+    /// The "non-shifted" value of the original variable, the one read from the original VCD file
+    value: vcd::Vector,
+    is_const: bool,
+    /// The expression that computes the value from other original variables
+    expression: Option<Expression>,
+}
+
+impl IdCodeWithShift {
+    pub fn create(id_code: IdCode, value: vcd::Vector, is_const: bool) -> Self {
+        Self {
+            id_code,
+            value,
+            is_const,
+            expression: None,
+        }
+    }
+
+    /// Create a value computed by an expression of the original variables. It is not updated
+    /// directly since `id_code` is the one of the new variable.
+    pub fn computed(id_code: IdCode, expression: Expression, width: usize) -> Self {
+        Self {
+            id_code,
+            value: expression.evaluate(width),
+            is_const: true,
+            expression: Some(expression),
+        }
+    }
+
+    pub fn get_value(&self) -> &vcd::Vector {
+        &self.value
+    }
+
+    /// Update the value of the original variable. Return true if the value changed.
+    pub fn update_value(&mut self, value: vcd::Vector) -> Result<bool> {
+        self.update_value_with_policy(value, WidthMismatchPolicy::Abort)
+    }
+
+    /// Update the value of the original variable, handling a value with a wrong width according
+    /// to `policy`. Return true if the value changed.
+    pub fn update_value_with_policy(
+        &mut self,
+        value: vcd::Vector,
+        policy: WidthMismatchPolicy,
+    ) -> Result<bool> {
+        // Do not update
+        if self.is_const {
+            return Ok(false);
+        }
+        let width = self.value.len();
+        let value = match policy {
+            _ if value.len() == width => value,
+            WidthMismatchPolicy::Abort => {
+                return Err(VcdRewriteError::UpdateValueError {
+                    id_code: self.id_code,
+                    len_diff: (value.len(), width),
+                    values_diff: (value.to_string(), self.value.to_string()),
+                });
+            }
+            // The first value is the most significant bit
+            WidthMismatchPolicy::Resize if value.len() > width => value
+                .iter()
+                .skip(value.len() - width)
+                .collect::<Vec<_>>()
+                .into(),
+            WidthMismatchPolicy::Resize => {
+                let fill = match value.iter().next() {
+                    Some(msb @ (Value::X | Value::Z)) => msb,
+                    _ => Value::V0,
+                };
+                std::iter::repeat_n(fill, width - value.len())
+                    .chain(value.iter())
+                    .collect::<Vec<_>>()
+                    .into()
+            }
+            WidthMismatchPolicy::MarkX => vcd::Vector::filled(Value::X, width),
+        };
+        let changed = self.value != value;
+        self.value = value;
+
+        Ok(changed)
+    }
+
+    // Recompute the value if the expression uses the original variable. Return true if the value
+    // changed.
+    fn update_expression(&mut self, source_id_code: &IdCode, value: &vcd::Vector) -> bool {
+        let Some(expression) = &mut self.expression else {
+            return false;
+        };
+        if !expression.update_value(source_id_code, value) {
+            return false;
+        }
+        let value = expression.evaluate(self.value.len());
+        let changed = self.value != value;
+        self.value = value;
+        changed
+    }
+}
@@ -0,0 +1,381 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+use fst_writer::{
+    FstBodyWriter, FstFileType, FstHeaderWriter, FstInfo, FstScopeType, FstSignalId, FstSignalType,
+    FstVarDirection, FstVarType,
+};
+use vcd::{IdCode, ReferenceIndex, SimulationCommand, TimescaleUnit, VarType, Writer};
+
+use super::{Result, VcdRewriteError};
+
+/// The format of the rewritten trace.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OutputFormat {
+    /// Value Change Dump
+    Vcd,
+    /// Fast Signal Trace
+    Fst,
+}
+
+impl OutputFormat {
+    /// Select the format from the extension of a file (`.vcd` or `.fst`).
+    pub fn from_path(path: &Path) -> Option<Self> {
+        match path.extension()?.to_str()? {
+            "vcd" => Some(OutputFormat::Vcd),
+            "fst" => Some(OutputFormat::Fst),
+            _ => None,
+        }
+    }
+}
+
+/// The operations used by the [super::VcdRewriter] to write a trace.
+///
+/// The header is written first (date, version, timescale, scopes and variables) and closed by
+/// [TraceWriter::enddefinitions], then the value changes follow.
+pub trait TraceWriter {
+    /// Write the date of the trace.
+    fn date(&mut self, date: &str) -> Result<()>;
+    /// Write the version of the tool that generated the trace.
+    fn version(&mut self, version: &str) -> Result<()>;
+    /// Write the timescale of the trace.
+    fn timescale(&mut self, ts: u32, unit: TimescaleUnit) -> Result<()>;
+    /// Write a comment in the header.
+    fn comment(&mut self, comment: &str) -> Result<()>;
+    /// Open a module scope.
+    fn add_module(&mut self, name: &str) -> Result<()>;
+    /// Open a struct scope, used for the fields of a struct or the elements of a vector.
+    fn add_struct(&mut self, name: &str) -> Result<()>;
+    /// Close the current scope.
+    fn upscope(&mut self) -> Result<()>;
+    /// Declare a variable in the current scope and return its id code.
+    fn add_var(
+        &mut self,
+        var_type: VarType,
+        width: u32,
+        reference: &str,
+        index: Option<ReferenceIndex>,
+    ) -> Result<IdCode>;
+    /// Close the header.
+    fn enddefinitions(&mut self) -> Result<()>;
+    /// Move to a new timestamp.
+    fn timestamp(&mut self, ts: u64) -> Result<()>;
+    /// Start a snapshot of the values of all the variables (`$dumpvars`).
+    fn begin_dumpvars(&mut self) -> Result<()>;
+    /// Close the snapshot started by [TraceWriter::begin_dumpvars].
+    fn end_dumpvars(&mut self) -> Result<()>;
+    /// Change the value of a variable.
+    fn change_vector(&mut self, id_code: IdCode, value: &vcd::Vector) -> Result<()>;
+    /// Change the value of a real variable.
+    fn change_real(&mut self, id_code: IdCode, value: f64) -> Result<()>;
+    /// Change the value of a string variable.
+    fn change_string(&mut self, id_code: IdCode, value: &str) -> Result<()>;
+    /// Return true if the trace can have string variables.
+    fn has_strings(&self) -> bool {
+        true
+    }
+    /// Complete the trace. No other operation is allowed after this one.
+    fn finish(&mut self) -> Result<()>;
+}
+
+/// Write a VCD trace.
+pub struct VcdTraceWriter<W: Write> {
+    writer: Writer<W>,
+    /// Whether each open scope is a struct, not supported by [Writer]
+    struct_scopes: Vec<bool>,
+}
+
+impl<W: Write> VcdTraceWriter<W> {
+    pub fn new(writer: W) -> Self {
+        Self {
+            writer: Writer::new(writer),
+            struct_scopes: Vec::new(),
+        }
+    }
+}
+
+impl VcdTraceWriter<BufWriter<File>> {
+    /// Create a VCD trace at `path`.
+    pub fn create(path: &Path) -> Result<Self> {
+        Ok(Self::new(BufWriter::new(File::create(path)?)))
+    }
+}
+
+impl<W: Write> TraceWriter for VcdTraceWriter<W> {
+    fn date(&mut self, date: &str) -> Result<()> {
+        Ok(self.writer.date(date)?)
+    }
+    fn version(&mut self, version: &str) -> Result<()> {
+        Ok(self.writer.version(version)?)
+    }
+    fn timescale(&mut self, ts: u32, unit: TimescaleUnit) -> Result<()> {
+        Ok(self.writer.timescale(ts, unit)?)
+    }
+    fn comment(&mut self, comment: &str) -> Result<()> {
+        Ok(self.writer.comment(comment)?)
+    }
+    fn add_module(&mut self, name: &str) -> Result<()> {
+        self.struct_scopes.push(false);
+        Ok(self.writer.add_module(name)?)
+    }
+    fn add_struct(&mut self, name: &str) -> Result<()> {
+        self.struct_scopes.push(true);
+        Ok(writeln!(
+            self.writer.writer(),
+            "$scope struct {} $end",
+            name
+        )?)
+    }
+    fn upscope(&mut self) -> Result<()> {
+        match self.struct_scopes.pop() {
+            Some(true) => Ok(writeln!(self.writer.writer(), "$upscope $end")?),
+            _ => Ok(self.writer.upscope()?),
+        }
+    }
+    fn add_var(
+        &mut self,
+        var_type: VarType,
+        width: u32,
+        reference: &str,
+        index: Option<ReferenceIndex>,
+    ) -> Result<IdCode> {
+        Ok(self.writer.add_var(var_type, width, reference, index)?)
+    }
+    fn enddefinitions(&mut self) -> Result<()> {
+        Ok(self.writer.enddefinitions()?)
+    }
+    fn timestamp(&mut self, ts: u64) -> Result<()> {
+        Ok(self.writer.timestamp(ts)?)
+    }
+    fn begin_dumpvars(&mut self) -> Result<()> {
+        Ok(self.writer.begin(SimulationCommand::Dumpvars)?)
+    }
+    fn end_dumpvars(&mut self) -> Result<()> {
+        Ok(self.writer.end()?)
+    }
+    fn change_vector(&mut self, id_code: IdCode, value: &vcd::Vector) -> Result<()> {
+        Ok(self.writer.change_vector(id_code, value.iter())?)
+    }
+    fn change_real(&mut self, id_code: IdCode, value: f64) -> Result<()> {
+        Ok(self.writer.change_real(id_code, value)?)
+    }
+    fn change_string(&mut self, id_code: IdCode, value: &str) -> Result<()> {
+        Ok(self.writer.change_string(id_code, value)?)
+    }
+    fn finish(&mut self) -> Result<()> {
+        Ok(self.writer.flush()?)
+    }
+}
+
+// The FST file is created only when the first scope or variable is declared, since the date,
+// the version and the timescale are part of its metadata.
+enum FstState {
+    Info(FstInfo),
+    Header(FstHeaderWriter<BufWriter<File>>),
+    Body(FstBodyWriter<BufWriter<File>>),
+    Finished,
+}
+
+/// Write an FST trace.
+///
+/// The variables are identified by the same [IdCode]s that a VCD writer would assign.
+/// Real and string variables are not supported: the FST writer has no encoding for their values.
+/// The timescale must be a power of ten.
+pub struct FstTraceWriter {
+    path: String,
+    state: FstState,
+    /// The FST signal of each declared variable
+    signals: HashMap<IdCode, FstSignalId>,
+    next_id_code: IdCode,
+}
+
+impl FstTraceWriter {
+    /// Create an FST trace at `path`.
+    pub fn create(path: &Path) -> Result<Self> {
+        // Fail early if the file cannot be created
+        File::create(path)?;
+        Ok(Self {
+            path: path.to_string_lossy().to_string(),
+            state: FstState::Info(FstInfo {
+                start_time: 0,
+                timescale_exponent: 0,
+                version: String::new(),
+                date: String::new(),
+                file_type: FstFileType::Verilog,
+            }),
+            signals: HashMap::new(),
+            next_id_code: IdCode::FIRST,
+        })
+    }
+
+    fn info(&mut self) -> Result<&mut FstInfo> {
+        match &mut self.state {
+            FstState::Info(info) => Ok(info),
+            _ => Err(Self::order_error("metadata after the definitions")),
+        }
+    }
+
+    // Open the file if needed and return the header writer.
+    fn header(&mut self) -> Result<&mut FstHeaderWriter<BufWriter<File>>> {
+        if let FstState::Info(info) = &self.state {
+            let header = fst_writer::open_fst(&self.path, info)?;
+            self.state = FstState::Header(header);
+        }
+        match &mut self.state {
+            FstState::Header(header) => Ok(header),
+            _ => Err(Self::order_error("definitions after the header")),
+        }
+    }
+
+    fn body(&mut self) -> Result<&mut FstBodyWriter<BufWriter<File>>> {
+        match &mut self.state {
+            FstState::Body(body) => Ok(body),
+            _ => Err(Self::order_error("value changes outside the body")),
+        }
+    }
+
+    fn order_error(what: &str) -> VcdRewriteError {
+        VcdRewriteError::Other(format!("Cannot write {} of an FST trace", what))
+    }
+
+    fn var_type(var_type: VarType) -> FstVarType {
+        match var_type {
+            VarType::Event => FstVarType::Event,
+            VarType::Integer => FstVarType::Integer,
+            VarType::Parameter => FstVarType::Parameter,
+            VarType::Reg => FstVarType::Reg,
+            VarType::Supply0 => FstVarType::Supply0,
+            VarType::Supply1 => FstVarType::Supply1,
+            VarType::Time => FstVarType::Time,
+            VarType::Tri => FstVarType::Tri,
+            VarType::TriAnd => FstVarType::TriAnd,
+            VarType::TriOr => FstVarType::TriOr,
+            VarType::TriReg => FstVarType::TriReg,
+            VarType::Tri0 => FstVarType::Tri0,
+            VarType::Tri1 => FstVarType::Tri1,
+            VarType::WAnd => FstVarType::Wand,
+            VarType::WOr => FstVarType::Wor,
+            _ => FstVarType::Wire,
+        }
+    }
+}
+
+impl TraceWriter for FstTraceWriter {
+    fn date(&mut self, date: &str) -> Result<()> {
+        self.info()?.date = date.to_string();
+        Ok(())
+    }
+    fn version(&mut self, version: &str) -> Result<()> {
+        self.info()?.version = version.to_string();
+        Ok(())
+    }
+    fn timescale(&mut self, ts: u32, unit: TimescaleUnit) -> Result<()> {
+        let unit_exponent = match unit {
+            TimescaleUnit::S => 0,
+            TimescaleUnit::MS => -3,
+            TimescaleUnit::US => -6,
+            TimescaleUnit::NS => -9,
+            TimescaleUnit::PS => -12,
+            TimescaleUnit::FS => -15,
+        };
+        if ts == 0 || 10u32.pow(ts.ilog10()) != ts {
+            return Err(VcdRewriteError::Other(format!(
+                "The timescale {} {} of an FST trace is not a power of ten",
+                ts, unit
+            )));
+        }
+        self.info()?.timescale_exponent = unit_exponent + ts.ilog10() as i8;
+        Ok(())
+    }
+    // FST has no comments
+    fn comment(&mut self, _comment: &str) -> Result<()> {
+        Ok(())
+    }
+    fn add_module(&mut self, name: &str) -> Result<()> {
+        Ok(self.header()?.scope(name, "", FstScopeType::Module)?)
+    }
+    fn add_struct(&mut self, name: &str) -> Result<()> {
+        Ok(self.header()?.scope(name, "", FstScopeType::Struct)?)
+    }
+    fn upscope(&mut self) -> Result<()> {
+        Ok(self.header()?.up_scope()?)
+    }
+    fn add_var(
+        &mut self,
+        var_type: VarType,
+        width: u32,
+        reference: &str,
+        index: Option<ReferenceIndex>,
+    ) -> Result<IdCode> {
+        let name = match index {
+            Some(index) => format!("{} {}", reference, index),
+            None => reference.to_string(),
+        };
+        if matches!(var_type, VarType::Real | VarType::String) {
+            return Err(VcdRewriteError::Other(format!(
+                "The {} variable {} cannot be written to an FST trace",
+                var_type, reference
+            )));
+        }
+        let signal_id = self.header()?.var(
+            name,
+            FstSignalType::bit_vec(width),
+            Self::var_type(var_type),
+            FstVarDirection::Implicit,
+            None,
+        )?;
+
+        let id_code = self.next_id_code;
+        self.next_id_code = id_code.next();
+        self.signals.insert(id_code, signal_id);
+        Ok(id_code)
+    }
+    fn enddefinitions(&mut self) -> Result<()> {
+        self.header()?;
+        match std::mem::replace(&mut self.state, FstState::Finished) {
+            FstState::Header(header) => self.state = FstState::Body(header.finish()?),
+            _ => unreachable!("the header has just been opened"),
+        }
+        Ok(())
+    }
+    fn timestamp(&mut self, ts: u64) -> Result<()> {
+        Ok(self.body()?.time_change(ts)?)
+    }
+    // FST has no snapshots: the values are regular changes
+    fn begin_dumpvars(&mut self) -> Result<()> {
+        Ok(())
+    }
+    fn end_dumpvars(&mut self) -> Result<()> {
+        Ok(())
+    }
+    fn change_vector(&mut self, id_code: IdCode, value: &vcd::Vector) -> Result<()> {
+        let signal_id = *self.signals.get(&id_code).ok_or_else(|| {
+            VcdRewriteError::Other(format!("Unknown id code {} in the FST trace", id_code))
+        })?;
+        let value: Vec<u8> = value.iter().map(|v| v.to_string().as_bytes()[0]).collect();
+        Ok(self.body()?.signal_change(signal_id, &value)?)
+    }
+    fn change_real(&mut self, id_code: IdCode, _value: f64) -> Result<()> {
+        Err(VcdRewriteError::Other(format!(
+            "The real variable {} cannot be written to an FST trace",
+            id_code
+        )))
+    }
+    fn change_string(&mut self, id_code: IdCode, _value: &str) -> Result<()> {
+        Err(VcdRewriteError::Other(format!(
+            "The string variable {} cannot be written to an FST trace",
+            id_code
+        )))
+    }
+    fn has_strings(&self) -> bool {
+        false
+    }
+    fn finish(&mut self) -> Result<()> {
+        match std::mem::replace(&mut self.state, FstState::Finished) {
+            FstState::Body(body) => Ok(body.finish()?),
+            _ => Err(Self::order_error("the end before the definitions")),
+        }
+    }
+}
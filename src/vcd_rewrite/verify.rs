@@ -0,0 +1,458 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::io::{self, BufRead};
+use std::path::Path;
+
+use vcd::{Command, Header, IdCode, VarType};
+
+use super::filter::PathFilter;
+use super::{variant_name, Result, TyScope, TyVarKind, TyVariable};
+use crate::tyvcd::trace_pointer::{TraceGetter, TraceValue};
+use crate::waveform::db::RawValue;
+use crate::waveform::source::{self, WaveformSource};
+
+/// A value of the rewritten trace that does not match the original signal.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RewriteMismatch {
+    /// The path of the ground variable (i.e. `Top.io.bits.data`)
+    pub path: String,
+    /// The time of the mismatch
+    pub time: u64,
+    /// The value of the original signal
+    pub expected: String,
+    /// The value in the rewritten trace
+    pub actual: String,
+}
+
+/// The result of the verification of a rewritten trace.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RewriteReport {
+    /// The values that do not match, in order of variable and time
+    pub mismatches: Vec<RewriteMismatch>,
+    /// The typed variables with an original signal that are not in the rewritten trace
+    pub missing: Vec<String>,
+}
+
+impl RewriteReport {
+    /// Return true if the rewritten trace matches the original one.
+    pub fn is_empty(&self) -> bool {
+        self.mismatches.is_empty() && self.missing.is_empty()
+    }
+}
+
+impl fmt::Display for RewriteReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for mismatch in &self.mismatches {
+            writeln!(
+                f,
+                "{}: at time {} expected {}, found {}",
+                mismatch.path, mismatch.time, mismatch.expected, mismatch.actual
+            )?;
+        }
+        for path in &self.missing {
+            writeln!(f, "{}: missing in the rewritten trace", path)?;
+        }
+        Ok(())
+    }
+}
+
+// A ground variable with a signal in the original trace and its bits in the rewritten trace
+struct Leaf {
+    path: String,
+    original: IdCode,
+    rewritten: IdCode,
+    // The position of the bits (MSB first) in the rewritten variable and its width
+    offset: usize,
+    width: usize,
+    rewritten_width: usize,
+    // The rewritten variable stores the bits of the leaf
+    is_bits: bool,
+    // The enum variable of a leaf written with its variant names
+    enum_variable: Option<TyVariable>,
+}
+
+impl Leaf {
+    // Return the expected and the actual values of the leaf.
+    fn values(
+        &self,
+        original: Option<&RawValue>,
+        rewritten: Option<&RawValue>,
+    ) -> (String, String) {
+        match (self.is_bits, &self.enum_variable) {
+            (true, _) => (
+                bits_of(original, self.width),
+                bits_of(rewritten, self.rewritten_width)
+                    .get(self.offset..self.offset + self.width)
+                    .unwrap_or_default()
+                    .to_string(),
+            ),
+            (false, Some(variable)) => (
+                original
+                    .map(|value| variant_name(variable, &value.to_bits(self.width)))
+                    .unwrap_or_default(),
+                text_of(rewritten),
+            ),
+            (false, None) => (text_of(original), text_of(rewritten)),
+        }
+    }
+}
+
+/// Verify a rewritten trace against its original trace.
+///
+/// For every ground variable of the typed scopes that refers to a signal of the original trace,
+/// the bits of the variable in the rewritten trace are compared with the original signal at each
+/// time either of them changes. The enums written with their variant names are compared with the
+/// names of the original values. Constants and computed values are not checked.
+/// The times of the two traces must be the same (no timescale, offset or clock cycles conversion).
+///
+/// The two traces are read together in order of time: only the current value of each signal is
+/// kept.
+pub struct RewriteCheck<'a> {
+    /// The typed scopes used to rewrite the trace
+    scopes: &'a [TyScope],
+    /// The first time to check
+    start_time: Option<u64>,
+    /// The last time to check
+    end_time: Option<u64>,
+    /// Check only the selected variables
+    path_filter: Option<PathFilter>,
+}
+
+impl<'a> RewriteCheck<'a> {
+    pub fn new(scopes: &'a [TyScope]) -> Self {
+        Self {
+            scopes,
+            start_time: None,
+            end_time: None,
+            path_filter: None,
+        }
+    }
+
+    /// Check only the time window `[start, end]`, as written by
+    /// [super::VcdRewriter::with_time_window].
+    pub fn with_time_window(mut self, start: Option<u64>, end: Option<u64>) -> Self {
+        self.start_time = start;
+        self.end_time = end;
+        self
+    }
+
+    /// Check only the variables selected by a [PathFilter].
+    pub fn with_path_filter(mut self, path_filter: Option<PathFilter>) -> Self {
+        self.path_filter = path_filter;
+        self
+    }
+
+    /// Verify the rewritten trace file (VCD or FST) against the original trace file.
+    pub fn check_files(&self, original: &Path, rewritten: &Path) -> Result<RewriteReport> {
+        self.check_sources(source::open(original)?, source::open(rewritten)?)
+    }
+
+    /// Verify a rewritten VCD trace against the original VCD trace (i.e. in-memory buffers).
+    pub fn check<R: BufRead, W: BufRead>(
+        &self,
+        original: R,
+        rewritten: W,
+    ) -> Result<RewriteReport> {
+        self.check_sources(source::vcd_parser(original), source::vcd_parser(rewritten))
+    }
+
+    /// Verify a rewritten trace against the original trace read from any source.
+    pub fn check_sources<O: WaveformSource, W: WaveformSource>(
+        &self,
+        mut original: O,
+        mut rewritten: W,
+    ) -> Result<RewriteReport> {
+        let original_header = original.parse_header()?;
+        let rewritten_header = rewritten.parse_header()?;
+
+        let mut report = RewriteReport::default();
+        let mut leaves = Vec::new();
+        for scope in self.scopes {
+            self.collect_scope_leaves(
+                scope,
+                &[],
+                &original_header,
+                &rewritten_header,
+                &mut leaves,
+                &mut report.missing,
+            );
+        }
+
+        let start = self.start_time.unwrap_or(0);
+        let end = self.end_time.unwrap_or(u64::MAX);
+        let mut original = TraceCursor::new(original);
+        let mut rewritten = TraceCursor::new(rewritten);
+        // The mismatches with the index of their leaf
+        let mut mismatches = Vec::new();
+        let mut check = |time: u64,
+                         original: &TraceCursor<O>,
+                         rewritten: &TraceCursor<W>,
+                         is_changed: &dyn Fn(&Leaf) -> bool| {
+            for (idx, leaf) in leaves
+                .iter()
+                .enumerate()
+                .filter(|(_, leaf)| is_changed(leaf))
+            {
+                let (expected, actual) = leaf.values(
+                    original.values.get(&leaf.original),
+                    rewritten.values.get(&leaf.rewritten),
+                );
+                if expected != actual {
+                    mismatches.push((
+                        idx,
+                        RewriteMismatch {
+                            path: leaf.path.clone(),
+                            time,
+                            expected,
+                            actual,
+                        },
+                    ));
+                }
+            }
+        };
+
+        // All the leaves are checked at the start time, then only the changed ones
+        let mut is_start_checked = false;
+        while let Some(time) = original
+            .next_time
+            .into_iter()
+            .chain(rewritten.next_time)
+            .min()
+        {
+            if time > end {
+                break;
+            }
+            if time > start && !is_start_checked {
+                check(start, &original, &rewritten, &|_| true);
+                is_start_checked = true;
+            }
+            let original_changes = original.advance(time)?;
+            let rewritten_changes = rewritten.advance(time)?;
+            if time == start {
+                check(start, &original, &rewritten, &|_| true);
+                is_start_checked = true;
+            } else if time > start {
+                check(time, &original, &rewritten, &|leaf| {
+                    original_changes.contains(&leaf.original)
+                        || rewritten_changes.contains(&leaf.rewritten)
+                });
+            }
+        }
+        if !is_start_checked {
+            check(start, &original, &rewritten, &|_| true);
+        }
+
+        // In order of variable and time
+        mismatches.sort_by_key(|(idx, _)| *idx);
+        report.mismatches = mismatches.into_iter().map(|(_, m)| m).collect();
+        Ok(report)
+    }
+
+    // Collect the leaves of the variables of a scope and of its children.
+    fn collect_scope_leaves(
+        &self,
+        scope: &TyScope,
+        path_scope: &[String],
+        original: &Header,
+        rewritten: &Header,
+        leaves: &mut Vec<Leaf>,
+        missing: &mut Vec<String>,
+    ) {
+        let Some(scope_name) = scope.get_trace_name() else {
+            return;
+        };
+        let child_path_scope = &[path_scope, std::slice::from_ref(scope_name)].concat();
+        for variable in &scope.variables {
+            let name = variable.get_trace_name().unwrap_or(&variable.name);
+            let path = [child_path_scope.as_slice(), std::slice::from_ref(name)].concat();
+            if self
+                .path_filter
+                .as_ref()
+                .is_some_and(|filter| !filter.is_selected(&path))
+            {
+                continue;
+            }
+            let collector = LeafCollector {
+                scope_path: child_path_scope,
+                original,
+                rewritten,
+            };
+            collector.collect_variable(variable, path, leaves, missing);
+        }
+        for child_scope in scope.subscopes.values() {
+            self.collect_scope_leaves(
+                &child_scope.read().unwrap(),
+                child_path_scope,
+                original,
+                rewritten,
+                leaves,
+                missing,
+            );
+        }
+    }
+}
+
+// Find the bits of the ground variables of a scope in the rewritten trace.
+struct LeafCollector<'h> {
+    scope_path: &'h [String],
+    original: &'h Header,
+    rewritten: &'h Header,
+}
+
+impl LeafCollector<'_> {
+    // Collect the leaves of a variable written at `path` (flattened or as a hierarchy).
+    fn collect_variable(
+        &self,
+        variable: &TyVariable,
+        path: Vec<String>,
+        leaves: &mut Vec<Leaf>,
+        missing: &mut Vec<String>,
+    ) {
+        if let Some(var) = self.rewritten.find_var(&path) {
+            let is_bits = !matches!(var.var_type, VarType::Real | VarType::String);
+            let rewritten = (var.code, var.size as usize, is_bits);
+            self.collect_fields(variable, &path, rewritten, &mut 0, leaves);
+            return;
+        }
+        match &variable.kind {
+            // Each field is a variable in a struct scope
+            TyVarKind::Struct { fields } | TyVarKind::Vector { fields } => {
+                let is_vector = matches!(variable.kind, TyVarKind::Vector { .. });
+                for (idx, field) in fields.iter().enumerate() {
+                    let field_name = match is_vector {
+                        true => format!("[{}]", idx),
+                        false => field.name.clone(),
+                    };
+                    let field_path = [path.as_slice(), &[field_name]].concat();
+                    self.collect_variable(field, field_path, leaves, missing);
+                }
+            }
+            _ => {
+                if self.original_signal(variable).is_some() {
+                    missing.push(path.join("."));
+                }
+            }
+        }
+    }
+
+    // Collect the ground fields of a variable, the first one is the most significant.
+    fn collect_fields(
+        &self,
+        variable: &TyVariable,
+        path: &[String],
+        rewritten: (IdCode, usize, bool),
+        offset: &mut usize,
+        leaves: &mut Vec<Leaf>,
+    ) {
+        let width = variable.kind.find_width() as usize;
+        match &variable.kind {
+            TyVarKind::Struct { fields } | TyVarKind::Vector { fields } => {
+                let is_vector = matches!(variable.kind, TyVarKind::Vector { .. });
+                for (idx, field) in fields.iter().enumerate() {
+                    let field_name = match is_vector {
+                        true => format!("[{}]", idx),
+                        false => field.name.clone(),
+                    };
+                    let field_path = [path, &[field_name]].concat();
+                    self.collect_fields(field, &field_path, rewritten, offset, leaves);
+                }
+            }
+            _ => {
+                let (rewritten, rewritten_width, is_bits) = rewritten;
+                if let Some(original) = self.original_signal(variable) {
+                    // The variant names are compared with the names of the original values
+                    let enum_variable =
+                        (!is_bits && variable.enum_val_map.is_some()).then(|| variable.clone());
+                    leaves.push(Leaf {
+                        path: path.join("."),
+                        original,
+                        rewritten,
+                        offset: *offset,
+                        width,
+                        rewritten_width,
+                        is_bits,
+                        enum_variable,
+                    });
+                }
+                *offset += width;
+            }
+        }
+    }
+
+    // Return the id code of the original signal of a ground variable.
+    fn original_signal(&self, variable: &TyVariable) -> Option<IdCode> {
+        match (variable.get_trace_value(), &variable.kind) {
+            (TraceValue::RefTraceName(trace_name), TyVarKind::Ground(width)) if *width > 0 => {
+                let path = [self.scope_path, std::slice::from_ref(trace_name)].concat();
+                self.original.find_var(&path).map(|var| var.code)
+            }
+            _ => None,
+        }
+    }
+}
+
+// The bits of a value (MSB first), `x` if it has no value yet.
+fn bits_of(value: Option<&RawValue>, width: usize) -> String {
+    match value {
+        Some(value) => value.to_bits(width),
+        None => "x".repeat(width),
+    }
+}
+
+// The text of a real or string value.
+fn text_of(value: Option<&RawValue>) -> String {
+    match value {
+        Some(RawValue::Bits(bits)) => bits.clone(),
+        Some(RawValue::Real(real)) => real.to_string(),
+        Some(RawValue::String(string)) => string.clone(),
+        None => String::new(),
+    }
+}
+
+// A trace read one timestamp at a time, with the current value of each signal.
+struct TraceCursor<S: WaveformSource> {
+    source: S,
+    // The time of the next changes, None at the end of the trace
+    next_time: Option<u64>,
+    values: HashMap<IdCode, RawValue>,
+}
+
+impl<S: WaveformSource> TraceCursor<S> {
+    fn new(source: S) -> Self {
+        Self {
+            source,
+            // The changes before the first timestamp are at time 0
+            next_time: Some(0),
+            values: HashMap::new(),
+        }
+    }
+
+    // Apply the changes at `time`, if it is the next time of the trace, and return their signals.
+    fn advance(&mut self, time: u64) -> io::Result<HashSet<IdCode>> {
+        let mut changed = HashSet::new();
+        if self.next_time != Some(time) {
+            return Ok(changed);
+        }
+        self.next_time = None;
+        for command in self.source.by_ref() {
+            let (id_code, value) = match command? {
+                // Repeated timestamps are merged
+                Command::Timestamp(ts) if ts <= time => continue,
+                Command::Timestamp(ts) => {
+                    self.next_time = Some(ts);
+                    break;
+                }
+                Command::ChangeScalar(id_code, value) => {
+                    (id_code, RawValue::Bits(value.to_string()))
+                }
+                Command::ChangeVector(id_code, value) => (id_code, RawValue::from(&value)),
+                Command::ChangeReal(id_code, value) => (id_code, RawValue::Real(value)),
+                Command::ChangeString(id_code, value) => (id_code, RawValue::String(value)),
+                _ => continue, // ignore the other commands
+            };
+            changed.insert(id_code);
+            self.values.insert(id_code, value);
+        }
+        Ok(changed)
+    }
+}
@@ -0,0 +1,96 @@
+use std::collections::HashMap;
+use vcd::IdCode;
+
+use crate::tyvcd::value::DecodedValue;
+
+use super::db::{RawValue, SignalChanges, TypedSignal, WaveformDb};
+
+/// Iterator over the changes of a typed variable: it yields `(time, value)` pairs.
+///
+/// The changes of all the signals referenced by the variable are merged in time order,
+/// and a pair is emitted only when the decoded value of the whole variable changes.
+/// Variables that do not depend on any signal (i.e. constants) emit their value once at time 0.
+pub struct TypedChanges<'a> {
+    /// The variable whose changes are returned
+    signal: TypedSignal,
+    /// The changes of each signal with the position of the next change to read
+    cursors: Vec<(IdCode, &'a SignalChanges, usize)>,
+    /// The current raw value of each signal
+    current: HashMap<IdCode, &'a RawValue>,
+    /// The last emitted value
+    last_value: Option<DecodedValue>,
+}
+
+impl<'a> TypedChanges<'a> {
+    pub fn new(db: &'a WaveformDb, signal: TypedSignal) -> Self {
+        let mut cursors: Vec<(IdCode, &SignalChanges, usize)> = Vec::new();
+        for id_code in signal.id_codes() {
+            // The same signal can be referenced multiple times (aliases)
+            if cursors.iter().any(|(id, _, _)| *id == id_code) {
+                continue;
+            }
+            if let Some(changes) = db.signal_changes(id_code) {
+                cursors.push((id_code, changes, 0));
+            }
+        }
+
+        Self {
+            signal,
+            cursors,
+            current: HashMap::new(),
+            last_value: None,
+        }
+    }
+
+    /// Return the variable whose changes are returned.
+    pub fn get_signal(&self) -> &TypedSignal {
+        &self.signal
+    }
+
+    // Apply the next changes of all the signals and return their time.
+    fn advance(&mut self) -> Option<u64> {
+        let time = self
+            .cursors
+            .iter()
+            .filter_map(|(_, changes, idx)| changes.get(*idx).map(|(time, _)| time))
+            .min()?;
+
+        for (id_code, changes, idx) in &mut self.cursors {
+            if let Some((change_time, value)) = changes.get(*idx) {
+                if change_time == time {
+                    self.current.insert(*id_code, value);
+                    *idx += 1;
+                }
+            }
+        }
+        Some(time)
+    }
+}
+
+impl Iterator for TypedChanges<'_> {
+    type Item = (u64, DecodedValue);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        // Constants: emit only the initial value
+        if self.cursors.is_empty() {
+            if self.last_value.is_some() {
+                return None;
+            }
+            let value = self.signal.decode_with(|_| None);
+            self.last_value = Some(value.clone());
+            return Some((0, value));
+        }
+
+        loop {
+            let time = self.advance()?;
+            let value = self
+                .signal
+                .decode_with(|id_code| self.current.get(&id_code).copied());
+            // Emit only when the value changes
+            if self.last_value.as_ref() != Some(&value) {
+                self.last_value = Some(value.clone());
+                return Some((time, value));
+            }
+        }
+    }
+}
@@ -0,0 +1,258 @@
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::tyvcd::spec::VariableKind;
+use crate::tyvcd::value::DecodedValue;
+
+use super::db::{TypedSignal, WaveformDb, WaveformError};
+
+type Result<T> = std::result::Result<T, WaveformError>;
+
+/// The coverage counters of a group of ground leaves.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize)]
+pub struct CoverageSummary {
+    /// The number of toggles to cover: two (0→1 and 1→0) for each bit
+    pub toggles: u64,
+    /// The number of toggles observed
+    pub toggles_covered: u64,
+    /// The number of enum values to cover
+    pub enum_values: u64,
+    /// The number of enum values observed
+    pub enum_values_covered: u64,
+}
+
+impl CoverageSummary {
+    /// Add the counters of another summary.
+    pub fn add(&mut self, other: &CoverageSummary) {
+        self.toggles += other.toggles;
+        self.toggles_covered += other.toggles_covered;
+        self.enum_values += other.enum_values;
+        self.enum_values_covered += other.enum_values_covered;
+    }
+
+    /// Return the fraction of the toggles observed (1 if there is nothing to cover).
+    pub fn toggle_ratio(&self) -> f64 {
+        ratio(self.toggles_covered, self.toggles)
+    }
+
+    /// Return the fraction of the enum values observed (1 if there is nothing to cover).
+    pub fn enum_ratio(&self) -> f64 {
+        ratio(self.enum_values_covered, self.enum_values)
+    }
+}
+
+fn ratio(covered: u64, total: u64) -> f64 {
+    if total == 0 {
+        1.0
+    } else {
+        covered as f64 / total as f64
+    }
+}
+
+/// The toggles observed for a bit.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize)]
+pub struct BitToggle {
+    /// The bit changed from 0 to 1
+    pub rise: bool,
+    /// The bit changed from 1 to 0
+    pub fall: bool,
+}
+
+/// Whether an entry of the `enum_val_map` of a variable has been observed.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct EnumValueCoverage {
+    /// The encoding of the enum value
+    pub value: i64,
+    /// The name of the enum value
+    pub name: String,
+    /// The value has been observed at least once
+    pub observed: bool,
+}
+
+/// The coverage of a ground leaf.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct LeafCoverage {
+    /// The typed path of the leaf
+    pub path: String,
+    /// The type name of the leaf
+    pub type_name: String,
+    /// The toggles of each bit: the element `i` is the bit `i` (the least significant bit first)
+    pub bits: Vec<BitToggle>,
+    /// The coverage of the enum values, sorted by encoding (empty if not an enum)
+    pub enum_values: Vec<EnumValueCoverage>,
+    /// The counters of this leaf
+    pub summary: CoverageSummary,
+}
+
+/// The coverage of a group of leaves: a struct (or vector) variable, a scope or a module definition.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct GroupCoverage {
+    /// The typed path of the variable or scope, the name of the module definition
+    pub name: String,
+    /// The type name of the variable, the definition name of the scope, empty for a module
+    pub type_name: String,
+    /// The counters of all the leaves in the group
+    pub summary: CoverageSummary,
+}
+
+/// The toggle and enum value coverage of a waveform.
+///
+/// All the lists are sorted by path (or name) to keep the report stable across runs.
+/// Leaves not linked to any signal in the trace (i.e. constants) are not covered.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct CoverageReport {
+    /// The coverage of each ground leaf
+    pub leaves: Vec<LeafCoverage>,
+    /// The coverage of each struct and vector variable (including the nested ones)
+    pub structs: Vec<GroupCoverage>,
+    /// The coverage of each scope, it does not include the subscopes
+    pub scopes: Vec<GroupCoverage>,
+    /// The coverage of each module definition, summed over all its instances
+    pub modules: Vec<GroupCoverage>,
+    /// The coverage of the whole waveform
+    pub total: CoverageSummary,
+}
+
+impl CoverageReport {
+    /// Compute the coverage of all the typed variables in a waveform.
+    pub fn compute(db: &WaveformDb) -> Result<Self> {
+        let mut report = CoverageReport::default();
+        let mut modules: BTreeMap<String, CoverageSummary> = BTreeMap::new();
+
+        for scope_path in db.scope_paths() {
+            let definition = db.scope_definition(&scope_path)?;
+            let mut scope_summary = CoverageSummary::default();
+            for signal in db.signals_in_scope(&scope_path)? {
+                let summary = report.cover_variable(db, &signal);
+                scope_summary.add(&summary);
+            }
+
+            modules
+                .entry(definition.clone())
+                .or_default()
+                .add(&scope_summary);
+            report.total.add(&scope_summary);
+            report.scopes.push(GroupCoverage {
+                name: scope_path,
+                type_name: definition,
+                summary: scope_summary,
+            });
+        }
+
+        report.modules = modules
+            .into_iter()
+            .map(|(name, summary)| GroupCoverage {
+                name,
+                type_name: String::new(),
+                summary,
+            })
+            .collect();
+        report.leaves.sort_by(|a, b| a.path.cmp(&b.path));
+        report.structs.sort_by(|a, b| a.name.cmp(&b.name));
+        report.scopes.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(report)
+    }
+
+    /// Return the report in JSON format.
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).unwrap()
+    }
+
+    // Cover a linked variable and its fields, and return its counters.
+    fn cover_variable(&mut self, db: &WaveformDb, signal: &TypedSignal) -> CoverageSummary {
+        let variable = &signal.variable;
+        match &variable.kind {
+            VariableKind::Struct { fields } | VariableKind::Vector { fields } => {
+                let mut summary = CoverageSummary::default();
+                // The fields of a packed struct or vector are slices of the signal of the variable
+                for field_signal in (0..fields.len()).filter_map(|idx| signal.field(idx)) {
+                    summary.add(&self.cover_variable(db, &field_signal));
+                }
+                self.structs.push(GroupCoverage {
+                    name: signal.path.clone(),
+                    type_name: variable.high_level_info.type_name.clone(),
+                    summary,
+                });
+                summary
+            }
+            VariableKind::Ground(_) => match Self::cover_leaf(db, signal) {
+                Some(leaf) => {
+                    let summary = leaf.summary;
+                    self.leaves.push(leaf);
+                    summary
+                }
+                None => CoverageSummary::default(),
+            },
+            VariableKind::External => CoverageSummary::default(),
+        }
+    }
+
+    // Replay the changes of a ground leaf and compute its coverage.
+    fn cover_leaf(db: &WaveformDb, signal: &TypedSignal) -> Option<LeafCoverage> {
+        // Only the leaves linked to a signal can toggle
+        signal.id_codes().next()?;
+        let variable = &signal.variable;
+        let width = variable.kind.find_width() as usize;
+
+        let mut bits = vec![BitToggle::default(); width];
+        let mut observed = BTreeSet::new();
+        let mut last_bits: Option<String> = None;
+        for (time, value) in db.changes(signal) {
+            if let DecodedValue::Enum(name) = &value {
+                observed.insert(name.clone());
+            }
+            let new_bits = db.signal_bits_at(signal, time);
+            if let Some(last_bits) = &last_bits {
+                // The strings are MSB first: the bit `i` is at position `width - 1 - i`
+                for (i, (old, new)) in last_bits
+                    .chars()
+                    .rev()
+                    .zip(new_bits.chars().rev())
+                    .enumerate()
+                {
+                    match (old, new) {
+                        ('0', '1') => bits[i].rise = true,
+                        ('1', '0') => bits[i].fall = true,
+                        _ => {}
+                    }
+                }
+            }
+            last_bits = Some(new_bits);
+        }
+
+        let mut enum_values: Vec<EnumValueCoverage> = variable
+            .enum_val_map
+            .as_ref()
+            .map(|enum_val_map| {
+                enum_val_map
+                    .read()
+                    .unwrap()
+                    .iter()
+                    .map(|(value, name)| EnumValueCoverage {
+                        value: *value,
+                        name: name.clone(),
+                        observed: observed.contains(name),
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+        enum_values.sort_by_key(|enum_value| enum_value.value);
+
+        let summary = CoverageSummary {
+            toggles: 2 * width as u64,
+            toggles_covered: bits
+                .iter()
+                .map(|bit| bit.rise as u64 + bit.fall as u64)
+                .sum(),
+            enum_values: enum_values.len() as u64,
+            enum_values_covered: enum_values.iter().filter(|e| e.observed).count() as u64,
+        };
+        Some(LeafCoverage {
+            path: signal.path.clone(),
+            type_name: variable.high_level_info.type_name.clone(),
+            bits,
+            enum_values,
+            summary,
+        })
+    }
+}
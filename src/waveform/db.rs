@@ -0,0 +1,517 @@
+use std::collections::HashMap;
+use std::io::BufRead;
+use std::path::Path;
+use vcd::{Command, Header, IdCode};
+
+use crate::tyvcd::spec::{TyVcd, Variable, VariableKind};
+use crate::tyvcd::trace_pointer::TraceGetter;
+use crate::tyvcd::value::{self, DecodedValue};
+
+use super::changes::TypedChanges;
+use super::hierarchy::TypedHierarchy;
+use super::source::{self, WaveformSource};
+
+type Result<T> = std::result::Result<T, WaveformError>;
+
+#[derive(Debug)]
+pub enum WaveformError {
+    /// An IO error occurred while reading the trace
+    IoError(std::io::Error),
+    /// The typed path does not exist in the TyVcd
+    PathNotFound(String),
+    /// The variable cannot be used for the requested operation (i.e. a clock wider than 1 bit)
+    InvalidSignal(String),
+}
+
+impl From<std::io::Error> for WaveformError {
+    fn from(err: std::io::Error) -> Self {
+        WaveformError::IoError(err)
+    }
+}
+
+/// The raw value of a signal in a trace.
+#[derive(Debug, Clone, PartialEq)]
+pub enum RawValue {
+    /// A binary value (MSB first), it may contain `x` and `z`
+    Bits(String),
+    /// A real value
+    Real(f64),
+    /// A string value
+    String(String),
+}
+
+impl RawValue {
+    /// Return the binary representation (MSB first) of the value in exactly `width` bits.
+    pub fn to_bits(&self, width: usize) -> String {
+        let bits = match self {
+            RawValue::Bits(bits) => value::extend_bits(bits, width),
+            RawValue::Real(real) => value::extend_bits(&format!("{:064b}", real.to_bits()), width),
+            RawValue::String(_) => "x".repeat(width),
+        };
+        // Keep only the least significant bits
+        bits[bits.len().saturating_sub(width)..].to_string()
+    }
+}
+
+impl From<&vcd::Vector> for RawValue {
+    fn from(vector: &vcd::Vector) -> Self {
+        RawValue::Bits(vector.to_string())
+    }
+}
+
+/// The value changes of a signal ordered by time.
+#[derive(Debug, Default, Clone)]
+pub struct SignalChanges {
+    times: Vec<u64>,
+    values: Vec<RawValue>,
+}
+
+impl SignalChanges {
+    /// Record a new change. Changes at the same time replace the previous one.
+    fn push(&mut self, time: u64, value: RawValue) {
+        if self.times.last() == Some(&time) {
+            *self.values.last_mut().unwrap() = value;
+        } else {
+            self.times.push(time);
+            self.values.push(value);
+        }
+    }
+
+    /// Return the value of the signal at `time`: the value of the last change before or at `time`.
+    pub fn value_at(&self, time: u64) -> Option<&RawValue> {
+        let idx = self.times.partition_point(|t| *t <= time);
+        idx.checked_sub(1).map(|idx| &self.values[idx])
+    }
+
+    /// Return the times of the changes in the range `(start, end]`.
+    pub fn change_times(&self, start: u64, end: u64) -> &[u64] {
+        let first = self.times.partition_point(|t| *t <= start);
+        let last = self.times.partition_point(|t| *t <= end);
+        &self.times[first..last.max(first)]
+    }
+
+    /// Return the change at position `idx` in time order.
+    pub fn get(&self, idx: usize) -> Option<(u64, &RawValue)> {
+        Some((*self.times.get(idx)?, &self.values[idx]))
+    }
+
+    /// Iterate over all the changes in time order.
+    pub fn iter(&self) -> impl Iterator<Item = (u64, &RawValue)> {
+        self.times.iter().copied().zip(self.values.iter())
+    }
+
+    pub fn len(&self) -> usize {
+        self.times.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.times.is_empty()
+    }
+}
+
+/// Split a typed path into its elements.
+///
+/// Elements are separated by `.` and vector indexes can be written as `[i]`:
+/// `Top.dut.io.bits.data[3]` becomes `["Top", "dut", "io", "bits", "data", "3"]`.
+pub fn split_path(path: &str) -> Vec<String> {
+    path.split('.')
+        .flat_map(|elem| elem.split(['[', ']']))
+        .filter(|elem| !elem.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+/// Where the bits of a part of a typed variable come from.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum LeafSource {
+    /// A signal in the trace
+    Signal(IdCode),
+    /// A value that is not stored in the trace: constants (they are resolved while decoding),
+    /// computed values or signals missing from the trace
+    Unknown,
+}
+
+/// A slice of the bits of a typed variable.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct SignalLeaf {
+    pub(crate) width: usize,
+    pub(crate) source: LeafSource,
+    /// The position (MSB first) of the slice in the bits of the source signal
+    pub(crate) offset: usize,
+    /// The width of the source signal
+    pub(crate) signal_width: usize,
+}
+
+impl SignalLeaf {
+    fn new(width: usize, source: LeafSource) -> Self {
+        Self {
+            width,
+            source,
+            offset: 0,
+            signal_width: width,
+        }
+    }
+
+    // Return true if the leaf holds all the bits of its source signal.
+    fn is_whole(&self) -> bool {
+        self.offset == 0 && self.width == self.signal_width
+    }
+}
+
+/// A typed variable linked to the signals of a trace.
+///
+/// Its raw value is the ***ordered*** concatenation of its leaves, as expected by [Variable::decode_value].
+#[derive(Debug, Clone)]
+pub struct TypedSignal {
+    /// The typed path used to find the variable
+    pub path: String,
+    /// The path of the scope (trace names) that contains the variable
+    pub scope_path: Vec<String>,
+    /// The typed variable
+    pub variable: Variable,
+    leaves: Vec<SignalLeaf>,
+}
+
+impl TypedSignal {
+    /// Link a typed variable declared in `scope_path` to the signals of a trace.
+    pub fn create(
+        path: String,
+        scope_path: Vec<String>,
+        variable: Variable,
+        header: &Header,
+    ) -> Self {
+        let mut leaves = Vec::new();
+        Self::collect_leaves(&variable, &scope_path, header, &mut leaves);
+        Self {
+            path,
+            scope_path,
+            variable,
+            leaves,
+        }
+    }
+
+    // Collect the leaves of a variable in order (the first leaf is the most significant).
+    fn collect_leaves(
+        variable: &Variable,
+        scope_path: &[String],
+        header: &Header,
+        leaves: &mut Vec<SignalLeaf>,
+    ) {
+        let width = variable.kind.find_width() as usize;
+        if width == 0 {
+            return;
+        }
+
+        // Find the signal in the trace (if it exists)
+        let signal = variable.get_trace_name().and_then(|trace_name| {
+            let path = [scope_path, std::slice::from_ref(trace_name)].concat();
+            header.find_var(&path).map(|var| var.code)
+        });
+
+        match (signal, &variable.kind) {
+            (Some(id_code), _) => leaves.push(SignalLeaf::new(width, LeafSource::Signal(id_code))),
+            // The fields contain the actual values
+            (None, VariableKind::Struct { fields } | VariableKind::Vector { fields }) => {
+                // The fields of a struct scope (hierarchy aggregate mode of the rewriter)
+                let struct_path = variable
+                    .get_trace_name()
+                    .map(|trace_name| [scope_path, std::slice::from_ref(trace_name)].concat())
+                    .filter(|struct_path| header.find_scope(struct_path).is_some());
+                let fields_path = struct_path.as_deref().unwrap_or(scope_path);
+                for field in fields {
+                    Self::collect_leaves(field, fields_path, header, leaves);
+                }
+            }
+            (None, _) => leaves.push(SignalLeaf::new(width, LeafSource::Unknown)),
+        }
+    }
+
+    /// Return the signal of the field (or vector element) at position `idx` of the variable.
+    ///
+    /// The field is a slice of the bits of the variable: a packed struct or vector stored as a
+    /// single signal is split at the offset of the field (the first field is the most significant).
+    pub fn field(&self, idx: usize) -> Option<TypedSignal> {
+        let (fields, is_vector) = match &self.variable.kind {
+            VariableKind::Struct { fields } => (fields, false),
+            VariableKind::Vector { fields } => (fields, true),
+            _ => return None,
+        };
+        let field = fields.get(idx)?;
+        let start: usize = fields[..idx]
+            .iter()
+            .map(|field| field.kind.find_width() as usize)
+            .sum();
+        let end = start + field.kind.find_width() as usize;
+
+        let mut leaves = Vec::new();
+        let mut position = 0;
+        for leaf in &self.leaves {
+            let (first, last) = (start.max(position), end.min(position + leaf.width));
+            if first < last {
+                leaves.push(SignalLeaf {
+                    width: last - first,
+                    source: leaf.source.clone(),
+                    offset: leaf.offset + first - position,
+                    signal_width: leaf.signal_width,
+                });
+            }
+            position += leaf.width;
+        }
+
+        let path = match is_vector {
+            true => format!("{}[{}]", self.path, idx),
+            false => format!("{}.{}", self.path, field.name),
+        };
+        Some(TypedSignal {
+            path,
+            scope_path: self.scope_path.clone(),
+            variable: field.clone(),
+            leaves,
+        })
+    }
+
+    /// Return the id codes of the signals this variable depends on.
+    pub fn id_codes(&self) -> impl Iterator<Item = IdCode> + '_ {
+        self.leaves.iter().filter_map(|leaf| match leaf.source {
+            LeafSource::Signal(id_code) => Some(id_code),
+            LeafSource::Unknown => None,
+        })
+    }
+
+    /// Decode the value of the variable given the raw value of its signals.
+    pub fn decode_with<'a, F>(&self, raw_value_of: F) -> DecodedValue
+    where
+        F: Fn(IdCode) -> Option<&'a RawValue>,
+    {
+        // A single real or string signal is returned as it is
+        if let [leaf @ SignalLeaf {
+            source: LeafSource::Signal(id_code),
+            ..
+        }] = self.leaves.as_slice()
+        {
+            match raw_value_of(*id_code) {
+                Some(RawValue::Real(real)) if leaf.is_whole() => return DecodedValue::Real(*real),
+                Some(RawValue::String(s)) if leaf.is_whole() => {
+                    return DecodedValue::String(s.clone())
+                }
+                _ => {}
+            }
+        }
+
+        self.variable.decode_value(&self.bits_with(raw_value_of))
+    }
+
+    /// Return the raw bits (MSB first) of the variable given the raw value of its signals.
+    /// The bits of missing signals are `x`.
+    pub fn bits_with<'a, F>(&self, raw_value_of: F) -> String
+    where
+        F: Fn(IdCode) -> Option<&'a RawValue>,
+    {
+        let mut bits = String::with_capacity(self.variable.kind.find_width() as usize);
+        for leaf in &self.leaves {
+            match leaf.source {
+                LeafSource::Signal(id_code) => match raw_value_of(id_code) {
+                    Some(raw_value) => {
+                        let signal_bits = raw_value.to_bits(leaf.signal_width);
+                        bits.push_str(&signal_bits[leaf.offset..leaf.offset + leaf.width]);
+                    }
+                    None => bits.push_str(&"x".repeat(leaf.width)),
+                },
+                LeafSource::Unknown => bits.push_str(&"x".repeat(leaf.width)),
+            }
+        }
+        bits
+    }
+}
+
+/// A database of the value changes in a trace, indexed per id code.
+///
+/// It uses a [TyVcd] to answer queries on typed variables, struct fields and vector elements.
+/// Typed paths are made of the trace names of the scopes followed by the name of the variable
+/// and of its fields, for example `Top.dut.io.bits.data[3]`.
+pub struct WaveformDb {
+    /// The header and the typed information of the trace
+    hierarchy: TypedHierarchy,
+    /// The value changes of each signal in the trace
+    signals: HashMap<IdCode, SignalChanges>,
+    /// The last timestamp of the trace
+    end_time: u64,
+}
+
+impl WaveformDb {
+    /// Load a trace file (VCD or FST) and index its value changes.
+    pub fn open(trace_path: &Path, tyvcd: TyVcd) -> Result<Self> {
+        Self::from_source(source::open(trace_path)?, tyvcd)
+    }
+
+    /// Load a VCD from a reader and index its value changes.
+    pub fn from_reader<R: BufRead>(reader: R, tyvcd: TyVcd) -> Result<Self> {
+        Self::from_source(source::vcd_parser(reader), tyvcd)
+    }
+
+    /// Load a trace from a [WaveformSource] and index its value changes.
+    pub fn from_source<S: WaveformSource>(mut parser: S, tyvcd: TyVcd) -> Result<Self> {
+        let header = parser.parse_header()?;
+
+        let mut signals: HashMap<IdCode, SignalChanges> = HashMap::new();
+        let mut time = 0;
+        for command in parser {
+            let (id_code, value) = match command? {
+                Command::Timestamp(ts) => {
+                    time = ts;
+                    continue;
+                }
+                Command::ChangeScalar(id_code, value) => {
+                    (id_code, RawValue::Bits(value.to_string()))
+                }
+                Command::ChangeVector(id_code, value) => (id_code, RawValue::from(&value)),
+                Command::ChangeReal(id_code, value) => (id_code, RawValue::Real(value)),
+                Command::ChangeString(id_code, value) => (id_code, RawValue::String(value)),
+                _ => continue, // ignore the other commands
+            };
+            signals.entry(id_code).or_default().push(time, value);
+        }
+
+        Ok(Self {
+            hierarchy: TypedHierarchy::new(header, tyvcd),
+            signals,
+            end_time: time,
+        })
+    }
+
+    /// Return the header of the trace.
+    pub fn get_header(&self) -> &Header {
+        self.hierarchy.get_header()
+    }
+
+    /// Return the typed information used by the database.
+    pub fn get_tyvcd(&self) -> &TyVcd {
+        self.hierarchy.get_tyvcd()
+    }
+
+    /// Return the typed hierarchy used to resolve the typed paths.
+    pub fn get_hierarchy(&self) -> &TypedHierarchy {
+        &self.hierarchy
+    }
+
+    /// Return the last timestamp of the trace.
+    pub fn end_time(&self) -> u64 {
+        self.end_time
+    }
+
+    /// Return the value changes of a signal in the trace.
+    pub fn signal_changes(&self, id_code: IdCode) -> Option<&SignalChanges> {
+        self.signals.get(&id_code)
+    }
+
+    /// Return the raw value of a signal at `time`.
+    pub fn raw_value_at(&self, id_code: IdCode, time: u64) -> Option<&RawValue> {
+        self.signals.get(&id_code)?.value_at(time)
+    }
+
+    /// Find a typed variable (or one of its fields) and link it to the signals of the trace.
+    pub fn find_signal(&self, path: &str) -> Result<TypedSignal> {
+        self.hierarchy.find_signal(path)
+    }
+
+    /// Return the paths (trace names joined by `.`) of all the scopes in the TyVcd, sorted.
+    pub fn scope_paths(&self) -> Vec<String> {
+        self.hierarchy.scope_paths()
+    }
+
+    /// Return the name of the definition (i.e. the module) of the scope at `scope_path`.
+    pub fn scope_definition(&self, scope_path: &str) -> Result<String> {
+        self.hierarchy.scope_definition(scope_path)
+    }
+
+    /// Link all the variables declared in the scope at `scope_path` to the signals of the trace.
+    pub fn signals_in_scope(&self, scope_path: &str) -> Result<Vec<TypedSignal>> {
+        self.hierarchy.signals_in_scope(scope_path)
+    }
+
+    /// Return the typed value of a linked variable at `time`.
+    pub fn signal_value_at(&self, signal: &TypedSignal, time: u64) -> DecodedValue {
+        signal.decode_with(|id_code| self.raw_value_at(id_code, time))
+    }
+
+    /// Return the raw bits (MSB first) of a linked variable at `time`.
+    pub fn signal_bits_at(&self, signal: &TypedSignal, time: u64) -> String {
+        signal.bits_with(|id_code| self.raw_value_at(id_code, time))
+    }
+
+    /// Return the typed value of the variable at `path` at `time`.
+    pub fn value_at(&self, path: &str, time: u64) -> Result<DecodedValue> {
+        let signal = self.find_signal(path)?;
+        Ok(self.signal_value_at(&signal, time))
+    }
+
+    /// Iterate over the changes of a linked variable.
+    pub fn changes<'a>(&'a self, signal: &TypedSignal) -> TypedChanges<'a> {
+        TypedChanges::new(self, signal.clone())
+    }
+
+    /// Iterate over the changes of a typed variable declared in the scope at `scope_path`.
+    pub fn variable_changes<'a>(
+        &'a self,
+        variable: &Variable,
+        scope_path: &[String],
+    ) -> TypedChanges<'a> {
+        let path = [scope_path, std::slice::from_ref(&variable.name)]
+            .concat()
+            .join(".");
+        let signal = TypedSignal::create(
+            path,
+            scope_path.to_vec(),
+            variable.clone(),
+            self.get_header(),
+        );
+        TypedChanges::new(self, signal)
+    }
+
+    /// Return the typed values of a linked variable in the range `[start, end]`.
+    ///
+    /// The first element is the value at `start`, the following ones are the values
+    /// at the times the variable changes.
+    pub fn signal_values_between(
+        &self,
+        signal: &TypedSignal,
+        start: u64,
+        end: u64,
+    ) -> Vec<(u64, DecodedValue)> {
+        // Collect the times of the changes of all the signals
+        let mut times: Vec<u64> = signal
+            .id_codes()
+            .filter_map(|id_code| self.signals.get(&id_code))
+            .flat_map(|changes| changes.change_times(start, end).iter().copied())
+            .collect();
+        times.sort_unstable();
+        times.dedup();
+
+        let mut values: Vec<(u64, DecodedValue)> = Vec::with_capacity(times.len() + 1);
+        for time in std::iter::once(start).chain(times) {
+            let value = self.signal_value_at(signal, time);
+            // Emit only when the value changes
+            if values.last().map(|(_, last)| last) != Some(&value) {
+                values.push((time, value));
+            }
+        }
+        values
+    }
+
+    /// Return the typed values of the variable at `path` in the range `[start, end]`.
+    pub fn values_between(
+        &self,
+        path: &str,
+        start: u64,
+        end: u64,
+    ) -> Result<Vec<(u64, DecodedValue)>> {
+        let signal = self.find_signal(path)?;
+        Ok(self.signal_values_between(&signal, start, end))
+    }
+}
+
+impl AsRef<TypedHierarchy> for WaveformDb {
+    fn as_ref(&self) -> &TypedHierarchy {
+        &self.hierarchy
+    }
+}
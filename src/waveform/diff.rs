@@ -0,0 +1,341 @@
+use serde::Serialize;
+use std::fmt;
+
+use crate::tyvcd::value::DecodedValue;
+
+use super::db::{TypedSignal, WaveformDb, WaveformError};
+use super::sampling::{ClockSampler, SignalSelector};
+
+type Result<T> = std::result::Result<T, WaveformError>;
+
+/// How the times of the two waveforms are aligned before comparing them.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Alignment {
+    /// The value at time `t` of the left waveform is compared with the value at time `t + offset`
+    /// of the right one.
+    Time { offset: i64 },
+    /// The values are sampled on the rising edges of a clock: the cycle `n` of the left waveform
+    /// is compared with the cycle `n + offset` of the right one.
+    ClockCycles { clock: SignalSelector, offset: i64 },
+}
+
+impl Default for Alignment {
+    fn default() -> Self {
+        Alignment::Time { offset: 0 }
+    }
+}
+
+/// A struct field or vector element whose values differ in the two waveforms.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ValueDifference {
+    /// The typed path of the field or element (i.e. `Top.io.bits.data[3]`)
+    pub path: String,
+    /// The value in the left waveform
+    pub left: DecodedValue,
+    /// The value in the right waveform
+    pub right: DecodedValue,
+}
+
+/// The first divergence of a typed variable between two waveforms.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Divergence {
+    /// The typed path of the variable
+    pub path: String,
+    /// The time of the divergence in the left waveform (the sampling time if aligned on clock cycles)
+    pub left_time: u64,
+    /// The time of the divergence in the right waveform (the sampling time if aligned on clock cycles)
+    pub right_time: u64,
+    /// The cycle of the divergence in the left waveform, if aligned on clock cycles
+    pub cycle: Option<u64>,
+    /// The value of the variable in the left waveform
+    pub left: DecodedValue,
+    /// The value of the variable in the right waveform
+    pub right: DecodedValue,
+    /// The innermost fields and elements that differ
+    pub differences: Vec<ValueDifference>,
+}
+
+/// The result of the comparison of two waveforms.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct DiffReport {
+    /// The first divergence of each variable that differs, sorted by path
+    pub divergences: Vec<Divergence>,
+    /// The typed paths of the left waveform that do not exist in the right one
+    pub missing: Vec<String>,
+    /// The typed paths of the right waveform that do not exist in the left one
+    pub added: Vec<String>,
+}
+
+impl DiffReport {
+    /// Return true if no variable diverges and no variable is missing or added.
+    pub fn is_empty(&self) -> bool {
+        self.divergences.is_empty() && self.missing.is_empty() && self.added.is_empty()
+    }
+
+    /// Return the report in JSON format.
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).unwrap()
+    }
+}
+
+// Human readable report
+impl fmt::Display for DiffReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for divergence in &self.divergences {
+            write!(
+                f,
+                "{}: diverges at time {}",
+                divergence.path, divergence.left_time
+            )?;
+            if divergence.right_time != divergence.left_time {
+                write!(f, " (right {})", divergence.right_time)?;
+            }
+            if let Some(cycle) = divergence.cycle {
+                write!(f, ", cycle {}", cycle)?;
+            }
+            writeln!(f)?;
+            for difference in &divergence.differences {
+                writeln!(
+                    f,
+                    "    {}: {} != {}",
+                    difference.path, difference.left, difference.right
+                )?;
+            }
+        }
+        for path in &self.missing {
+            writeln!(f, "{}: missing in the right waveform", path)?;
+        }
+        for path in &self.added {
+            writeln!(f, "{}: added in the right waveform", path)?;
+        }
+        Ok(())
+    }
+}
+
+/// Compare two waveforms of the same design through their typed variables.
+pub struct WaveformDiff<'a> {
+    left: &'a WaveformDb,
+    right: &'a WaveformDb,
+    alignment: Alignment,
+    /// The typed paths to compare, all the variables if empty
+    paths: Vec<String>,
+}
+
+impl<'a> WaveformDiff<'a> {
+    /// Create a comparison of all the variables, with the two waveforms aligned in time.
+    pub fn new(left: &'a WaveformDb, right: &'a WaveformDb) -> Self {
+        Self {
+            left,
+            right,
+            alignment: Alignment::default(),
+            paths: Vec::new(),
+        }
+    }
+
+    /// Set how the two waveforms are aligned.
+    pub fn with_alignment(mut self, alignment: Alignment) -> Self {
+        self.alignment = alignment;
+        self
+    }
+
+    /// Compare only the given variables (and the ones of the previous calls).
+    pub fn with_variable(mut self, path: &str) -> Self {
+        self.paths.push(path.to_string());
+        self
+    }
+
+    // Return the variables to compare in the left waveform, and the paths of the variables that
+    // exist only in the right waveform.
+    fn left_signals(&self) -> Result<(Vec<TypedSignal>, Vec<String>)> {
+        if !self.paths.is_empty() {
+            let mut signals = Vec::new();
+            let mut added = Vec::new();
+            for path in &self.paths {
+                match self.left.find_signal(path) {
+                    Ok(signal) => signals.push(signal),
+                    Err(WaveformError::PathNotFound(_)) if self.right.find_signal(path).is_ok() => {
+                        added.push(path.clone())
+                    }
+                    Err(err) => return Err(err),
+                }
+            }
+            return Ok((signals, added));
+        }
+        let signals = all_signals(self.left)?;
+        let added = all_signals(self.right)?
+            .into_iter()
+            .map(|signal| signal.path)
+            .filter(|path| self.left.find_signal(path).is_err())
+            .collect();
+        Ok((signals, added))
+    }
+
+    /// Compare the two waveforms and report the first divergence of each variable.
+    pub fn compare(&self) -> Result<DiffReport> {
+        // The points in time to compare: (left time, right time, cycle)
+        let (time_offset, cycle_points) = match &self.alignment {
+            Alignment::Time { offset } => (*offset, None),
+            Alignment::ClockCycles { clock, offset } => (
+                0,
+                Some(Self::cycle_points(self.left, self.right, clock, *offset)?),
+            ),
+        };
+
+        let (left_signals, added) = self.left_signals()?;
+        let mut report = DiffReport {
+            added,
+            ..Default::default()
+        };
+        for left in left_signals {
+            let Ok(right) = self.right.find_signal(&left.path) else {
+                report.missing.push(left.path);
+                continue;
+            };
+            let time_points;
+            let points = match &cycle_points {
+                Some(points) => points,
+                None => {
+                    time_points = self.time_points(&left, &right, time_offset);
+                    &time_points
+                }
+            };
+            if let Some(divergence) = self.first_divergence(&left, &right, points) {
+                report.divergences.push(divergence);
+            }
+        }
+        Ok(report)
+    }
+
+    // Return the rising edges of the clock in both waveforms, paired by cycle.
+    fn cycle_points(
+        left: &WaveformDb,
+        right: &WaveformDb,
+        clock: &SignalSelector,
+        offset: i64,
+    ) -> Result<Vec<(u64, u64, Option<u64>)>> {
+        let left_sampler = ClockSampler::new(left, clock)?;
+        let right_sampler = ClockSampler::new(right, clock)?;
+        let right_edges = right_sampler.rising_edges();
+
+        let points = left_sampler
+            .rising_edges()
+            .into_iter()
+            .enumerate()
+            .filter_map(|(cycle, left_edge)| {
+                let right_cycle = usize::try_from(cycle as i64 + offset).ok()?;
+                let right_edge = *right_edges.get(right_cycle)?;
+                Some((
+                    left_sampler.sample_time(left_edge),
+                    right_sampler.sample_time(right_edge),
+                    Some(cycle as u64),
+                ))
+            })
+            .collect();
+        Ok(points)
+    }
+
+    // Return the times where either variable changes, in both waveforms.
+    fn time_points(
+        &self,
+        left: &TypedSignal,
+        right: &TypedSignal,
+        offset: i64,
+    ) -> Vec<(u64, u64, Option<u64>)> {
+        let to_left = |time: u64| u64::try_from(time as i64 - offset).ok();
+        let mut times: Vec<u64> = self
+            .left
+            .changes(left)
+            .map(|(time, _)| time)
+            .chain(
+                self.right
+                    .changes(right)
+                    .filter_map(|(time, _)| to_left(time)),
+            )
+            .collect();
+        times.sort_unstable();
+        times.dedup();
+
+        // The first time where both waveforms are defined
+        let start = u64::try_from(-offset).unwrap_or(0);
+        if times.first().is_none_or(|first| *first > start) {
+            times.insert(0, start);
+        }
+
+        times
+            .into_iter()
+            .filter(|time| *time >= start)
+            .filter_map(|time| {
+                let right_time = u64::try_from(time as i64 + offset).ok()?;
+                Some((time, right_time, None))
+            })
+            .collect()
+    }
+
+    // Compare the variable at the given points and return the first divergence.
+    fn first_divergence(
+        &self,
+        left: &TypedSignal,
+        right: &TypedSignal,
+        points: &[(u64, u64, Option<u64>)],
+    ) -> Option<Divergence> {
+        points.iter().find_map(|&(left_time, right_time, cycle)| {
+            let left_value = self.left.signal_value_at(left, left_time);
+            let right_value = self.right.signal_value_at(right, right_time);
+            if left_value == right_value {
+                return None;
+            }
+            let mut differences = Vec::new();
+            diff_values(&left.path, &left_value, &right_value, &mut differences);
+            Some(Divergence {
+                path: left.path.clone(),
+                left_time,
+                right_time,
+                cycle,
+                left: left_value,
+                right: right_value,
+                differences,
+            })
+        })
+    }
+}
+
+// Return all the variables of a waveform, sorted by path.
+fn all_signals(db: &WaveformDb) -> Result<Vec<TypedSignal>> {
+    let mut signals = Vec::new();
+    for scope_path in db.scope_paths() {
+        signals.extend(db.signals_in_scope(&scope_path)?);
+    }
+    signals.sort_by(|a, b| a.path.cmp(&b.path));
+    Ok(signals)
+}
+
+// Collect the innermost fields and elements that differ in two values.
+fn diff_values(
+    path: &str,
+    left: &DecodedValue,
+    right: &DecodedValue,
+    differences: &mut Vec<ValueDifference>,
+) {
+    match (left, right) {
+        (DecodedValue::Struct(left_fields), DecodedValue::Struct(right_fields))
+            if left_fields.len() == right_fields.len() =>
+        {
+            for ((name, l), (_, r)) in left_fields.iter().zip(right_fields) {
+                diff_values(&format!("{}.{}", path, name), l, r, differences);
+            }
+        }
+        (DecodedValue::Vector(left_elems), DecodedValue::Vector(right_elems))
+            if left_elems.len() == right_elems.len() =>
+        {
+            for (idx, (l, r)) in left_elems.iter().zip(right_elems).enumerate() {
+                diff_values(&format!("{}[{}]", path, idx), l, r, differences);
+            }
+        }
+        _ if left != right => differences.push(ValueDifference {
+            path: path.to_string(),
+            left: left.clone(),
+            right: right.clone(),
+        }),
+        _ => {}
+    }
+}
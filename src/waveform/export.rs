@@ -0,0 +1,292 @@
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::io::{BufRead, BufWriter, Write};
+use std::path::Path;
+use vcd::{Command, IdCode};
+
+use crate::tyvcd::spec::{TyVcd, VariableKind};
+use crate::tyvcd::value::DecodedValue;
+
+use super::db::{RawValue, TypedSignal, WaveformError};
+use super::hierarchy::TypedHierarchy;
+use super::sampling::SignalSelector;
+use super::source::{self, WaveformSource};
+
+type Result<T> = std::result::Result<T, WaveformError>;
+
+/// The format of an exported trace.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ExportFormat {
+    /// Comma separated values with a header row
+    Csv,
+    /// One JSON object per row
+    JsonLines,
+}
+
+impl ExportFormat {
+    /// Select the format from the extension of a file (`.csv`, `.jsonl` or `.ndjson`).
+    pub fn from_path(path: &Path) -> Option<Self> {
+        match path.extension()?.to_str()? {
+            "csv" => Some(ExportFormat::Csv),
+            "jsonl" | "ndjson" => Some(ExportFormat::JsonLines),
+            _ => None,
+        }
+    }
+}
+
+/// Export the values of typed variables to CSV or JSON Lines.
+///
+/// The struct and vector variables are flattened: each ground field is a column named after its
+/// typed path, for example `Top.io.bits.addr` and `Top.io.bits.data[3]`. Enums are written with the
+/// names of their variants. A row is written every time a selected variable changes or, if a clock
+/// is set, at every rising edge of the clock (with the values sampled right before the edge).
+///
+/// The trace is read and written in a streaming fashion, for both VCD and FST inputs: only the
+/// current value of each signal is kept, the value changes are never stored.
+pub struct TraceExporter {
+    format: ExportFormat,
+    /// The typed paths of the exported variables
+    paths: Vec<String>,
+    /// The clock to sample the variables, rows are written at every change if [None]
+    clock: Option<SignalSelector>,
+}
+
+// A column of the exported trace
+struct Column {
+    name: String,
+    signal: TypedSignal,
+}
+
+impl TraceExporter {
+    /// Create an exporter of the changes of the selected variables.
+    pub fn new(format: ExportFormat) -> Self {
+        Self {
+            format,
+            paths: Vec::new(),
+            clock: None,
+        }
+    }
+
+    /// Export the variable at `path` (all its fields if it is a struct or a vector).
+    pub fn with_variable(mut self, path: &str) -> Self {
+        self.paths.push(path.to_string());
+        self
+    }
+
+    /// Write one row per rising edge of a clock instead of one row per change.
+    pub fn with_clock(mut self, clock: SignalSelector) -> Self {
+        self.clock = Some(clock);
+        self
+    }
+
+    /// Export a trace file (VCD or FST) to `output_path`. Return the number of rows written.
+    pub fn export_file(&self, tyvcd: TyVcd, trace_path: &Path, output_path: &Path) -> Result<u64> {
+        let source = source::open(trace_path)?;
+        let mut writer = BufWriter::new(File::create(output_path)?);
+        let rows = self.export_source(tyvcd, source, &mut writer)?;
+        writer.flush()?;
+        Ok(rows)
+    }
+
+    /// Export a VCD read from `reader` to `writer`. Return the number of rows written.
+    pub fn export<R: BufRead, W: Write>(&self, tyvcd: TyVcd, reader: R, writer: W) -> Result<u64> {
+        self.export_source(tyvcd, source::vcd_parser(reader), writer)
+    }
+
+    /// Export a trace read from a [WaveformSource] to `writer`. Return the number of rows written.
+    pub fn export_source<S: WaveformSource, W: Write>(
+        &self,
+        tyvcd: TyVcd,
+        mut parser: S,
+        writer: W,
+    ) -> Result<u64> {
+        let hierarchy = TypedHierarchy::new(parser.parse_header()?, tyvcd);
+
+        let mut columns = Vec::new();
+        for path in &self.paths {
+            let signal = hierarchy.find_signal(path)?;
+            Self::flatten(signal, &mut columns);
+        }
+        let clock = match &self.clock {
+            Some(clock) => Some(clock.find(&hierarchy)?),
+            None => None,
+        };
+
+        let mut state = ExportState {
+            format: self.format,
+            writer,
+            columns,
+            clock,
+            current: HashMap::new(),
+            pending: Vec::new(),
+            last_row: None,
+            cycle: 0,
+            rows: 0,
+        };
+        state.write_header()?;
+
+        let mut time = 0;
+        for command in parser {
+            let (id_code, value) = match command? {
+                Command::Timestamp(ts) => {
+                    state.flush(time)?;
+                    time = ts;
+                    continue;
+                }
+                Command::ChangeScalar(id_code, value) => {
+                    (id_code, RawValue::Bits(value.to_string()))
+                }
+                Command::ChangeVector(id_code, value) => (id_code, RawValue::from(&value)),
+                Command::ChangeReal(id_code, value) => (id_code, RawValue::Real(value)),
+                Command::ChangeString(id_code, value) => (id_code, RawValue::String(value)),
+                _ => continue, // ignore the other commands
+            };
+            state.pending.push((id_code, value));
+        }
+        state.flush(time)?;
+        Ok(state.rows)
+    }
+
+    // Split a variable in its ground fields.
+    // A packed struct or vector (a single signal in the trace) is sliced at the offset of each field.
+    fn flatten(signal: TypedSignal, columns: &mut Vec<Column>) {
+        match &signal.variable.kind {
+            VariableKind::Struct { fields } | VariableKind::Vector { fields } => {
+                for idx in 0..fields.len() {
+                    if let Some(field_signal) = signal.field(idx) {
+                        Self::flatten(field_signal, columns);
+                    }
+                }
+            }
+            VariableKind::Ground(_) | VariableKind::External => columns.push(Column {
+                name: signal.path.clone(),
+                signal,
+            }),
+        }
+    }
+}
+
+// The state of an export while the trace is read
+struct ExportState<W: Write> {
+    format: ExportFormat,
+    writer: W,
+    columns: Vec<Column>,
+    clock: Option<TypedSignal>,
+    /// The current value of each signal
+    current: HashMap<IdCode, RawValue>,
+    /// The changes of the current timestamp, not applied yet
+    pending: Vec<(IdCode, RawValue)>,
+    /// The values of the last row written (only for the rows per change)
+    last_row: Option<Vec<DecodedValue>>,
+    cycle: u64,
+    rows: u64,
+}
+
+impl<W: Write> ExportState<W> {
+    fn write_header(&mut self) -> Result<()> {
+        if self.format != ExportFormat::Csv {
+            return Ok(());
+        }
+        let mut header = vec!["time".to_string()];
+        if self.clock.is_some() {
+            header.push("cycle".to_string());
+        }
+        header.extend(self.columns.iter().map(|c| csv_field(&c.name)));
+        writeln!(self.writer, "{}", header.join(","))?;
+        Ok(())
+    }
+
+    // Return the values of the columns from the current values of the signals.
+    fn row(&self) -> Vec<DecodedValue> {
+        self.columns
+            .iter()
+            .map(|column| {
+                column
+                    .signal
+                    .decode_with(|id_code| self.current.get(&id_code))
+            })
+            .collect()
+    }
+
+    // Process the changes of the timestamp `time`.
+    fn flush(&mut self, time: u64) -> Result<()> {
+        let pending = std::mem::take(&mut self.pending);
+
+        // Whether the clock has a rising edge at this timestamp
+        let is_edge = self.clock.as_ref().map(|clock| {
+            let old_clock = clock.decode_with(|id_code| self.current.get(&id_code));
+            let new_clock = clock.decode_with(|id_code| {
+                pending
+                    .iter()
+                    .rev()
+                    .find(|(id, _)| *id == id_code)
+                    .map(|(_, value)| value)
+                    .or_else(|| self.current.get(&id_code))
+            });
+            old_clock == DecodedValue::Unsigned(0) && new_clock == DecodedValue::Unsigned(1)
+        });
+
+        match is_edge {
+            // Write the values before the changes at the rising edges
+            Some(is_edge) => {
+                if is_edge {
+                    let row = self.row();
+                    self.write_row(time, Some(self.cycle), &row)?;
+                    self.cycle += 1;
+                }
+                self.current.extend(pending);
+            }
+            // Write the values after the changes, if any column changed
+            None => {
+                let changed: HashSet<IdCode> = pending.iter().map(|(id, _)| *id).collect();
+                self.current.extend(pending);
+                let is_selected = self
+                    .columns
+                    .iter()
+                    .any(|c| c.signal.id_codes().any(|id| changed.contains(&id)));
+                if is_selected {
+                    let row = self.row();
+                    if self.last_row.as_ref() != Some(&row) {
+                        self.write_row(time, None, &row)?;
+                        self.last_row = Some(row);
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn write_row(&mut self, time: u64, cycle: Option<u64>, row: &[DecodedValue]) -> Result<()> {
+        match self.format {
+            ExportFormat::Csv => {
+                let mut fields = vec![time.to_string()];
+                fields.extend(cycle.map(|c| c.to_string()));
+                fields.extend(row.iter().map(|v| csv_field(&v.to_string())));
+                writeln!(self.writer, "{}", fields.join(","))?;
+            }
+            ExportFormat::JsonLines => {
+                let mut fields = vec![format!("\"time\":{}", time)];
+                fields.extend(cycle.map(|c| format!("\"cycle\":{}", c)));
+                for (column, value) in self.columns.iter().zip(row) {
+                    fields.push(format!(
+                        "{}:{}",
+                        serde_json::to_string(&column.name).unwrap(),
+                        serde_json::to_string(value).unwrap()
+                    ));
+                }
+                writeln!(self.writer, "{{{}}}", fields.join(","))?;
+            }
+        }
+        self.rows += 1;
+        Ok(())
+    }
+}
+
+// Quote a CSV field if needed.
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
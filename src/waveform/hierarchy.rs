@@ -0,0 +1,161 @@
+use std::sync::{Arc, RwLock};
+use vcd::Header;
+
+use crate::tyvcd::spec::{Scope, TyVcd, Variable, VariableKind};
+use crate::tyvcd::trace_pointer::TraceGetter;
+
+use super::db::{split_path, TypedSignal, WaveformError};
+
+type Result<T> = std::result::Result<T, WaveformError>;
+
+// A scope found from a path: the scope, its path in the trace and the remaining elements of the path.
+type ScopeMatch<'p> = (Arc<RwLock<Scope>>, Vec<String>, &'p [String]);
+
+/// The hierarchy of a trace (its header) with the typed information of a [TyVcd].
+///
+/// It resolves the typed paths to the signals of the trace without loading the value changes.
+#[derive(Debug)]
+pub struct TypedHierarchy {
+    /// The header of the trace
+    header: Header,
+    /// The typed information of the trace
+    tyvcd: TyVcd,
+}
+
+impl TypedHierarchy {
+    pub fn new(header: Header, tyvcd: TyVcd) -> Self {
+        Self { header, tyvcd }
+    }
+
+    /// Return the header of the trace.
+    pub fn get_header(&self) -> &Header {
+        &self.header
+    }
+
+    /// Return the typed information of the trace.
+    pub fn get_tyvcd(&self) -> &TyVcd {
+        &self.tyvcd
+    }
+
+    // Find the deepest scope matching the beginning of the path.
+    // Return the scope, its path (trace names) and the remaining elements of the path.
+    fn find_scope<'p>(&self, elems: &'p [String]) -> Option<ScopeMatch<'p>> {
+        let (top_name, mut elems) = elems.split_first()?;
+
+        // Find the top scope by its trace name
+        let mut scope = self
+            .tyvcd
+            .scopes
+            .iter()
+            .find(|(name, scope)| {
+                scope.read().unwrap().get_trace_name() == Some(top_name) || *name == top_name
+            })
+            .map(|(_, scope)| scope.clone())?;
+        let mut scope_path = vec![scope.read().unwrap().get_trace_name()?.clone()];
+
+        // Explore the subscopes
+        while let Some((name, rest)) = elems.split_first() {
+            let subscope = scope.read().unwrap().subscopes.get(name).cloned();
+            match subscope {
+                Some(subscope) => {
+                    scope = subscope;
+                    scope_path.push(name.clone());
+                    elems = rest;
+                }
+                None => break,
+            }
+        }
+        Some((scope, scope_path, elems))
+    }
+
+    /// Find a typed variable (or one of its fields) and link it to the signals of the trace.
+    pub fn find_signal(&self, path: &str) -> Result<TypedSignal> {
+        let not_found = || WaveformError::PathNotFound(path.to_string());
+        let elems = split_path(path);
+        let (scope, scope_path, elems) = self.find_scope(&elems).ok_or_else(not_found)?;
+
+        // Find the variable and slice its fields
+        let (var_name, field_names) = elems.split_first().ok_or_else(not_found)?;
+        let variable = Self::find_variable_in(&scope, var_name).ok_or_else(not_found)?;
+        let mut signal = TypedSignal::create(path.to_string(), scope_path, variable, &self.header);
+        for field_name in field_names {
+            let idx = match &signal.variable.kind {
+                VariableKind::Struct { fields } | VariableKind::Vector { fields } => {
+                    fields.iter().position(|f| &f.name == field_name)
+                }
+                VariableKind::Ground(_) | VariableKind::External => None,
+            };
+            signal = idx
+                .and_then(|idx| signal.field(idx))
+                .ok_or_else(not_found)?;
+        }
+        signal.path = path.to_string();
+        Ok(signal)
+    }
+
+    /// Return the paths (trace names joined by `.`) of all the scopes in the TyVcd, sorted.
+    pub fn scope_paths(&self) -> Vec<String> {
+        fn collect(scope: &Arc<RwLock<Scope>>, path: String, paths: &mut Vec<String>) {
+            let scope = scope.read().unwrap();
+            for (name, subscope) in &scope.subscopes {
+                collect(subscope, format!("{}.{}", path, name), paths);
+            }
+            paths.push(path);
+        }
+
+        let mut paths = Vec::new();
+        for scope in self.tyvcd.scopes.values() {
+            let trace_name = scope.read().unwrap().get_trace_name().cloned();
+            if let Some(trace_name) = trace_name {
+                collect(scope, trace_name, &mut paths);
+            }
+        }
+        paths.sort();
+        paths
+    }
+
+    /// Return the name of the definition (i.e. the module) of the scope at `scope_path`.
+    pub fn scope_definition(&self, scope_path: &str) -> Result<String> {
+        let not_found = || WaveformError::PathNotFound(scope_path.to_string());
+        let elems = split_path(scope_path);
+        match self.find_scope(&elems) {
+            Some((scope, _, [])) => Ok(scope.read().unwrap().name.clone()),
+            _ => Err(not_found()),
+        }
+    }
+
+    /// Link all the variables declared in the scope at `scope_path` to the signals of the trace.
+    pub fn signals_in_scope(&self, scope_path: &str) -> Result<Vec<TypedSignal>> {
+        let not_found = || WaveformError::PathNotFound(scope_path.to_string());
+        let elems = split_path(scope_path);
+        let (scope, scope_path, elems) = self.find_scope(&elems).ok_or_else(not_found)?;
+        if !elems.is_empty() {
+            return Err(not_found());
+        }
+
+        let scope = scope.read().unwrap();
+        let signals = scope
+            .variables
+            .iter()
+            .map(|variable| {
+                let path = [scope_path.as_slice(), std::slice::from_ref(&variable.name)]
+                    .concat()
+                    .join(".");
+                TypedSignal::create(path, scope_path.clone(), variable.clone(), &self.header)
+            })
+            .collect();
+        Ok(signals)
+    }
+
+    // Find a variable in a scope from its name followed by the names of its fields.
+    fn find_variable_in(scope: &Arc<RwLock<Scope>>, var_name: &str) -> Option<Variable> {
+        let scope = scope.read().unwrap();
+        scope.variables.iter().find(|v| v.name == var_name).cloned()
+    }
+}
+
+impl AsRef<TypedHierarchy> for TypedHierarchy {
+    fn as_ref(&self) -> &TypedHierarchy {
+        self
+    }
+}
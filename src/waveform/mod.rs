@@ -0,0 +1,20 @@
+/// Iterators over the changes of typed variables.
+pub mod changes;
+/// Toggle and enum value coverage of the typed variables.
+pub mod coverage;
+/// Database of the value changes in a waveform, queried through the typed variables of a TyVcd.
+pub mod db;
+/// Comparison of two waveforms through their typed variables.
+pub mod diff;
+/// Export of typed traces to CSV and JSON Lines.
+pub mod export;
+/// Typed hierarchy of a trace: resolution of the typed paths to the signals of the trace.
+pub mod hierarchy;
+/// Predicates over typed variables and their evaluation over time.
+pub mod query;
+/// Sampling of typed variables on the edges of a clock.
+pub mod sampling;
+/// Sources of the value changes of a trace: VCD and FST readers.
+pub mod source;
+/// Extraction of the transactions fired on ready/valid bundles.
+pub mod transactions;
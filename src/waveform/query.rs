@@ -0,0 +1,688 @@
+use std::collections::HashSet;
+
+use crate::hgldd::spec::Opcode;
+use crate::tyvcd::spec::NumericKind;
+use crate::tyvcd::value;
+
+use super::db::{TypedSignal, WaveformDb, WaveformError};
+use super::sampling::ClockSampler;
+
+type Result<T> = std::result::Result<T, QueryError>;
+
+/// Error raised when a query cannot be parsed or linked to a waveform.
+#[derive(Debug)]
+pub enum QueryError {
+    /// The expression is not valid: the position (in characters) and the reason
+    ParseError {
+        position: usize,
+        message: String,
+    },
+    /// An identifier is neither a typed path nor a variant of the enums in the expression
+    UnknownIdentifier(String),
+    WaveformError(WaveformError),
+}
+
+impl From<WaveformError> for QueryError {
+    fn from(e: WaveformError) -> Self {
+        QueryError::WaveformError(e)
+    }
+}
+
+/// An expression over typed variables.
+///
+/// The operators are the ones of the HGLDD [Opcode]s (Verilog semantics), plus the logical
+/// `!`, `&&`, `||` and the unary `~` and `-`. A value with `x` or `z` bits is unknown, so the
+/// 4-state comparisons (`===`, `!==`, `==?` and `!=?`) are not supported.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expr {
+    /// A typed path (i.e. `Top.io.in.bits.op`) or the name of an enum variant (i.e. `ADD`)
+    Ident(String),
+    /// An integer constant
+    Const(i128),
+    /// Logical not `!`
+    Not(Box<Expr>),
+    /// Bitwise not `~`
+    BitNot(Box<Expr>),
+    /// Negation `-`
+    Neg(Box<Expr>),
+    /// Logical and `&&`
+    LogicAnd(Box<Expr>, Box<Expr>),
+    /// Logical or `||`
+    LogicOr(Box<Expr>, Box<Expr>),
+    /// An HGLDD operator applied to its operands: binary operators have two operands,
+    /// [Opcode::Mux] has three (`c ? a : b`) and [Opcode::Extract] has the value and the
+    /// constant bounds (`a[hi:lo]`, with `0 <= lo <= hi < 128`)
+    Op(Opcode, Vec<Expr>),
+}
+
+impl Expr {
+    /// Parse an expression, for example `Top.io.in.valid && Top.io.in.bits.op == ADD`.
+    pub fn parse(expr: &str) -> Result<Self> {
+        let tokens = tokenize(expr)?;
+        let mut parser = ExprParser {
+            tokens,
+            pos: 0,
+            end: expr.chars().count(),
+        };
+        let expr = parser.parse_expr(0)?;
+        match parser.peek() {
+            None => Ok(expr),
+            Some((position, token)) => Err(QueryError::ParseError {
+                position,
+                message: format!("unexpected {:?}", token),
+            }),
+        }
+    }
+}
+
+// The tokens of an expression
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Ident(String),
+    Number(i128),
+    Operator(String),
+    LParen,
+    RParen,
+    LBracket,
+    RBracket,
+    Colon,
+    Question,
+}
+
+// Split an expression in tokens, each with its position.
+fn tokenize(expr: &str) -> Result<Vec<(usize, Token)>> {
+    let chars: Vec<char> = expr.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    // The longest operators first
+    const OPERATORS: [&str; 25] = [
+        ">>>", "===", "!==", "==?", "!=?", "&&", "||", "==", "!=", "<=", ">=", "<<", ">>", "+",
+        "-", "*", "/", "%", "&", "|", "^", "~", "!", "<", ">",
+    ];
+
+    while i < chars.len() {
+        let c = chars[i];
+        let start = i;
+        if c.is_whitespace() {
+            i += 1;
+        } else if c.is_ascii_alphabetic() || c == '_' {
+            // A path: identifiers separated by `.` and followed by indexes `[n]`
+            while i < chars.len() {
+                let is_ident_char = |c: &char| c.is_ascii_alphanumeric() || *c == '_';
+                if is_ident_char(&chars[i])
+                    || (chars[i] == '.' && chars.get(i + 1).is_some_and(is_ident_char))
+                {
+                    i += 1;
+                } else if chars[i] == '[' {
+                    // Only indexes are part of the path, bit ranges `[hi:lo]` are not
+                    let digits = chars[i + 1..]
+                        .iter()
+                        .take_while(|c| c.is_ascii_digit())
+                        .count();
+                    if digits == 0 || chars.get(i + 1 + digits) != Some(&']') {
+                        break;
+                    }
+                    i += digits + 2;
+                } else {
+                    break;
+                }
+            }
+            let ident: String = chars[start..i].iter().collect();
+            tokens.push((start, Token::Ident(ident)));
+        } else if c.is_ascii_digit() {
+            while i < chars.len() && (chars[i].is_ascii_alphanumeric() || chars[i] == '_') {
+                i += 1;
+            }
+            let literal: String = chars[start..i].iter().filter(|c| **c != '_').collect();
+            let number = match literal.get(..2) {
+                Some("0x") | Some("0X") => i128::from_str_radix(&literal[2..], 16),
+                Some("0b") | Some("0B") => i128::from_str_radix(&literal[2..], 2),
+                _ => literal.parse::<i128>(),
+            };
+            let number = number.map_err(|_| QueryError::ParseError {
+                position: start,
+                message: format!("invalid number {}", literal),
+            })?;
+            tokens.push((start, Token::Number(number)));
+        } else {
+            let token = match c {
+                '(' => Some(Token::LParen),
+                ')' => Some(Token::RParen),
+                '[' => Some(Token::LBracket),
+                ']' => Some(Token::RBracket),
+                ':' => Some(Token::Colon),
+                '?' if chars.get(i + 1) != Some(&'=') => Some(Token::Question),
+                _ => None,
+            };
+            if let Some(token) = token {
+                tokens.push((start, token));
+                i += 1;
+                continue;
+            }
+
+            let rest: String = chars[i..chars.len().min(i + 3)].iter().collect();
+            let operator = OPERATORS
+                .iter()
+                .find(|op| rest.starts_with(*op))
+                .ok_or_else(|| QueryError::ParseError {
+                    position: start,
+                    message: format!("unexpected character {:?}", c),
+                })?;
+            tokens.push((start, Token::Operator(operator.to_string())));
+            i += operator.len();
+        }
+    }
+    Ok(tokens)
+}
+
+// Return the binding power of a binary operator (higher binds tighter)
+fn binary_power(op: &str) -> Option<u8> {
+    let power = match op {
+        "||" => 2,
+        "&&" => 3,
+        "|" => 4,
+        "^" => 5,
+        "&" => 6,
+        "==" | "!=" | "===" | "!==" | "==?" | "!=?" => 7,
+        "<" | "<=" | ">" | ">=" => 8,
+        "<<" | ">>" | ">>>" => 9,
+        "+" | "-" => 10,
+        "*" | "/" | "%" => 11,
+        _ => return None,
+    };
+    Some(power)
+}
+
+// The binding power of the ternary operator (the lowest one)
+const MUX_POWER: u8 = 1;
+
+// Return the HGLDD opcode of a binary operator
+fn binary_opcode(op: &str) -> Option<Opcode> {
+    let opcode = match op {
+        "&" => Opcode::And,
+        "|" => Opcode::Or,
+        "^" => Opcode::UnaryOrXor,
+        "+" => Opcode::Add,
+        "-" => Opcode::Sub,
+        "*" => Opcode::Mul,
+        "/" => Opcode::Div,
+        "%" => Opcode::Mod,
+        "<<" => Opcode::ShiftLeft,
+        ">>" => Opcode::ShiftRight,
+        ">>>" => Opcode::ShiftRightSigned,
+        "==" => Opcode::Eq,
+        "!=" => Opcode::NotEq,
+        "<" => Opcode::LessThan,
+        ">" => Opcode::GreaterThan,
+        "<=" => Opcode::LessEq,
+        ">=" => Opcode::GreaterEq,
+        _ => return None,
+    };
+    Some(opcode)
+}
+
+// A precedence climbing parser
+struct ExprParser {
+    tokens: Vec<(usize, Token)>,
+    pos: usize,
+    /// The position of the end of the expression
+    end: usize,
+}
+
+impl ExprParser {
+    fn peek(&self) -> Option<(usize, &Token)> {
+        self.tokens.get(self.pos).map(|(p, t)| (*p, t))
+    }
+
+    fn next(&mut self) -> Result<(usize, Token)> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token.ok_or_else(|| QueryError::ParseError {
+            position: self.end,
+            message: "unexpected end of the expression".to_string(),
+        })
+    }
+
+    fn expect(&mut self, expected: Token) -> Result<()> {
+        let (position, token) = self.next()?;
+        if token != expected {
+            return Err(QueryError::ParseError {
+                position,
+                message: format!("expected {:?}, found {:?}", expected, token),
+            });
+        }
+        Ok(())
+    }
+
+    fn expect_number(&mut self) -> Result<i128> {
+        match self.next()? {
+            (_, Token::Number(n)) => Ok(n),
+            (position, token) => Err(QueryError::ParseError {
+                position,
+                message: format!("expected a number, found {:?}", token),
+            }),
+        }
+    }
+
+    // Parse an expression with operators binding tighter than `min_power`
+    fn parse_expr(&mut self, min_power: u8) -> Result<Expr> {
+        let mut lhs = self.parse_unary()?;
+        loop {
+            match self.peek() {
+                Some((_, Token::Question)) if MUX_POWER > min_power => {
+                    self.pos += 1;
+                    let then = self.parse_expr(0)?;
+                    self.expect(Token::Colon)?;
+                    // Right associative
+                    let otherwise = self.parse_expr(MUX_POWER - 1)?;
+                    lhs = Expr::Op(Opcode::Mux, vec![lhs, then, otherwise]);
+                }
+                Some((position, Token::Operator(op))) => {
+                    let op = op.clone();
+                    let Some(power) = binary_power(&op).filter(|p| *p > min_power) else {
+                        break;
+                    };
+                    // The values with `x` or `z` are unknown: the 4-state operators cannot be
+                    // evaluated
+                    let opcode = match op.as_str() {
+                        "&&" | "||" => None,
+                        _ => Some(binary_opcode(&op).ok_or_else(|| QueryError::ParseError {
+                            position,
+                            message: format!("the 4-state operator {} is not supported", op),
+                        })?),
+                    };
+                    self.pos += 1;
+                    let rhs = self.parse_expr(power)?;
+                    lhs = match opcode {
+                        Some(opcode) => Expr::Op(opcode, vec![lhs, rhs]),
+                        None if op == "&&" => Expr::LogicAnd(Box::new(lhs), Box::new(rhs)),
+                        None => Expr::LogicOr(Box::new(lhs), Box::new(rhs)),
+                    };
+                }
+                _ => break,
+            }
+        }
+        Ok(lhs)
+    }
+
+    // Parse a unary operator applied to a primary expression
+    fn parse_unary(&mut self) -> Result<Expr> {
+        if let Some((_, Token::Operator(op))) = self.peek() {
+            let unary: Option<fn(Box<Expr>) -> Expr> = match op.as_str() {
+                "!" => Some(Expr::Not),
+                "~" => Some(Expr::BitNot),
+                "-" => Some(Expr::Neg),
+                _ => None,
+            };
+            if let Some(unary) = unary {
+                self.pos += 1;
+                return Ok(unary(Box::new(self.parse_unary()?)));
+            }
+        }
+        let mut expr = self.parse_primary()?;
+
+        // Bit ranges `[hi:lo]` and single bits `[n]`
+        while let Some((position, Token::LBracket)) = self.peek() {
+            self.pos += 1;
+            let hi = self.expect_number()?;
+            let lo = match self.peek() {
+                Some((_, Token::Colon)) => {
+                    self.pos += 1;
+                    self.expect_number()?
+                }
+                _ => hi,
+            };
+            self.expect(Token::RBracket)?;
+            if !(0 <= lo && lo <= hi && hi < 128) {
+                return Err(QueryError::ParseError {
+                    position,
+                    message: format!("invalid bit range [{}:{}]", hi, lo),
+                });
+            }
+            expr = Expr::Op(
+                Opcode::Extract,
+                vec![expr, Expr::Const(hi), Expr::Const(lo)],
+            );
+        }
+        Ok(expr)
+    }
+
+    fn parse_primary(&mut self) -> Result<Expr> {
+        match self.next()? {
+            (_, Token::Ident(ident)) => Ok(Expr::Ident(ident)),
+            (_, Token::Number(n)) => Ok(Expr::Const(n)),
+            (_, Token::LParen) => {
+                let expr = self.parse_expr(0)?;
+                self.expect(Token::RParen)?;
+                Ok(expr)
+            }
+            (position, token) => Err(QueryError::ParseError {
+                position,
+                message: format!("unexpected {:?}", token),
+            }),
+        }
+    }
+}
+
+// An expression linked to the signals of a waveform
+#[derive(Debug, Clone)]
+enum Linked {
+    Signal(Box<TypedSignal>),
+    Const(i128),
+    Not(Box<Linked>),
+    BitNot(Box<Linked>),
+    Neg(Box<Linked>),
+    LogicAnd(Box<Linked>, Box<Linked>),
+    LogicOr(Box<Linked>, Box<Linked>),
+    Op(Opcode, Vec<Linked>),
+}
+
+/// An interval of time where a query holds: from `start` (included) to `end` (excluded).
+///
+/// If the query holds until the end of the trace, `end` is the time of the last change.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Interval {
+    pub start: u64,
+    pub end: u64,
+}
+
+/// A predicate over the typed variables of a waveform.
+///
+/// ```ignore
+/// let query = Query::new(&db, "Top.io.in.valid && Top.io.in.bits.op == ADD")?;
+/// let intervals = query.intervals();
+/// ```
+pub struct Query<'a> {
+    db: &'a WaveformDb,
+    expr: Linked,
+}
+
+impl<'a> Query<'a> {
+    /// Parse an expression and link its typed paths and enum variants to a waveform.
+    pub fn new(db: &'a WaveformDb, expr: &str) -> Result<Self> {
+        Self::from_expr(db, &Expr::parse(expr)?)
+    }
+
+    /// Link the typed paths and enum variants of an expression to a waveform.
+    ///
+    /// An identifier that is not a typed path is an enum variant: it is resolved using the
+    /// enum of the variable it is compared with, or the enums of all the variables in the expression.
+    pub fn from_expr(db: &'a WaveformDb, expr: &Expr) -> Result<Self> {
+        let mut signals = Vec::new();
+        collect_signals(db, expr, &mut signals);
+        let expr = link(db, expr, None, &signals)?;
+        Ok(Self { db, expr })
+    }
+
+    /// Return whether the query holds at `time`. Unknown values (`x`, `z`) never hold.
+    pub fn holds_at(&self, time: u64) -> bool {
+        eval(self.db, &self.expr, time).is_some_and(|v| v != 0)
+    }
+
+    /// Return the intervals of time where the query holds.
+    pub fn intervals(&self) -> Vec<Interval> {
+        let mut id_codes = HashSet::new();
+        collect_id_codes(&self.expr, &mut id_codes);
+        let mut times: Vec<u64> = id_codes
+            .into_iter()
+            .filter_map(|id_code| self.db.signal_changes(id_code))
+            .flat_map(|changes| changes.iter().map(|(time, _)| time))
+            .chain(std::iter::once(0))
+            .collect();
+        times.sort_unstable();
+        times.dedup();
+
+        let mut intervals = Vec::new();
+        let mut start = None;
+        for &time in &times {
+            match (self.holds_at(time), start) {
+                (true, None) => start = Some(time),
+                (false, Some(s)) => {
+                    intervals.push(Interval {
+                        start: s,
+                        end: time,
+                    });
+                    start = None;
+                }
+                _ => {}
+            }
+        }
+        if let Some(start) = start {
+            intervals.push(Interval {
+                start,
+                end: self.db.end_time(),
+            });
+        }
+        intervals
+    }
+
+    /// Return the clock cycles (with the time of their rising edge) where the query holds.
+    pub fn cycles(&self, sampler: &ClockSampler) -> Vec<(u64, u64)> {
+        sampler
+            .rising_edges()
+            .into_iter()
+            .enumerate()
+            .filter(|(_, edge)| self.holds_at(sampler.sample_time(*edge)))
+            .map(|(cycle, edge)| (cycle as u64, edge))
+            .collect()
+    }
+}
+
+// Collect the signals of the typed paths in an expression.
+fn collect_signals(db: &WaveformDb, expr: &Expr, signals: &mut Vec<TypedSignal>) {
+    match expr {
+        Expr::Ident(ident) => {
+            if let Ok(signal) = db.find_signal(ident) {
+                signals.push(signal);
+            }
+        }
+        Expr::Const(_) => {}
+        Expr::Not(e) | Expr::BitNot(e) | Expr::Neg(e) => collect_signals(db, e, signals),
+        Expr::LogicAnd(a, b) | Expr::LogicOr(a, b) => {
+            collect_signals(db, a, signals);
+            collect_signals(db, b, signals);
+        }
+        Expr::Op(_, operands) => operands
+            .iter()
+            .for_each(|e| collect_signals(db, e, signals)),
+    }
+}
+
+// Return the encoding of an enum variant in the enum of a signal.
+fn enum_value(signal: &TypedSignal, name: &str) -> Option<i64> {
+    let enum_val_map = signal.variable.enum_val_map.as_ref()?;
+    let enum_val_map = enum_val_map.read().unwrap();
+    enum_val_map
+        .iter()
+        .find(|(_, n)| *n == name)
+        .map(|(value, _)| *value)
+}
+
+// Link an expression to the signals of a waveform. `context` is the signal the expression is compared with.
+fn link(
+    db: &WaveformDb,
+    expr: &Expr,
+    context: Option<&TypedSignal>,
+    signals: &[TypedSignal],
+) -> Result<Linked> {
+    let link_box = |e: &Expr| link(db, e, None, signals).map(Box::new);
+    let linked = match expr {
+        Expr::Ident(ident) => {
+            if let Ok(signal) = db.find_signal(ident) {
+                return Ok(Linked::Signal(Box::new(signal)));
+            }
+            // The index of a ground variable selects one of its bits: `count[2]`
+            if let Some((path, bit)) = split_last_index(ident) {
+                if db.find_signal(path).is_ok() {
+                    let value = link(db, &Expr::Ident(path.to_string()), None, signals)?;
+                    let bit = Linked::Const(bit);
+                    return Ok(Linked::Op(Opcode::Extract, vec![value, bit.clone(), bit]));
+                }
+            }
+            // An enum variant: the compared signal has the priority
+            let value = context
+                .and_then(|signal| enum_value(signal, ident))
+                .or_else(|| signals.iter().find_map(|s| enum_value(s, ident)))
+                .ok_or_else(|| QueryError::UnknownIdentifier(ident.clone()))?;
+            Linked::Const(value as i128)
+        }
+        Expr::Const(c) => Linked::Const(*c),
+        Expr::Not(e) => Linked::Not(link_box(e)?),
+        Expr::BitNot(e) => Linked::BitNot(link_box(e)?),
+        Expr::Neg(e) => Linked::Neg(link_box(e)?),
+        Expr::LogicAnd(a, b) => Linked::LogicAnd(link_box(a)?, link_box(b)?),
+        Expr::LogicOr(a, b) => Linked::LogicOr(link_box(a)?, link_box(b)?),
+        Expr::Op(opcode, operands) => {
+            // The enum variants compared with a signal are resolved with its enum
+            let signal_of = |e: &Expr| match e {
+                Expr::Ident(ident) => db.find_signal(ident).ok(),
+                _ => None,
+            };
+            let linked = match operands.as_slice() {
+                [a, b] => vec![
+                    link(db, a, signal_of(b).as_ref(), signals)?,
+                    link(db, b, signal_of(a).as_ref(), signals)?,
+                ],
+                _ => operands
+                    .iter()
+                    .map(|e| link(db, e, None, signals))
+                    .collect::<Result<_>>()?,
+            };
+            Linked::Op(opcode.clone(), linked)
+        }
+    };
+    Ok(linked)
+}
+
+// Split a path ending with an index `[n]` in the rest of the path and the index.
+fn split_last_index(path: &str) -> Option<(&str, i128)> {
+    let (path, index) = path.strip_suffix(']')?.rsplit_once('[')?;
+    let index: i128 = index.parse().ok()?;
+    (0..128).contains(&index).then_some((path, index))
+}
+
+fn collect_id_codes(expr: &Linked, id_codes: &mut HashSet<vcd::IdCode>) {
+    match expr {
+        Linked::Signal(signal) => id_codes.extend(signal.id_codes()),
+        Linked::Const(_) => {}
+        Linked::Not(e) | Linked::BitNot(e) | Linked::Neg(e) => collect_id_codes(e, id_codes),
+        Linked::LogicAnd(a, b) | Linked::LogicOr(a, b) => {
+            collect_id_codes(a, id_codes);
+            collect_id_codes(b, id_codes);
+        }
+        Linked::Op(_, operands) => operands.iter().for_each(|e| collect_id_codes(e, id_codes)),
+    }
+}
+
+// The width of an expression, if it is known: a signal or a bit range.
+fn self_width(expr: &Linked) -> Option<u32> {
+    match expr {
+        Linked::Signal(signal) => u32::try_from(signal.variable.kind.find_width()).ok(),
+        Linked::Op(Opcode::Extract, operands) => match operands.as_slice() {
+            [_, Linked::Const(hi), Linked::Const(lo)] => {
+                u32::try_from(hi.checked_sub(*lo)?.checked_add(1)?).ok()
+            }
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+// The mask of the `width` least significant bits.
+fn mask(width: u32) -> u128 {
+    u128::MAX
+        .checked_shr(128u32.saturating_sub(width))
+        .unwrap_or(0)
+}
+
+// Evaluate an expression at `time`. `None` is an unknown value.
+fn eval(db: &WaveformDb, expr: &Linked, time: u64) -> Option<i128> {
+    let truth = |e: &Linked| eval(db, e, time).map(|v| v != 0);
+    match expr {
+        Linked::Signal(signal) => {
+            let bits = db.signal_bits_at(signal, time);
+            let uint = value::parse_bits(&bits)?;
+            match signal.variable.numeric_kind {
+                NumericKind::Signed | NumericKind::FixedPoint { .. } => {
+                    Some(value::sign_extend(uint, bits.len() as u32))
+                }
+                NumericKind::Unsigned | NumericKind::Real => Some(uint as i128),
+            }
+        }
+        Linked::Const(c) => Some(*c),
+        Linked::Not(e) => truth(e).map(|v| !v as i128),
+        Linked::BitNot(e) => eval(db, e, time).map(|v| !v),
+        Linked::Neg(e) => eval(db, e, time).map(|v| v.wrapping_neg()),
+        // A known false (true) operand is enough for && (||)
+        Linked::LogicAnd(a, b) => match (truth(a), truth(b)) {
+            (Some(false), _) | (_, Some(false)) => Some(0),
+            (Some(true), Some(true)) => Some(1),
+            _ => None,
+        },
+        Linked::LogicOr(a, b) => match (truth(a), truth(b)) {
+            (Some(true), _) | (_, Some(true)) => Some(1),
+            (Some(false), Some(false)) => Some(0),
+            _ => None,
+        },
+        Linked::Op(Opcode::Mux, operands) => match operands.as_slice() {
+            [c, a, b] => {
+                if truth(c)? {
+                    eval(db, a, time)
+                } else {
+                    eval(db, b, time)
+                }
+            }
+            _ => None,
+        },
+        Linked::Op(Opcode::Extract, operands) => match operands.as_slice() {
+            [e, Linked::Const(hi), Linked::Const(lo)] => {
+                let (hi, lo) = (u32::try_from(*hi).ok()?, u32::try_from(*lo).ok()?);
+                let width = hi.checked_sub(lo)?.checked_add(1)?.min(128);
+                let v = eval(db, e, time)? as u128;
+                Some((v.checked_shr(lo).unwrap_or(0) & mask(width)) as i128)
+            }
+            _ => None,
+        },
+        Linked::Op(opcode, operands) => {
+            let [a_expr, b_expr] = operands.as_slice() else {
+                return None;
+            };
+            let (a, b) = (eval(db, a_expr, time)?, eval(db, b_expr, time)?);
+            let result = match opcode {
+                Opcode::And => a & b,
+                Opcode::Or => a | b,
+                Opcode::UnaryOrXor => a ^ b,
+                Opcode::Add => a.wrapping_add(b),
+                Opcode::Sub => a.wrapping_sub(b),
+                Opcode::Mul => a.wrapping_mul(b),
+                Opcode::Div => a.checked_div(b)?,
+                Opcode::Mod => a.checked_rem(b)?,
+                Opcode::ShiftLeft => a.checked_shl(u32::try_from(b).ok()?).unwrap_or(0),
+                // A logical shift of the bits of the operand, not of its sign extension
+                Opcode::ShiftRight => {
+                    let bits = (a as u128) & mask(self_width(a_expr).unwrap_or(128));
+                    (bits.checked_shr(u32::try_from(b).ok()?).unwrap_or(0)) as i128
+                }
+                Opcode::ShiftRightSigned => a >> u32::try_from(b).ok()?.min(127),
+                Opcode::Eq => (a == b) as i128,
+                Opcode::NotEq => (a != b) as i128,
+                Opcode::LessThan => (a < b) as i128,
+                Opcode::GreaterThan => (a > b) as i128,
+                Opcode::LessEq => (a <= b) as i128,
+                Opcode::GreaterEq => (a >= b) as i128,
+                // Not produced by the parser (the 4-state comparisons are rejected), Mux and
+                // Extract have their own arms
+                Opcode::Struct
+                | Opcode::Concat
+                | Opcode::Replicate
+                | Opcode::CEq
+                | Opcode::CNotEq
+                | Opcode::WEq
+                | Opcode::WNotEq
+                | Opcode::Mux
+                | Opcode::Extract => return None,
+            };
+            Some(result)
+        }
+    }
+}
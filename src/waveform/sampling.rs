@@ -0,0 +1,212 @@
+use crate::tyvcd::value::DecodedValue;
+
+use super::db::{TypedSignal, WaveformDb, WaveformError};
+use super::hierarchy::TypedHierarchy;
+
+type Result<T> = std::result::Result<T, WaveformError>;
+
+/// Select a typed variable in a [WaveformDb].
+#[derive(Debug, Clone, PartialEq)]
+pub enum SignalSelector {
+    /// The variable at a typed path (i.e. `Top.dut.clock`)
+    Path(String),
+    /// The first variable in a scope whose base type name matches (i.e. `Clock` for `IO[Clock]`)
+    Type { scope: String, type_name: String },
+    /// The clock of a scope: the first variable with `Clock` type
+    Clock(String),
+    /// The reset of a scope: the first variable with `Reset` or `AsyncReset` type,
+    /// otherwise the `Bool` variable named `reset`
+    Reset(String),
+}
+
+impl SignalSelector {
+    /// Find the selected variable and link it to the signals of the trace.
+    pub fn find<H: AsRef<TypedHierarchy>>(&self, hierarchy: &H) -> Result<TypedSignal> {
+        let db = hierarchy.as_ref();
+        let find_in_scope = |scope: &str, matches: &dyn Fn(&TypedSignal) -> bool| {
+            db.signals_in_scope(scope)?
+                .into_iter()
+                .find(|signal| matches(signal))
+                .ok_or_else(|| WaveformError::PathNotFound(format!("{:?}", self)))
+        };
+        let base_type_name =
+            |signal: &TypedSignal| signal.variable.high_level_info.base_type_name().to_string();
+
+        match self {
+            SignalSelector::Path(path) => db.find_signal(path),
+            SignalSelector::Type { scope, type_name } => {
+                find_in_scope(scope, &|signal| base_type_name(signal) == *type_name)
+            }
+            SignalSelector::Clock(scope) => {
+                find_in_scope(scope, &|signal| base_type_name(signal) == "Clock")
+            }
+            SignalSelector::Reset(scope) => find_in_scope(scope, &|signal| {
+                matches!(base_type_name(signal).as_str(), "Reset" | "AsyncReset")
+            })
+            .or_else(|_| {
+                find_in_scope(scope, &|signal| {
+                    signal.variable.name == "reset" && base_type_name(signal) == "Bool"
+                })
+            }),
+        }
+    }
+}
+
+/// The point, relative to a rising edge of the clock, where the values are sampled.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum SamplingPoint {
+    /// The values right before the edge: the ones seen by the registers (default)
+    #[default]
+    BeforeEdge,
+    /// The values at the time of the edge, after the changes at that time
+    AtEdge,
+}
+
+/// The values of the sampled variables in a clock cycle.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CycleSnapshot {
+    /// The number of the cycle, starting from 0 at the first rising edge
+    pub cycle: u64,
+    /// The time of the rising edge
+    pub time: u64,
+    /// Whether the reset was active when the values were sampled
+    pub in_reset: bool,
+    /// The typed path and the value of each sampled variable
+    pub values: Vec<(String, DecodedValue)>,
+}
+
+impl CycleSnapshot {
+    /// Return the sampled value of the variable at `path`.
+    pub fn get(&self, path: &str) -> Option<&DecodedValue> {
+        self.values.iter().find(|(p, _)| p == path).map(|(_, v)| v)
+    }
+}
+
+/// Sample typed variables on the rising edges of a clock.
+///
+/// ```ignore
+/// let sampler = ClockSampler::new(&db, &SignalSelector::Clock("Top".to_string()))?
+///     .with_reset(&SignalSelector::Reset("Top".to_string()), true)?
+///     .with_variable(&SignalSelector::Path("Top.io.out".to_string()))?;
+/// for snapshot in sampler.sample() { ... }
+/// ```
+pub struct ClockSampler<'a> {
+    db: &'a WaveformDb,
+    clock: TypedSignal,
+    /// The reset and its active level
+    reset: Option<(TypedSignal, bool)>,
+    variables: Vec<TypedSignal>,
+    sampling_point: SamplingPoint,
+}
+
+impl<'a> ClockSampler<'a> {
+    /// Create a sampler on the rising edges of a 1-bit clock.
+    pub fn new(db: &'a WaveformDb, clock: &SignalSelector) -> Result<Self> {
+        let clock = Self::find_bit(db, clock)?;
+        Ok(Self {
+            db,
+            clock,
+            reset: None,
+            variables: Vec::new(),
+            sampling_point: SamplingPoint::default(),
+        })
+    }
+
+    // Find a variable that must be 1 bit wide (clock and reset).
+    fn find_bit(db: &WaveformDb, selector: &SignalSelector) -> Result<TypedSignal> {
+        let signal = selector.find(db)?;
+        if signal.variable.kind.find_width() != 1 {
+            return Err(WaveformError::InvalidSignal(signal.path));
+        }
+        Ok(signal)
+    }
+
+    /// Use a 1-bit reset to mark the cycles in reset. `active_high` is its active level.
+    pub fn with_reset(mut self, reset: &SignalSelector, active_high: bool) -> Result<Self> {
+        self.reset = Some((Self::find_bit(self.db, reset)?, active_high));
+        Ok(self)
+    }
+
+    /// Add a variable to sample.
+    pub fn with_variable(mut self, variable: &SignalSelector) -> Result<Self> {
+        self.variables.push(variable.find(self.db)?);
+        Ok(self)
+    }
+
+    /// Add all the variables declared in the scope at `scope_path` to sample.
+    pub fn with_scope(mut self, scope_path: &str) -> Result<Self> {
+        self.variables.extend(self.db.signals_in_scope(scope_path)?);
+        Ok(self)
+    }
+
+    /// Set where the values are sampled with respect to the clock edges.
+    pub fn with_sampling_point(mut self, sampling_point: SamplingPoint) -> Self {
+        self.sampling_point = sampling_point;
+        self
+    }
+
+    /// Return the clock variable.
+    pub fn get_clock(&self) -> &TypedSignal {
+        &self.clock
+    }
+
+    /// Return the database the sampler reads the values from.
+    pub fn get_db(&self) -> &'a WaveformDb {
+        self.db
+    }
+
+    /// Return the times of the rising edges of the clock (transitions from 0 to 1).
+    pub fn rising_edges(&self) -> Vec<u64> {
+        let (zero, one) = (DecodedValue::Unsigned(0), DecodedValue::Unsigned(1));
+        let mut last = None;
+        let mut edges = Vec::new();
+        for (time, value) in self.db.changes(&self.clock) {
+            if last.as_ref() == Some(&zero) && value == one {
+                edges.push(time);
+            }
+            last = Some(value);
+        }
+        edges
+    }
+
+    /// Return the time at which the values of the rising edge at `edge` are sampled.
+    pub fn sample_time(&self, edge: u64) -> u64 {
+        match self.sampling_point {
+            // An edge is always preceded by a 0 value, so it is never at time 0
+            SamplingPoint::BeforeEdge => edge.saturating_sub(1),
+            SamplingPoint::AtEdge => edge,
+        }
+    }
+
+    /// Return whether the reset is active at `time`. It is always false without a reset.
+    pub fn in_reset_at(&self, time: u64) -> bool {
+        self.reset.as_ref().is_some_and(|(reset, active_high)| {
+            self.db.signal_value_at(reset, time) == DecodedValue::Unsigned(*active_high as u128)
+        })
+    }
+
+    /// Return the snapshot of the sampled variables at each rising edge of the clock.
+    pub fn sample(&self) -> Vec<CycleSnapshot> {
+        self.rising_edges()
+            .into_iter()
+            .enumerate()
+            .map(|(cycle, time)| {
+                let sample_time = self.sample_time(time);
+                let values = self
+                    .variables
+                    .iter()
+                    .map(|signal| {
+                        let value = self.db.signal_value_at(signal, sample_time);
+                        (signal.path.clone(), value)
+                    })
+                    .collect();
+                CycleSnapshot {
+                    cycle: cycle as u64,
+                    time,
+                    in_reset: self.in_reset_at(sample_time),
+                    values,
+                }
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,388 @@
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read, Seek};
+use std::path::Path;
+use std::sync::mpsc::{self, Receiver};
+
+use fst_reader::{
+    FstFilter, FstHierarchyEntry, FstReader, FstScopeType, FstSignalValue, FstVarType,
+};
+use vcd::{Command, Header, IdCode, Parser, ScopeType, TimescaleUnit, Value, VarType, Writer};
+
+/// The format of a trace file.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TraceFormat {
+    /// Value Change Dump
+    Vcd,
+    /// Fast Signal Trace
+    Fst,
+}
+
+impl TraceFormat {
+    /// Select the format from the extension of a file (`.vcd` or `.fst`).
+    pub fn from_path(path: &Path) -> Option<Self> {
+        match path.extension()?.to_str()? {
+            "vcd" => Some(TraceFormat::Vcd),
+            "fst" => Some(TraceFormat::Fst),
+            _ => None,
+        }
+    }
+
+    /// Detect the format of a file from its extension or, if unknown, from its content.
+    pub fn detect(path: &Path) -> io::Result<Self> {
+        if let Some(format) = Self::from_path(path) {
+            return Ok(format);
+        }
+        let mut file = BufReader::new(File::open(path)?);
+        if fst_reader::is_fst_file(&mut file) {
+            Ok(TraceFormat::Fst)
+        } else {
+            Ok(TraceFormat::Vcd)
+        }
+    }
+}
+
+/// A source of the header and of the value changes of a trace.
+///
+/// It follows the interface of [vcd::Parser]: the header is parsed first, then the commands
+/// (timestamps and value changes) are returned in order of time. The commands are read on demand:
+/// the sources never keep the whole trace in memory.
+pub trait WaveformSource: Iterator<Item = io::Result<Command>> {
+    /// Parse the header of the trace. It must be called before reading the commands.
+    fn parse_header(&mut self) -> io::Result<Header>;
+}
+
+impl<R: BufRead> WaveformSource for Parser<R> {
+    fn parse_header(&mut self) -> io::Result<Header> {
+        Parser::parse_header(self)
+    }
+}
+
+impl<S: WaveformSource + ?Sized> WaveformSource for Box<S> {
+    fn parse_header(&mut self) -> io::Result<Header> {
+        (**self).parse_header()
+    }
+}
+
+/// Open a trace file, VCD or FST.
+pub fn open(path: &Path) -> io::Result<Box<dyn WaveformSource>> {
+    let reader = BufReader::new(File::open(path)?);
+    match TraceFormat::detect(path)? {
+        TraceFormat::Vcd => Ok(Box::new(vcd_parser(reader))),
+        TraceFormat::Fst => Ok(Box::new(FstSource::new(reader)?)),
+    }
+}
+
+/// Create a parser of a VCD read from `reader`, with its `$scope struct` read as modules (see
+/// [StructScopesAsModules]).
+pub fn vcd_parser<R: BufRead>(reader: R) -> Parser<StructScopesAsModules<R>> {
+    Parser::new(StructScopesAsModules::new(reader))
+}
+
+// The number of decoded commands of an FST waiting to be read
+const FST_COMMANDS_BOUND: usize = 4096;
+
+// The kind of values of an FST signal
+#[derive(Clone, Copy)]
+enum FstSignalKind {
+    Bits,
+    Real,
+    String,
+}
+
+/// Read an FST trace as a [WaveformSource].
+///
+/// The hierarchy of the FST is converted to a VCD [Header]: each signal handle becomes an
+/// [IdCode] and the aliases of a signal share the same id code.
+/// The value changes are decoded by a background thread while they are read: only the section
+/// being decoded and a bounded number of commands are kept in memory.
+pub struct FstSource {
+    header: Option<Header>,
+    commands: Receiver<io::Result<Command>>,
+}
+
+impl FstSource {
+    /// Read an FST trace from `reader`.
+    pub fn new<R: BufRead + Seek + Send + 'static>(reader: R) -> io::Result<Self> {
+        let mut reader = FstReader::open_and_read_time_table(reader).map_err(io::Error::other)?;
+
+        // Write the hierarchy as a VCD header and parse it back
+        let fst_header = reader.get_header();
+        let mut header_bytes = Vec::new();
+        let mut writer = Writer::new(&mut header_bytes);
+        writer.date(&fst_header.date)?;
+        writer.version(&fst_header.version)?;
+        let (ts, unit) = Self::timescale(fst_header.timescale_exponent);
+        writer.timescale(ts, unit)?;
+
+        let mut kinds: Vec<FstSignalKind> = Vec::new();
+        let mut result = Ok(());
+        reader
+            .read_hierarchy(|entry| {
+                if result.is_err() {
+                    return;
+                }
+                result = match entry {
+                    FstHierarchyEntry::Scope { tpe, name, .. } => {
+                        writer.scope_def(Self::scope_type(tpe), &name)
+                    }
+                    FstHierarchyEntry::UpScope => writer.upscope(),
+                    FstHierarchyEntry::Var {
+                        tpe,
+                        name,
+                        length,
+                        handle,
+                        ..
+                    } => {
+                        let kind = match tpe {
+                            _ if tpe.is_real() => FstSignalKind::Real,
+                            FstVarType::GenericString => FstSignalKind::String,
+                            _ => FstSignalKind::Bits,
+                        };
+                        let idx = handle.get_index();
+                        if kinds.len() <= idx {
+                            kinds.resize(idx + 1, FstSignalKind::Bits);
+                        }
+                        kinds[idx] = kind;
+                        // The name may contain the index of the vector (i.e. `data [7:0]`)
+                        let id_code = IdCode::from(idx as u32);
+                        writer.var_def(Self::var_type(tpe), length, id_code, &name, None)
+                    }
+                    _ => Ok(()),
+                };
+            })
+            .map_err(io::Error::other)?;
+        result?;
+        writer.enddefinitions()?;
+        let header = Parser::new(header_bytes.as_slice()).parse_header()?;
+
+        // Convert the value changes to VCD commands
+        let (sender, commands) = mpsc::sync_channel(FST_COMMANDS_BOUND);
+        std::thread::spawn(move || {
+            // The source has been dropped: skip the remaining changes
+            let mut is_closed = false;
+            let mut last_time = None;
+            let result = reader.read_signals(&FstFilter::all(), |time, handle, value| {
+                if is_closed {
+                    return;
+                }
+                if last_time != Some(time) {
+                    is_closed |= sender.send(Ok(Command::Timestamp(time))).is_err();
+                    last_time = Some(time);
+                }
+                let idx = handle.get_index();
+                let kind = kinds.get(idx).copied().unwrap_or(FstSignalKind::Bits);
+                let command = Self::command(IdCode::from(idx as u32), value, kind);
+                is_closed |= sender.send(Ok(command)).is_err();
+            });
+            if let Err(err) = result {
+                let _ = sender.send(Err(io::Error::other(err)));
+            }
+        });
+
+        Ok(Self {
+            header: Some(header),
+            commands,
+        })
+    }
+
+    // Convert a value change to a VCD command.
+    fn command(id_code: IdCode, value: FstSignalValue, kind: FstSignalKind) -> Command {
+        match (value, kind) {
+            (FstSignalValue::Real(value), _) => Command::ChangeReal(id_code, value),
+            (FstSignalValue::String(value), FstSignalKind::String) => {
+                Command::ChangeString(id_code, String::from_utf8_lossy(value).to_string())
+            }
+            (FstSignalValue::String(value), FstSignalKind::Real) => {
+                let value = String::from_utf8_lossy(value);
+                Command::ChangeReal(id_code, value.parse().unwrap_or(f64::NAN))
+            }
+            (FstSignalValue::String(value), FstSignalKind::Bits) => {
+                let vector = Self::vector(value);
+                match vector.iter().next() {
+                    Some(value) if vector.len() == 1 => Command::ChangeScalar(id_code, value),
+                    _ => Command::ChangeVector(id_code, vector),
+                }
+            }
+        }
+    }
+
+    // Convert the bits of an FST value. The states not supported by VCD are converted to `x`.
+    fn vector(value: &[u8]) -> vcd::Vector {
+        value
+            .iter()
+            .map(|c| match c {
+                b'0' => Value::V0,
+                b'1' => Value::V1,
+                b'z' | b'Z' => Value::Z,
+                _ => Value::X,
+            })
+            .collect::<Vec<Value>>()
+            .into()
+    }
+
+    // Convert a timescale of 10^exponent seconds.
+    fn timescale(exponent: i8) -> (u32, TimescaleUnit) {
+        let exponent = exponent.clamp(-15, 2) as i32;
+        let unit_exponent = exponent.div_euclid(3) * 3;
+        let unit_exponent = unit_exponent.min(0);
+        let unit = match unit_exponent {
+            0 => TimescaleUnit::S,
+            -3 => TimescaleUnit::MS,
+            -6 => TimescaleUnit::US,
+            -9 => TimescaleUnit::NS,
+            -12 => TimescaleUnit::PS,
+            _ => TimescaleUnit::FS,
+        };
+        (10u32.pow((exponent - unit_exponent) as u32), unit)
+    }
+
+    fn scope_type(tpe: FstScopeType) -> ScopeType {
+        match tpe {
+            FstScopeType::Task => ScopeType::Task,
+            FstScopeType::Function => ScopeType::Function,
+            FstScopeType::Begin => ScopeType::Begin,
+            FstScopeType::Fork => ScopeType::Fork,
+            _ => ScopeType::Module,
+        }
+    }
+
+    fn var_type(tpe: FstVarType) -> VarType {
+        match tpe {
+            FstVarType::Event => VarType::Event,
+            FstVarType::Integer => VarType::Integer,
+            FstVarType::Parameter => VarType::Parameter,
+            FstVarType::Reg => VarType::Reg,
+            FstVarType::Supply0 => VarType::Supply0,
+            FstVarType::Supply1 => VarType::Supply1,
+            FstVarType::Time => VarType::Time,
+            FstVarType::Tri => VarType::Tri,
+            FstVarType::TriAnd => VarType::TriAnd,
+            FstVarType::TriOr => VarType::TriOr,
+            FstVarType::TriReg => VarType::TriReg,
+            FstVarType::Tri0 => VarType::Tri0,
+            FstVarType::Tri1 => VarType::Tri1,
+            FstVarType::Wand => VarType::WAnd,
+            FstVarType::Wor => VarType::WOr,
+            FstVarType::GenericString => VarType::String,
+            _ if tpe.is_real() => VarType::Real,
+            _ => VarType::Wire,
+        }
+    }
+}
+
+impl Iterator for FstSource {
+    type Item = io::Result<Command>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        // The channel is closed when all the changes have been read
+        self.commands.recv().ok()
+    }
+}
+
+impl WaveformSource for FstSource {
+    fn parse_header(&mut self) -> io::Result<Header> {
+        self.header.take().ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "the header has already been parsed",
+            )
+        })
+    }
+}
+
+/// A reader of a VCD that reads the `$scope struct` declarations (written by the hierarchy
+/// aggregate mode of [crate::vcd_rewrite::VcdRewriter]) as `$scope module`, since [vcd::Parser]
+/// does not support them.
+///
+/// Only the declarations of the header are changed, the comments and the value changes are read
+/// as they are.
+pub struct StructScopesAsModules<R: BufRead> {
+    reader: R,
+    // The patched line of the header being read and the position in it
+    line: Vec<u8>,
+    pos: usize,
+    // The state of the header
+    is_header_done: bool,
+    is_comment: bool,
+    is_scope_type: bool,
+}
+
+impl<R: BufRead> StructScopesAsModules<R> {
+    pub fn new(reader: R) -> Self {
+        Self {
+            reader,
+            line: Vec::new(),
+            pos: 0,
+            is_header_done: false,
+            is_comment: false,
+            is_scope_type: false,
+        }
+    }
+
+    // Read the next line of the header and replace the `struct` scope types.
+    fn read_header_line(&mut self) -> io::Result<()> {
+        let mut line = Vec::new();
+        self.reader.read_until(b'\n', &mut line)?;
+        if line.is_empty() {
+            self.is_header_done = true;
+        }
+        let line = String::from_utf8_lossy(&line);
+
+        let mut patched = String::with_capacity(line.len());
+        for piece in line.split_inclusive(char::is_whitespace) {
+            let token = piece.trim_end();
+            if token.is_empty() || self.is_header_done {
+                patched.push_str(piece);
+                continue;
+            }
+            let is_scope_type = std::mem::replace(&mut self.is_scope_type, token == "$scope");
+            match token {
+                "$end" if self.is_comment => self.is_comment = false,
+                _ if self.is_comment => {}
+                "$comment" => self.is_comment = true,
+                "$enddefinitions" => self.is_header_done = true,
+                "struct" if is_scope_type => {
+                    patched.push_str("module");
+                    patched.push_str(&piece[token.len()..]);
+                    continue;
+                }
+                _ => {}
+            }
+            patched.push_str(piece);
+        }
+        self.line = patched.into_bytes();
+        self.pos = 0;
+        Ok(())
+    }
+}
+
+impl<R: BufRead> Read for StructScopesAsModules<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let available = self.fill_buf()?;
+        let len = available.len().min(buf.len());
+        buf[..len].copy_from_slice(&available[..len]);
+        self.consume(len);
+        Ok(len)
+    }
+}
+
+impl<R: BufRead> BufRead for StructScopesAsModules<R> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        if self.pos >= self.line.len() && !self.is_header_done {
+            self.read_header_line()?;
+        }
+        if self.pos < self.line.len() {
+            Ok(&self.line[self.pos..])
+        } else {
+            self.reader.fill_buf()
+        }
+    }
+
+    fn consume(&mut self, amt: usize) {
+        if self.pos < self.line.len() {
+            self.pos += amt;
+        } else {
+            self.reader.consume(amt);
+        }
+    }
+}
@@ -0,0 +1,312 @@
+use crate::tyvcd::spec::{Variable, VariableKind};
+use crate::tyvcd::value::DecodedValue;
+
+use super::db::{TypedSignal, WaveformDb, WaveformError};
+use super::sampling::{ClockSampler, SignalSelector};
+
+type Result<T> = std::result::Result<T, WaveformError>;
+
+/// The protocol of a ready/valid bundle.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum HandshakeKind {
+    /// Chisel `Decoupled` (`DecoupledIO`): `ready`, `valid` and `bits`
+    Decoupled,
+    /// Chisel `Irrevocable` (`IrrevocableIO`): `ready`, `valid` and `bits`
+    Irrevocable,
+    /// Chisel `Valid` (`ValidIO`): `valid` and `bits`, it cannot be backpressured
+    Valid,
+}
+
+impl HandshakeKind {
+    /// Identify the protocol of a struct variable from its Chisel type and its fields.
+    ///
+    /// The type name must be a known one (i.e. `DecoupledIO` or `Valid`) and the variable must
+    /// have a 1-bit `valid` and a `bits` fields. A 1-bit `ready` field is required for
+    /// [HandshakeKind::Decoupled] and [HandshakeKind::Irrevocable].
+    pub fn from_variable(variable: &Variable) -> Option<Self> {
+        let has_ready = Self::handshake_fields(variable)?;
+        match (variable.high_level_info.base_type_name(), has_ready) {
+            ("DecoupledIO" | "Decoupled", true) => Some(HandshakeKind::Decoupled),
+            ("IrrevocableIO" | "Irrevocable", true) => Some(HandshakeKind::Irrevocable),
+            ("Valid" | "ValidIO", _) => Some(HandshakeKind::Valid),
+            _ => None,
+        }
+    }
+
+    /// Identify the protocol of a struct variable of any type from its fields.
+    ///
+    /// A known type name is identified as in [HandshakeKind::from_variable]. Otherwise, a struct
+    /// with a 1-bit `valid` and a `bits` fields is [HandshakeKind::Decoupled] if it has a 1-bit
+    /// `ready` field, [HandshakeKind::Valid] if it has not.
+    pub fn from_fields(variable: &Variable) -> Option<Self> {
+        Self::from_variable(variable).or_else(|| match Self::handshake_fields(variable)? {
+            true => Some(HandshakeKind::Decoupled),
+            false => Some(HandshakeKind::Valid),
+        })
+    }
+
+    // Return whether a struct with the `valid` and `bits` fields has a 1-bit `ready` field.
+    fn handshake_fields(variable: &Variable) -> Option<bool> {
+        let VariableKind::Struct { fields } = &variable.kind else {
+            return None;
+        };
+        let field = |name: &str| fields.iter().find(|f| f.name == name);
+        let is_bit = |name: &str| field(name).is_some_and(|f| f.kind.find_width() == 1);
+        if !is_bit("valid") || field("bits").is_none() {
+            return None;
+        }
+        Some(is_bit("ready"))
+    }
+}
+
+/// A ready/valid bundle linked to the signals of a trace.
+#[derive(Debug, Clone)]
+pub struct HandshakeBundle {
+    /// The typed path of the bundle
+    pub path: String,
+    /// The protocol of the bundle
+    pub kind: HandshakeKind,
+    valid: TypedSignal,
+    /// The ready of the bundle, [None] for [HandshakeKind::Valid]
+    ready: Option<TypedSignal>,
+    bits: TypedSignal,
+}
+
+impl HandshakeBundle {
+    /// Link the bundle at `path` to the signals of the trace.
+    ///
+    /// The bundle is selected explicitly: it can have any type name, its protocol is identified
+    /// with [HandshakeKind::from_fields].
+    pub fn find(db: &WaveformDb, path: &str) -> Result<Self> {
+        let signal = db.find_signal(path)?;
+        let kind = HandshakeKind::from_fields(&signal.variable)
+            .ok_or_else(|| WaveformError::InvalidSignal(path.to_string()))?;
+        let field = |name: &str| db.find_signal(&format!("{}.{}", path, name));
+
+        Ok(Self {
+            path: path.to_string(),
+            kind,
+            valid: field("valid")?,
+            ready: match kind {
+                HandshakeKind::Valid => None,
+                _ => Some(field("ready")?),
+            },
+            bits: field("bits")?,
+        })
+    }
+
+    /// Find all the ready/valid bundles in the variables of a trace, sorted by path.
+    ///
+    /// The bundles can be variables or fields of struct and vector variables.
+    /// The fields of a bundle are not explored.
+    /// Only the known Chisel types are detected (see [HandshakeKind::from_variable]).
+    pub fn detect(db: &WaveformDb) -> Vec<Self> {
+        Self::detect_with(db, HandshakeKind::from_variable)
+    }
+
+    /// Find all the ready/valid bundles like [HandshakeBundle::detect], including the structs of
+    /// any type with the fields of a bundle (see [HandshakeKind::from_fields]).
+    pub fn detect_by_fields(db: &WaveformDb) -> Vec<Self> {
+        Self::detect_with(db, HandshakeKind::from_fields)
+    }
+
+    // Find the bundles identified by `kind_of`.
+    fn detect_with(db: &WaveformDb, kind_of: fn(&Variable) -> Option<HandshakeKind>) -> Vec<Self> {
+        // Collect the paths of the bundles in a variable
+        fn collect(
+            variable: &Variable,
+            path: String,
+            kind_of: fn(&Variable) -> Option<HandshakeKind>,
+            paths: &mut Vec<String>,
+        ) {
+            if kind_of(variable).is_some() {
+                paths.push(path);
+                return;
+            }
+            match &variable.kind {
+                VariableKind::Struct { fields } | VariableKind::Vector { fields } => {
+                    for field in fields {
+                        collect(field, format!("{}.{}", path, field.name), kind_of, paths);
+                    }
+                }
+                VariableKind::Ground(_) | VariableKind::External => {}
+            }
+        }
+
+        let mut paths = Vec::new();
+        for scope_path in db.scope_paths() {
+            for signal in db.signals_in_scope(&scope_path).unwrap_or_default() {
+                collect(&signal.variable, signal.path, kind_of, &mut paths);
+            }
+        }
+        paths.sort();
+        paths
+            .into_iter()
+            .filter_map(|path| Self::find(db, &path).ok())
+            .collect()
+    }
+}
+
+/// A transaction fired on a ready/valid bundle: `valid` and `ready` were both high.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Transaction {
+    /// The time of the rising edge that fired the transaction
+    pub time: u64,
+    /// The clock cycle that fired the transaction
+    pub cycle: u64,
+    /// The value of the `bits` field
+    pub bits: DecodedValue,
+}
+
+/// The handshake statistics of a ready/valid bundle. Cycles in reset are not counted.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct HandshakeStats {
+    /// The number of cycles analyzed
+    pub cycles: u64,
+    /// The number of cycles with a transaction: `valid && ready`
+    pub fired: u64,
+    /// The number of cycles where the producer is stalled by backpressure: `valid && !ready`
+    pub stalled: u64,
+    /// The number of cycles where the consumer waits for data: `!valid && ready`
+    pub starved: u64,
+    /// The number of cycles without activity: `!valid && !ready`, or `!valid` for a bundle
+    /// without ready
+    pub idle: u64,
+    /// The longest sequence of consecutive stalled cycles
+    pub max_stall: u64,
+}
+
+impl HandshakeStats {
+    /// Return the fraction of the analyzed cycles that fired a transaction.
+    pub fn throughput(&self) -> f64 {
+        if self.cycles == 0 {
+            0.0
+        } else {
+            self.fired as f64 / self.cycles as f64
+        }
+    }
+
+    /// Return the fraction of the cycles with a valid `bits` that were stalled.
+    pub fn backpressure(&self) -> f64 {
+        let valid = self.fired + self.stalled;
+        if valid == 0 {
+            0.0
+        } else {
+            self.stalled as f64 / valid as f64
+        }
+    }
+}
+
+/// The transactions and the statistics of a ready/valid bundle.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BundleReport {
+    /// The typed path of the bundle
+    pub path: String,
+    /// The protocol of the bundle
+    pub kind: HandshakeKind,
+    /// The fired transactions in time order
+    pub transactions: Vec<Transaction>,
+    /// The handshake statistics
+    pub stats: HandshakeStats,
+}
+
+/// Extract the transactions of ready/valid bundles sampling them on the rising edges of a clock.
+pub struct TransactionAnalyzer<'a> {
+    sampler: ClockSampler<'a>,
+    bundles: Vec<HandshakeBundle>,
+}
+
+impl<'a> TransactionAnalyzer<'a> {
+    /// Create an analyzer on the rising edges of a clock, without bundles.
+    pub fn new(db: &'a WaveformDb, clock: &SignalSelector) -> Result<Self> {
+        Ok(Self {
+            sampler: ClockSampler::new(db, clock)?,
+            bundles: Vec::new(),
+        })
+    }
+
+    /// Ignore the cycles where the reset is active. `active_high` is its active level.
+    pub fn with_reset(mut self, reset: &SignalSelector, active_high: bool) -> Result<Self> {
+        self.sampler = self.sampler.with_reset(reset, active_high)?;
+        Ok(self)
+    }
+
+    /// Analyze the ready/valid bundle at `path`.
+    pub fn with_bundle(mut self, path: &str) -> Result<Self> {
+        self.bundles
+            .push(HandshakeBundle::find(self.sampler.get_db(), path)?);
+        Ok(self)
+    }
+
+    /// Analyze all the ready/valid bundles detected in the trace.
+    pub fn with_detected_bundles(mut self) -> Self {
+        self.bundles
+            .extend(HandshakeBundle::detect(self.sampler.get_db()));
+        self
+    }
+
+    /// Return the bundles to analyze.
+    pub fn get_bundles(&self) -> &[HandshakeBundle] {
+        &self.bundles
+    }
+
+    /// Replay the trace and return the report of each bundle.
+    pub fn analyze(&self) -> Vec<BundleReport> {
+        let db = self.sampler.get_db();
+        let edges = self.sampler.rising_edges();
+        let is_high = |signal: &TypedSignal, time: u64| {
+            db.signal_value_at(signal, time) == DecodedValue::Unsigned(1)
+        };
+
+        self.bundles
+            .iter()
+            .map(|bundle| {
+                let mut transactions = Vec::new();
+                let mut stats = HandshakeStats::default();
+                let mut stall = 0;
+
+                for (cycle, &time) in edges.iter().enumerate() {
+                    let sample_time = self.sampler.sample_time(time);
+                    if self.sampler.in_reset_at(sample_time) {
+                        continue;
+                    }
+                    let valid = is_high(&bundle.valid, sample_time);
+                    let ready = bundle
+                        .ready
+                        .as_ref()
+                        .map(|ready| is_high(ready, sample_time));
+
+                    stats.cycles += 1;
+                    // A bundle without ready always accepts data and never waits for it
+                    match (valid, ready) {
+                        (true, None | Some(true)) => {
+                            stats.fired += 1;
+                            transactions.push(Transaction {
+                                time,
+                                cycle: cycle as u64,
+                                bits: db.signal_value_at(&bundle.bits, sample_time),
+                            });
+                        }
+                        (true, Some(false)) => stats.stalled += 1,
+                        (false, Some(true)) => stats.starved += 1,
+                        (false, _) => stats.idle += 1,
+                    }
+
+                    stall = if valid && ready == Some(false) {
+                        stall + 1
+                    } else {
+                        0
+                    };
+                    stats.max_stall = stats.max_stall.max(stall);
+                }
+
+                BundleReport {
+                    path: bundle.path.clone(),
+                    kind: bundle.kind,
+                    transactions,
+                    stats,
+                }
+            })
+            .collect()
+    }
+}
@@ -1,6 +1,7 @@
 use std::collections::HashMap;
 use std::sync::Arc;
 use std::sync::RwLock;
+use tywaves_rs::hgldd::spec::Opcode;
 use tywaves_rs::tyvcd::{spec::*, trace_pointer::TraceValue};
 
 pub fn create_bar_single() -> TyVcd {
@@ -26,7 +27,7 @@ pub fn create_bar_single() -> TyVcd {
                     },
                 ],
             ),
-            &vec![],
+            &[],
         ))),
     );
     // inA
@@ -42,11 +43,13 @@ pub fn create_bar_single() -> TyVcd {
     // inB
     scopes.get("Bar").unwrap().write().unwrap().variables.push(
         Variable::new(
-            // op: *
-            TraceValue::RefTraceValues(vec![
-                TraceValue::RefTraceName("x".to_string()),
-                TraceValue::RefTraceName("x".to_string()),
-            ]),
+            TraceValue::Operation(
+                Opcode::Mul,
+                vec![
+                    TraceValue::RefTraceName("x".to_string()),
+                    TraceValue::RefTraceName("x".to_string()),
+                ],
+            ),
             String::from("outY"),
             TypeInfo::new("logic".to_string(), Vec::new()),
             VariableKind::Ground(32),
@@ -56,11 +59,13 @@ pub fn create_bar_single() -> TyVcd {
     // var1 => const
     scopes.get("Bar").unwrap().write().unwrap().variables.push(
         Variable::new(
-            // op: *
-            TraceValue::RefTraceValues(vec![
-                TraceValue::RefTraceName("x".to_string()),
-                TraceValue::RefTraceName("x".to_string()),
-            ]),
+            TraceValue::Operation(
+                Opcode::Mul,
+                vec![
+                    TraceValue::RefTraceName("x".to_string()),
+                    TraceValue::RefTraceName("x".to_string()),
+                ],
+            ),
             String::from("varZ"),
             TypeInfo::new("logic".to_string(), Vec::new()),
             VariableKind::Ground(32),
@@ -70,15 +75,19 @@ pub fn create_bar_single() -> TyVcd {
 
     scopes.get("Bar").unwrap().write().unwrap().variables.push(
         Variable::new(
-            // op: +
-            TraceValue::RefTraceValues(vec![
-                // op: *
-                TraceValue::RefTraceValues(vec![
-                    TraceValue::RefTraceName("x".to_string()),
+            TraceValue::Operation(
+                Opcode::Add,
+                vec![
+                    TraceValue::Operation(
+                        Opcode::Mul,
+                        vec![
+                            TraceValue::RefTraceName("x".to_string()),
+                            TraceValue::RefTraceName("x".to_string()),
+                        ],
+                    ),
                     TraceValue::RefTraceName("x".to_string()),
-                ]),
-                TraceValue::RefTraceName("x".to_string()),
-            ]),
+                ],
+            ),
             String::from("add"),
             TypeInfo::new("logic".to_string(), Vec::new()),
             VariableKind::Ground(32),
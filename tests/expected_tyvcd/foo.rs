@@ -17,7 +17,7 @@ pub fn create_foo_single_no_types() -> TyVcd {
             String::from("Foo"),
             // type info na, use the target language one
             TypeInfo::new("Foo".to_string(), Vec::new()),
-            &vec![],
+            &[],
         ))),
     );
     // inA
@@ -69,7 +69,7 @@ pub fn create_foo_single_no_types() -> TyVcd {
                 String::from("Bar"),
                 // type info na, use the target language one
                 TypeInfo::new("Bar".to_string(), Vec::new()),
-                &vec!["Foo".to_string()],
+                &["Foo".to_string()],
             ))),
         );
 
@@ -86,7 +86,7 @@ pub fn create_foo_single_no_types() -> TyVcd {
                 String::from("Bar"),
                 // type info na, use the target language one
                 TypeInfo::new("Bar".to_string(), Vec::new()),
-                &vec!["Foo".to_string()],
+                &["Foo".to_string()],
             ))),
         );
 
@@ -102,7 +102,7 @@ pub fn create_foo_single() -> TyVcd {
             String::from("Foo"),
             String::from("Foo"),
             TypeInfo::new("Foo".to_string(), Vec::new()),
-            &vec![],
+            &[],
         ))),
     );
     // inA
@@ -187,7 +187,7 @@ pub fn create_foo_single() -> TyVcd {
                 String::from("Bar"),
                 // type info na, use the target language one
                 TypeInfo::new("Bar".to_string(), Vec::new()),
-                &vec!["Foo".to_string()],
+                &["Foo".to_string()],
             ))),
         );
 
@@ -204,7 +204,7 @@ pub fn create_foo_single() -> TyVcd {
                 String::from("Bar"),
                 // type info na, use the target language one
                 TypeInfo::new("Bar".to_string(), Vec::new()),
-                &vec!["Foo".to_string()],
+                &["Foo".to_string()],
             ))),
         );
 
@@ -213,10 +213,18 @@ pub fn create_foo_single() -> TyVcd {
 
 // Create the TyVcd for the [[tests/inputs/tyvcd/foo]] directory.
 pub fn create_foo() -> TyVcd {
-    let foo = create_foo_single();
+    let foo_tyvcd = create_foo_single();
     let bar = super::bar::create_bar_single();
 
-    for (_, subscope_to_update) in &mut foo.scopes.get("Foo").unwrap().write().unwrap().subscopes {
+    for subscope_to_update in foo_tyvcd
+        .scopes
+        .get("Foo")
+        .unwrap()
+        .write()
+        .unwrap()
+        .subscopes
+        .values_mut()
+    {
         let new_scope = Scope::from_other(
             &bar.scopes.get("Bar").unwrap().read().unwrap().clone(),
             subscope_to_update
@@ -230,5 +238,5 @@ pub fn create_foo() -> TyVcd {
 
         *subscope_to_update = Arc::new(RwLock::new(new_scope));
     }
-    foo
+    foo_tyvcd
 }
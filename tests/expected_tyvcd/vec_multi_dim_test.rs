@@ -12,7 +12,7 @@ pub fn create_vecs_multi_dim() -> TyVcd {
             String::from("Issue16"),
             String::from("Issue16"),
             TypeInfo::new("Issue16".to_string(), vec![]),
-            &vec![],
+            &[],
         ))),
     );
 
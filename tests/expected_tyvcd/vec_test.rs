@@ -12,7 +12,7 @@ pub fn create_vecs() -> TyVcd {
             String::from("Issue10"),
             String::from("Issue10"),
             TypeInfo::new("Issue10".to_string(), vec![]),
-            &vec![],
+            &[],
         ))),
     );
 
@@ -24,7 +24,7 @@ pub fn create_with_bundles_and_vecs() -> TyVcd {
                     value: None,
                 }],
             ),
-            &vec![],
+            &[],
         ))),
     );
 
@@ -174,7 +174,7 @@ fn create_io_fields() -> Vec<Variable> {
             String::from("vec"),
             TypeInfo::new("VecType".to_string(), Vec::new()),
             VariableKind::Vector {
-                fields: vec![create_io_vec_fields(), create_io_vec_0_fields()].concat(),
+                fields: [create_io_vec_fields(), create_io_vec_0_fields()].concat(),
             },
         ),
     ]
@@ -231,7 +231,7 @@ fn create_io_b_b_fields() -> Vec<Variable> {
         ]),
         String::from("vec"),
         TypeInfo::new("logic".to_string(), Vec::new()),
-        VariableKind::Vector { fields: fields },
+        VariableKind::Vector { fields },
     )]
 }
 
@@ -270,7 +270,7 @@ fn create_io_vec_fields() -> Vec<Variable> {
         ]),
         String::from("0"),
         TypeInfo::new("VecType".to_string(), Vec::new()),
-        VariableKind::Struct { fields: fields },
+        VariableKind::Struct { fields },
     )]
 }
 
@@ -318,7 +318,7 @@ fn create_io_vec_0_fields() -> Vec<Variable> {
         ]),
         String::from("1"), // TODO: fix this name
         TypeInfo::new("VecType".to_string(), Vec::new()),
-        VariableKind::Struct { fields: fields },
+        VariableKind::Struct { fields },
     )]
 }
 
@@ -93,7 +93,7 @@ fn test_multi_hgldd_parser() {
 #[test]
 fn test_hgldd_parser_dir() {
     let hgldd_dir = Path::new("tests/inputs/hgldd");
-    let files_to_skip = vec!["file_with_wrong_ext.ddd", "file_without_ext"];
+    let files_to_skip = ["file_with_wrong_ext.ddd", "file_without_ext"];
     let hgldds = hgldd::reader::parse_hgldd_dir(hgldd_dir).expect("error parsing hgldd dir");
     assert_eq!(hgldds.len(), 8);
 
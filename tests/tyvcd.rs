@@ -8,6 +8,7 @@ use test_case::test_case;
 use tywaves_rs::hgldd;
 use tywaves_rs::tyvcd::spec::*;
 use tywaves_rs::tyvcd::trace_pointer::*;
+use tywaves_rs::tyvcd::value::{DecodedValue, EncodeError, SignalAssignment};
 use tywaves_rs::tyvcd::{self, builder::GenericBuilder};
 
 use expected_tyvcd::*;
@@ -194,3 +195,225 @@ fn test_trace_pointer() {
         )
     );
 }
+
+#[test_case("IO[UInt<8>]", NumericKind::Unsigned; "Test uint")]
+#[test_case("SInt<8>", NumericKind::Signed; "Test sint")]
+#[test_case("IO[SInt<8>[4]]", NumericKind::Signed; "Test vector of sint")]
+#[test_case("Wire[FixedPoint<8><<4>>]", NumericKind::FixedPoint { binary_point: 4 }; "Test fixed point")]
+#[test_case("IO[SIntBundle]", NumericKind::Unsigned; "Test bundle with a numeric prefix")]
+#[test_case("IO[MyEnum[3]]", NumericKind::Unsigned; "Test vector of enums")]
+fn test_numeric_kind_from_type_info(type_name: &str, expected: NumericKind) {
+    let type_info = TypeInfo::new(type_name.to_string(), Vec::new());
+    assert_eq!(NumericKind::from(&type_info), expected);
+}
+
+#[test]
+fn test_numeric_kind_from_binary_point_param() {
+    let type_info = TypeInfo::new(
+        "MyFixed".to_string(),
+        vec![ConstructorParams {
+            name: "binaryPoint".to_string(),
+            tpe: "Int".to_string(),
+            value: Some("2".to_string()),
+        }],
+    );
+    assert_eq!(
+        NumericKind::from(&type_info),
+        NumericKind::FixedPoint { binary_point: 2 }
+    );
+}
+
+// Create a ground variable stored in the signal `trace_name`.
+fn ground_in(trace_name: &str, name: &str, type_name: &str, width: u128) -> Variable {
+    Variable::new(
+        TraceValue::RefTraceName(trace_name.to_string()),
+        name.to_string(),
+        TypeInfo::new(type_name.to_string(), Vec::new()),
+        VariableKind::Ground(width),
+    )
+}
+
+// Create a ground variable stored in a signal with the same name.
+fn ground(name: &str, type_name: &str, width: u128) -> Variable {
+    ground_in(name, name, type_name, width)
+}
+
+#[test]
+fn test_decode_value() {
+    let var = ground("a", "UInt<4>", 4);
+    assert_eq!(var.decode_value("1110"), DecodedValue::Unsigned(14));
+    // Shorter values are extended as in VCD
+    assert_eq!(var.decode_value("10"), DecodedValue::Unsigned(2));
+    assert_eq!(
+        var.decode_value("x1"),
+        DecodedValue::Bits("xxx1".to_string())
+    );
+
+    let var = ground("b", "IO[SInt<4>]", 4);
+    assert_eq!(var.decode_value("1110"), DecodedValue::Signed(-2));
+    assert_eq!(var.decode_value("0110"), DecodedValue::Signed(6));
+
+    let var = ground("c", "FixedPoint<6><<2>>", 6);
+    assert_eq!(var.decode_value("111010"), DecodedValue::FixedPoint(-1.5));
+
+    let var = ground("d", "Real", 64);
+    let raw = format!("{:064b}", 2.5f64.to_bits());
+    assert_eq!(var.decode_value(&raw), DecodedValue::Real(2.5));
+
+    let var = ground("e", "IO[MyEnum]", 2)
+        .with_enum_val_map(HashMap::from([(0, "A".to_string()), (1, "B".to_string())]));
+    assert_eq!(var.decode_value("01"), DecodedValue::Enum("B".to_string()));
+    assert_eq!(var.decode_value("11"), DecodedValue::Unsigned(3));
+
+    // Aggregates: the first field occupies the most significant bits
+    let var = Variable::new(
+        TraceValue::RefTraceValues(Vec::new()),
+        "io".to_string(),
+        TypeInfo::new("IO[AnonymousBundle]".to_string(), Vec::new()),
+        VariableKind::Struct {
+            fields: vec![
+                ground("x", "SInt<4>", 4),
+                Variable::new(
+                    TraceValue::RefTraceValues(Vec::new()),
+                    "v".to_string(),
+                    TypeInfo::new("UInt<2>[2]".to_string(), Vec::new()),
+                    VariableKind::Vector {
+                        fields: vec![ground("0", "UInt<2>", 2), ground("1", "UInt<2>", 2)],
+                    },
+                ),
+            ],
+        },
+    );
+    let value = var.decode_value("11110110");
+    assert_eq!(
+        value,
+        DecodedValue::Struct(vec![
+            ("x".to_string(), DecodedValue::Signed(-1)),
+            (
+                "v".to_string(),
+                DecodedValue::Vector(vec![DecodedValue::Unsigned(1), DecodedValue::Unsigned(2)])
+            ),
+        ])
+    );
+    assert_eq!(value.to_string(), "{x: -1, v: [1, 2]}");
+}
+
+#[test]
+fn test_encode_value() {
+    // io: {x: SInt<4>, op: MyEnum, v: UInt<2>[2], c: 1}
+    let var = Variable::new(
+        TraceValue::RefTraceValues(Vec::new()),
+        "io".to_string(),
+        TypeInfo::new("IO[AnonymousBundle]".to_string(), Vec::new()),
+        VariableKind::Struct {
+            fields: vec![
+                ground_in("io_x", "x", "SInt<4>", 4),
+                ground_in("io_op", "op", "MyEnum", 2).with_enum_val_map(HashMap::from([
+                    (0, "ADD".to_string()),
+                    (1, "SUB".to_string()),
+                ])),
+                Variable::new(
+                    TraceValue::RefTraceValues(Vec::new()),
+                    "v".to_string(),
+                    TypeInfo::new("UInt<2>[2]".to_string(), Vec::new()),
+                    VariableKind::Vector {
+                        fields: vec![
+                            ground("io_v_0", "UInt<2>", 2),
+                            ground("io_v_1", "UInt<2>", 2),
+                        ],
+                    },
+                ),
+                Variable::new(
+                    TraceValue::Constant(ConstValue::FourValue(b"1".to_vec(), 1)),
+                    "c".to_string(),
+                    TypeInfo::new("Bool".to_string(), Vec::new()),
+                    VariableKind::Ground(1),
+                ),
+            ],
+        },
+    );
+    let value = DecodedValue::Struct(vec![
+        ("x".to_string(), DecodedValue::Signed(-3)),
+        ("op".to_string(), DecodedValue::Enum("SUB".to_string())),
+        (
+            "v".to_string(),
+            DecodedValue::Vector(vec![DecodedValue::Unsigned(2), DecodedValue::Unsigned(1)]),
+        ),
+        ("c".to_string(), DecodedValue::Unsigned(1)),
+    ]);
+
+    let assignments = var.encode_value(&value).expect("encoding failed");
+    let assignment = |trace_name: &str, bits: &str| SignalAssignment {
+        trace_name: trace_name.to_string(),
+        bits: bits.to_string(),
+    };
+    assert_eq!(
+        assignments,
+        vec![
+            assignment("io_x", "1101"),
+            assignment("io_op", "01"),
+            assignment("io_v_0", "10"),
+            assignment("io_v_1", "01"),
+        ]
+    );
+
+    // The concatenated bits are decoded back to the same value
+    let bits = var.encode_bits(&value).expect("encoding failed");
+    assert_eq!(bits, "11010110011");
+    assert_eq!(var.decode_value(&bits), value);
+
+    // Partial structs are allowed when the fields are in separate signals
+    let partial = DecodedValue::Struct(vec![("x".to_string(), DecodedValue::Signed(7))]);
+    assert_eq!(
+        var.encode_value(&partial),
+        Ok(vec![assignment("io_x", "0111")])
+    );
+    assert!(matches!(
+        var.encode_bits(&partial),
+        Err(EncodeError::MissingField { .. })
+    ));
+
+    // Invalid values
+    let invalid = |field: &str, value: DecodedValue| {
+        var.encode_value(&DecodedValue::Struct(vec![(field.to_string(), value)]))
+    };
+    assert!(matches!(
+        invalid("x", DecodedValue::Signed(8)),
+        Err(EncodeError::InvalidValue { .. })
+    ));
+    assert!(matches!(
+        invalid("op", DecodedValue::Enum("MUL".to_string())),
+        Err(EncodeError::UnknownEnumVariant { .. })
+    ));
+    assert!(matches!(
+        invalid("v", DecodedValue::Vector(vec![DecodedValue::Unsigned(0)])),
+        Err(EncodeError::VectorLength { .. })
+    ));
+    assert!(matches!(
+        invalid("c", DecodedValue::Unsigned(0)),
+        Err(EncodeError::ConstantMismatch { .. })
+    ));
+    assert!(matches!(
+        invalid("y", DecodedValue::Unsigned(0)),
+        Err(EncodeError::UnknownField { .. })
+    ));
+}
+
+#[test_case(NumericKind::Unsigned, DecodedValue::Unsigned(5), 4, Some("0101"); "Test unsigned")]
+#[test_case(NumericKind::Unsigned, DecodedValue::Unsigned(16), 4, None; "Test unsigned overflow")]
+#[test_case(NumericKind::Unsigned, DecodedValue::Signed(-1), 4, None; "Test unsigned negative")]
+#[test_case(NumericKind::Signed, DecodedValue::Signed(-8), 4, Some("1000"); "Test signed min")]
+#[test_case(NumericKind::Signed, DecodedValue::Unsigned(8), 4, None; "Test signed overflow")]
+#[test_case(NumericKind::FixedPoint { binary_point: 2 }, DecodedValue::FixedPoint(-1.5), 6, Some("111010"); "Test fixed point")]
+#[test_case(NumericKind::FixedPoint { binary_point: 2 }, DecodedValue::Unsigned(3), 6, Some("001100"); "Test fixed point from integer")]
+#[test_case(NumericKind::FixedPoint { binary_point: 2 }, DecodedValue::FixedPoint(-1.6), 6, None; "Test fixed point not a multiple of the resolution")]
+#[test_case(NumericKind::FixedPoint { binary_point: 2 }, DecodedValue::Real(0.75 + 1e-12), 6, Some("000011"); "Test fixed point rounding error")]
+#[test_case(NumericKind::Real, DecodedValue::Real(2.5), 32, None; "Test real wrong width")]
+fn test_numeric_kind_encode(
+    numeric_kind: NumericKind,
+    value: DecodedValue,
+    width: u32,
+    expected: Option<&str>,
+) {
+    assert_eq!(numeric_kind.encode(&value, width).as_deref(), expected);
+}
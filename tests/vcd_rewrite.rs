@@ -1,5 +1,1289 @@
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
 use std::str::FromStr;
-use tywaves_rs::vcd_rewrite::{IdCodeWithShift, VcdRewriteVariable};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use tywaves_rs::hgldd;
+use tywaves_rs::hgldd::spec::Opcode;
+use tywaves_rs::tyvcd::builder::{GenericBuilder, TyVcdBuilder};
+use tywaves_rs::tyvcd::spec::{Scope, TyVcd, TypeInfo, Variable, VariableKind};
+use tywaves_rs::tyvcd::trace_pointer::{ConstValue, TraceValue};
+use tywaves_rs::vcd_rewrite::metadata;
+use tywaves_rs::vcd_rewrite::output::OutputFormat;
+use tywaves_rs::vcd_rewrite::verify::{RewriteCheck, RewriteMismatch};
+use tywaves_rs::vcd_rewrite::{
+    AggregateMode, EnumMode, IdCodeWithShift, VcdRewriteError, VcdRewriteVariable, VcdRewriter,
+    WidthMismatch, WidthMismatchPolicy,
+};
+use tywaves_rs::waveform::db::{WaveformDb, WaveformError};
+
+use pretty_assertions::assert_eq;
+use test_case::test_case;
+
+// The changes of each variable of a trace, by full path. Repeated values are removed.
+type TraceChanges = BTreeMap<String, Vec<(u64, String)>>;
+
+// Build the typed information of the handshake design.
+fn handshake_tyvcd() -> TyVcd {
+    let hgldd = hgldd::reader::parse_hgldd_file(Path::new("tests/inputs/waveform/handshake.dd"))
+        .expect("error parsing hgldd");
+    let mut builder = TyVcdBuilder::init(hgldd);
+    builder.build().expect("build failed");
+    builder.get_copy().unwrap()
+}
+
+// Build the typed scopes of the handshake design.
+fn handshake_scopes() -> Vec<Scope> {
+    let tyvcd = handshake_tyvcd();
+    let mut scopes: Vec<Scope> = tyvcd
+        .scopes
+        .values()
+        .map(|scope| scope.read().unwrap().clone())
+        .collect();
+    scopes.sort_by(|a, b| a.name.cmp(&b.name));
+    scopes
+}
+
+// Return a path for `name` in the temporary directory that no other test or test run uses.
+fn temp_path(name: &str) -> PathBuf {
+    static NEXT: AtomicUsize = AtomicUsize::new(0);
+    let id = NEXT.fetch_add(1, Ordering::Relaxed);
+    std::env::temp_dir().join(format!("tywaves_{}_{}_{}", std::process::id(), id, name))
+}
+
+// Rewrite the handshake trace to `out_name` in a temporary directory.
+fn rewrite_handshake(out_name: &str, format: Option<OutputFormat>) -> String {
+    rewrite_trace("tests/inputs/waveform/handshake.vcd", out_name, format)
+}
+
+// Rewrite a trace of the handshake design to `out_name` in a temporary directory.
+fn rewrite_trace(trace_path: &str, out_name: &str, format: Option<OutputFormat>) -> String {
+    let out_path = temp_path(out_name);
+    let out_path = out_path.to_string_lossy().to_string();
+    let vcd_path = Path::new(trace_path);
+    let rewriter = match format {
+        Some(format) => {
+            VcdRewriter::new_with_format(vcd_path, handshake_scopes(), out_path, format)
+        }
+        None => VcdRewriter::new(vcd_path, handshake_scopes(), out_path),
+    }
+    .expect("failed to create the rewriter");
+    run_rewriter(rewriter)
+}
+
+fn run_rewriter(mut rewriter: VcdRewriter) -> String {
+    rewriter.rewrite().expect("failed to rewrite");
+    rewriter.get_final_file().clone()
+}
+
+fn push_change(changes: &mut TraceChanges, path: &str, time: u64, value: String) {
+    let var_changes = changes.entry(path.to_string()).or_default();
+    if let Some((last_time, last_value)) = var_changes.last_mut() {
+        if *last_value == value {
+            return;
+        }
+        if *last_time == time {
+            *last_value = value;
+            return;
+        }
+    }
+    var_changes.push((time, value));
+}
+
+fn read_vcd_changes(path: &str) -> TraceChanges {
+    let mut parser = vcd::Parser::new(BufReader::new(File::open(path).unwrap()));
+    let header = parser.parse_header().unwrap();
+
+    // The paths of each id code (aliases share the same id code)
+    let mut names: BTreeMap<vcd::IdCode, Vec<String>> = BTreeMap::new();
+    let mut stack = vec![(String::new(), header.items.as_slice())];
+    while let Some((prefix, items)) = stack.pop() {
+        for item in items {
+            match item {
+                vcd::ScopeItem::Scope(scope) => stack.push((
+                    format!("{}{}.", prefix, scope.identifier),
+                    scope.items.as_slice(),
+                )),
+                vcd::ScopeItem::Var(var) => {
+                    let path = format!("{}{}", prefix, var.reference);
+                    names.entry(var.code).or_default().push(path);
+                }
+                _ => {}
+            }
+        }
+    }
+
+    let mut changes = TraceChanges::new();
+    let mut time = 0;
+    for command in parser {
+        match command.unwrap() {
+            vcd::Command::Timestamp(ts) => time = ts,
+            vcd::Command::ChangeScalar(id, value) => {
+                for path in &names[&id] {
+                    push_change(&mut changes, path, time, value.to_string())
+                }
+            }
+            vcd::Command::ChangeVector(id, value) => {
+                for path in &names[&id] {
+                    push_change(&mut changes, path, time, value.to_string())
+                }
+            }
+            vcd::Command::ChangeReal(id, value) => {
+                for path in &names[&id] {
+                    push_change(&mut changes, path, time, value.to_string())
+                }
+            }
+            vcd::Command::ChangeString(id, value) => {
+                for path in &names[&id] {
+                    push_change(&mut changes, path, time, value.clone())
+                }
+            }
+            _ => {}
+        }
+    }
+    changes
+}
+
+// Return the type of each variable of a VCD file, by name.
+fn read_vcd_var_types(path: &str) -> BTreeMap<String, vcd::VarType> {
+    let mut parser = vcd::Parser::new(BufReader::new(File::open(path).unwrap()));
+    let header = parser.parse_header().unwrap();
+    let mut var_types = BTreeMap::new();
+    for item in header.items {
+        if let vcd::ScopeItem::Scope(scope) = item {
+            for item in scope.items {
+                if let vcd::ScopeItem::Var(var) = item {
+                    var_types.insert(var.reference, var.var_type);
+                }
+            }
+        }
+    }
+    var_types
+}
+
+// Build the typed scope of the sensor trace, with real and string variables.
+fn sensor_scopes() -> Vec<Scope> {
+    let ground = |trace_value: TraceValue, name: &str, type_name: &str, width: u128| {
+        Variable::new(
+            trace_value,
+            name.to_string(),
+            TypeInfo::new(type_name.to_string(), vec![]),
+            VariableKind::Ground(width),
+        )
+    };
+    let trace_name = |name: &str| TraceValue::RefTraceName(name.to_string());
+
+    let mut scope = Scope::empty(
+        "Sensor".to_string(),
+        "Sensor".to_string(),
+        TypeInfo::new("Sensor".to_string(), vec![]),
+        &[],
+    );
+    scope.variables = vec![
+        ground(trace_name("clock"), "clock", "Clock", 1),
+        ground(trace_name("temperature"), "temperature", "Double", 64),
+        ground(trace_name("status"), "status", "String", 1),
+        ground(trace_name("count"), "count", "UInt<8>", 8),
+        ground(
+            TraceValue::Constant(ConstValue::Real(1.5)),
+            "gain",
+            "Double",
+            64,
+        ),
+        ground(
+            TraceValue::Constant(ConstValue::String("fast".to_string())),
+            "mode",
+            "String",
+            1,
+        ),
+    ];
+    vec![scope]
+}
+
+fn read_fst_changes(path: &str) -> TraceChanges {
+    let mut reader =
+        fst_reader::FstReader::open_and_read_time_table(BufReader::new(File::open(path).unwrap()))
+            .unwrap();
+
+    let mut names = BTreeMap::new();
+    let mut scopes = Vec::new();
+    reader
+        .read_hierarchy(|entry| match entry {
+            fst_reader::FstHierarchyEntry::Scope { name, .. } => scopes.push(name),
+            fst_reader::FstHierarchyEntry::UpScope => {
+                scopes.pop();
+            }
+            fst_reader::FstHierarchyEntry::Var { name, handle, .. } => {
+                // Remove the index of the vectors: `name [7:0]`
+                let name = name.split(' ').next().unwrap();
+                names.insert(handle.get_index(), format!("{}.{}", scopes.join("."), name));
+            }
+            _ => {}
+        })
+        .unwrap();
+
+    let mut changes = TraceChanges::new();
+    reader
+        .read_signals(&fst_reader::FstFilter::all(), |time, handle, value| {
+            if let fst_reader::FstSignalValue::String(value) = value {
+                let value = String::from_utf8_lossy(value).to_string();
+                push_change(&mut changes, &names[&handle.get_index()], time, value);
+            }
+        })
+        .unwrap();
+    changes
+}
+
+#[test]
+fn rewrite_to_fst() {
+    let vcd_file = rewrite_handshake("handshake_rewrite_to_fst.vcd", None);
+    let fst_file = rewrite_handshake("handshake_rewrite_to_fst.fst", None);
+
+    let vcd_changes = read_vcd_changes(&vcd_file);
+    let fst_changes = read_fst_changes(&fst_file);
+    assert!(vcd_changes.contains_key("Handshake.io"));
+    assert_eq!(
+        fst_changes["Handshake.state"],
+        vcd_changes["Handshake.state"]
+    );
+    assert_eq!(fst_changes, vcd_changes);
+}
+
+#[test]
+fn rewrite_to_fst_explicit_format() {
+    let fst_file = rewrite_handshake("handshake_rewrite_explicit.trace", Some(OutputFormat::Fst));
+    let mut input = BufReader::new(File::open(fst_file).unwrap());
+    assert!(fst_reader::is_fst_file(&mut input));
+
+    let header = fst_reader::FstReader::open(input).unwrap().get_header();
+    assert_eq!(header.timescale_exponent, -9);
+    assert_eq!(header.end_time, 90);
+    assert_eq!(header.version, "Manual trace for tywaves-rs tests");
+}
+
+#[test]
+fn rewrite_from_fst() {
+    let from_vcd = rewrite_handshake("handshake_rewrite_from_vcd.vcd", None);
+    let from_fst = rewrite_trace(
+        "tests/inputs/waveform/handshake.fst",
+        "handshake_rewrite_from_fst.vcd",
+        None,
+    );
+    assert_eq!(read_vcd_changes(&from_fst), read_vcd_changes(&from_vcd));
+}
+
+#[test]
+fn rewrite_only_changed_variables() {
+    let vcd_file = rewrite_handshake("handshake_rewrite_changed.vcd", None);
+    let mut parser = vcd::Parser::new(BufReader::new(File::open(vcd_file).unwrap()));
+    parser.parse_header().unwrap();
+
+    // Each variable is written at most once per timestamp and only if its value changed
+    let mut last_values = BTreeMap::new();
+    let mut written = Vec::new();
+    for command in parser {
+        match command.unwrap() {
+            vcd::Command::Timestamp(_) => written.clear(),
+            vcd::Command::ChangeVector(id, value) => {
+                assert!(!written.contains(&id), "{} written twice", id);
+                written.push(id);
+                assert_ne!(last_values.insert(id, value.clone()), Some(value));
+            }
+            _ => {}
+        }
+    }
+
+    let changes = read_vcd_changes(&rewrite_handshake("handshake_rewrite_state.vcd", None));
+    assert_eq!(
+        changes["Handshake.state"],
+        vec![
+            (0, "00".to_string()),
+            (20, "01".to_string()),
+            (60, "10".to_string()),
+            (70, "11".to_string()),
+            (80, "00".to_string()),
+        ]
+    );
+}
+
+#[test]
+fn rewrite_untyped_signals() {
+    let vcd_path = Path::new("tests/inputs/waveform/handshake.vcd");
+    let out_path = |name: &str| temp_path(name).to_string_lossy().to_string();
+
+    // Without the untyped signals, the internal signals are lost
+    let typed_changes = read_vcd_changes(&rewrite_handshake("handshake_typed.vcd", None));
+    assert!(!typed_changes.contains_key("Handshake._GEN_0"));
+
+    let rewriter = VcdRewriter::new(vcd_path, handshake_scopes(), out_path("handshake_all.vcd"))
+        .unwrap()
+        .with_untyped_signals(true);
+    let all_changes = read_vcd_changes(&run_rewriter(rewriter));
+    let original_changes = read_vcd_changes("tests/inputs/waveform/handshake.vcd");
+    assert_eq!(
+        all_changes["Handshake._GEN_0"],
+        original_changes["Handshake._GEN_0"]
+    );
+    // The typed variables are not duplicated
+    assert!(!all_changes.contains_key("Handshake.io_in_bits_data"));
+    assert_eq!(
+        all_changes.len(),
+        typed_changes.len() + 1,
+        "{:?}",
+        all_changes.keys()
+    );
+
+    // Without typed scopes, the whole trace is copied
+    let rewriter = VcdRewriter::new(vcd_path, vec![], out_path("handshake_untyped.vcd"))
+        .unwrap()
+        .with_untyped_signals(true);
+    assert_eq!(read_vcd_changes(&run_rewriter(rewriter)), original_changes);
+}
+
+#[test]
+fn rewrite_real_and_string_values() {
+    let vcd_path = Path::new("tests/inputs/waveform/values.vcd");
+    let out_path = temp_path("values_rewrite.vcd");
+    let rewriter = VcdRewriter::new(
+        vcd_path,
+        sensor_scopes(),
+        out_path.to_string_lossy().to_string(),
+    )
+    .unwrap();
+    let out_file = run_rewriter(rewriter);
+
+    let var_types = read_vcd_var_types(&out_file);
+    assert_eq!(var_types["clock"], vcd::VarType::Wire);
+    assert_eq!(var_types["temperature"], vcd::VarType::Real);
+    assert_eq!(var_types["status"], vcd::VarType::String);
+    assert_eq!(var_types["gain"], vcd::VarType::Real);
+    assert_eq!(var_types["mode"], vcd::VarType::String);
+
+    let changes = read_vcd_changes(&out_file);
+    let values = |values: &[(u64, &str)]| {
+        values
+            .iter()
+            .map(|(time, value)| (*time, value.to_string()))
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(
+        changes["Sensor.temperature"],
+        values(&[(0, "20.5"), (5, "21.25"), (15, "-3.5")])
+    );
+    assert_eq!(
+        changes["Sensor.status"],
+        values(&[(0, "idle"), (10, "busy"), (15, "done")])
+    );
+    assert_eq!(changes["Sensor.gain"], values(&[(0, "1.5")]));
+    assert_eq!(changes["Sensor.mode"], values(&[(0, "fast")]));
+
+    // The untyped real and string variables are copied as well
+    let out_path = temp_path("values_untyped.vcd");
+    let rewriter = VcdRewriter::new(vcd_path, vec![], out_path.to_string_lossy().to_string())
+        .unwrap()
+        .with_untyped_signals(true);
+    let out_file = run_rewriter(rewriter);
+    assert_eq!(
+        read_vcd_changes(&out_file),
+        read_vcd_changes("tests/inputs/waveform/values.vcd")
+    );
+    assert_eq!(
+        read_vcd_var_types(&out_file),
+        read_vcd_var_types("tests/inputs/waveform/values.vcd")
+    );
+
+    // The FST writer does not support string variables: the output is not created
+    let out_path = temp_path("values_rewrite.fst");
+    let _ = std::fs::remove_file(&out_path);
+    let rewriter = VcdRewriter::new(
+        vcd_path,
+        sensor_scopes(),
+        out_path.to_string_lossy().to_string(),
+    );
+    assert!(matches!(rewriter, Err(VcdRewriteError::Other(_))));
+    assert!(!out_path.exists());
+}
+
+const REAL_TRACE: &str = "$timescale 1ns $end
+$scope module Sensor $end
+$var wire 1 ! clock $end
+$var real 64 \" temperature $end
+$upscope $end
+$enddefinitions $end
+#0
+0!
+r20.5 \"
+";
+
+const CLOCK_TRACE: &str = "$timescale 1ns $end
+$scope module Sensor $end
+$var wire 1 ! clock $end
+$upscope $end
+$enddefinitions $end
+#0
+0!
+#1
+1!
+";
+
+// Keep only the typed clock and the real constant of the sensor.
+fn sensor_real_scopes() -> Vec<Scope> {
+    let mut scopes = sensor_scopes();
+    scopes[0]
+        .variables
+        .retain(|variable| ["clock", "gain"].contains(&variable.name.as_str()));
+    scopes
+}
+
+#[test_case(REAL_TRACE, vec![], "values_real_signal" ; "real signal")]
+#[test_case(CLOCK_TRACE, sensor_real_scopes(), "values_real_constant" ; "real constant")]
+fn rewrite_fst_real_values(trace: &str, scopes: Vec<Scope>, name: &str) {
+    let vcd_path = temp_path(&format!("{}.vcd", name));
+    std::fs::write(&vcd_path, trace).unwrap();
+
+    // The FST writer does not support real variables: the output is not created
+    let out_path = temp_path(&format!("{}.fst", name));
+    let _ = std::fs::remove_file(&out_path);
+    let rewriter = VcdRewriter::new(&vcd_path, scopes, out_path.to_string_lossy().to_string());
+    assert!(matches!(rewriter, Err(VcdRewriteError::Other(_))));
+    assert!(!out_path.exists());
+}
+
+#[test]
+fn rewrite_fst_timescale() {
+    let vcd_path = temp_path("clock_25ns.vcd");
+    std::fs::write(&vcd_path, CLOCK_TRACE.replace("1ns", "25ns")).unwrap();
+
+    // 25 ns is not a power of ten: it has no FST timescale exponent
+    let out_path = temp_path("clock_25ns.fst");
+    let mut rewriter = VcdRewriter::new(&vcd_path, vec![], out_path.to_string_lossy().to_string())
+        .unwrap()
+        .with_untyped_signals(true);
+    assert!(matches!(rewriter.rewrite(), Err(VcdRewriteError::Other(_))));
+
+    // 10 ns is
+    std::fs::write(&vcd_path, CLOCK_TRACE.replace("1ns", "10ns")).unwrap();
+    let rewriter = VcdRewriter::new(&vcd_path, vec![], out_path.to_string_lossy().to_string())
+        .unwrap()
+        .with_untyped_signals(true);
+    let changes = read_fst_changes(&run_rewriter(rewriter));
+    assert_eq!(
+        changes["Sensor.clock"],
+        vec![(0, "0".to_string()), (1, "1".to_string())]
+    );
+}
+
+#[test]
+fn rewrite_hierarchy_mode() {
+    let vcd_path = Path::new("tests/inputs/waveform/handshake.vcd");
+    let out_path = |name: &str| temp_path(name).to_string_lossy().to_string();
+
+    let rewriter = VcdRewriter::new(vcd_path, handshake_scopes(), out_path("handshake_tree.vcd"))
+        .unwrap()
+        .with_aggregate_mode(AggregateMode::Hierarchy);
+    let vcd_file = run_rewriter(rewriter);
+    let vcd_text = std::fs::read_to_string(&vcd_file).unwrap();
+    assert!(vcd_text.contains("$scope struct io $end"));
+    assert!(vcd_text.contains("$scope struct bits $end"));
+
+    // The struct scopes are read back as modules
+    let original_db = WaveformDb::open(vcd_path, handshake_tyvcd()).unwrap();
+    let db = WaveformDb::open(Path::new(&vcd_file), handshake_tyvcd()).unwrap();
+    let raw_changes = |db: &WaveformDb, path: &[&str]| {
+        let var = db.get_header().find_var(path).unwrap();
+        let mut changes = TraceChanges::new();
+        for (time, value) in db.signal_changes(var.code).unwrap().iter() {
+            push_change(&mut changes, "", time, value.to_bits(var.size as usize));
+        }
+        changes[""].clone()
+    };
+    assert_eq!(
+        raw_changes(&db, &["Handshake", "io", "in", "bits", "data"]),
+        raw_changes(&original_db, &["Handshake", "io_in_bits_data"])
+    );
+    assert_eq!(
+        raw_changes(&db, &["Handshake", "io", "vec", "[1]"]),
+        raw_changes(&original_db, &["Handshake", "io_vec_1"])
+    );
+
+    let rewriter = VcdRewriter::new(vcd_path, handshake_scopes(), out_path("handshake_tree.fst"))
+        .unwrap()
+        .with_aggregate_mode(AggregateMode::Hierarchy);
+    let fst_changes = read_fst_changes(&run_rewriter(rewriter));
+    let original_changes = read_vcd_changes("tests/inputs/waveform/handshake.vcd");
+    assert_eq!(
+        fst_changes["Handshake.io.in.bits.data"],
+        original_changes["Handshake.io_in_bits_data"]
+    );
+    assert_eq!(
+        fst_changes["Handshake.io.vec.[1]"],
+        original_changes["Handshake.io_vec_1"]
+    );
+    assert_eq!(
+        fst_changes["Handshake.state"],
+        original_changes["Handshake.state"]
+    );
+}
+
+#[test]
+fn rewrite_enum_variant_names() {
+    let vcd_path = Path::new("tests/inputs/waveform/handshake.vcd");
+    let out_path = |name: &str| temp_path(name).to_string_lossy().to_string();
+    let expected_names: Vec<(u64, String)> = [
+        (0, "sIdle"),
+        (20, "sBusy"),
+        (60, "sDone"),
+        (70, "unknown(3)"),
+        (80, "sIdle"),
+    ]
+    .iter()
+    .map(|(time, name)| (*time, name.to_string()))
+    .collect();
+    let original_changes = read_vcd_changes("tests/inputs/waveform/handshake.vcd");
+
+    let rewriter = VcdRewriter::new(
+        vcd_path,
+        handshake_scopes(),
+        out_path("handshake_enum_companion.vcd"),
+    )
+    .unwrap()
+    .with_enum_mode(EnumMode::Companion);
+    let out_file = run_rewriter(rewriter);
+    let changes = read_vcd_changes(&out_file);
+    assert_eq!(changes["Handshake.state_variant"], expected_names);
+    assert_eq!(
+        changes["Handshake.state"],
+        original_changes["Handshake.state"]
+    );
+    let var_types = read_vcd_var_types(&out_file);
+    assert_eq!(var_types["state_variant"], vcd::VarType::String);
+    // Only the ground enums have a companion
+    assert!(!var_types.contains_key("io_variant"));
+
+    let rewriter = VcdRewriter::new(
+        vcd_path,
+        handshake_scopes(),
+        out_path("handshake_enum_replace.vcd"),
+    )
+    .unwrap()
+    .with_enum_mode(EnumMode::Replace);
+    let out_file = run_rewriter(rewriter);
+    assert_eq!(
+        read_vcd_changes(&out_file)["Handshake.state"],
+        expected_names
+    );
+    let var_types = read_vcd_var_types(&out_file);
+    assert_eq!(var_types["state"], vcd::VarType::String);
+    assert!(!var_types.contains_key("state_variant"));
+
+    // FST traces have no strings: the enums are written as bits
+    for (enum_mode, name) in [
+        (EnumMode::Companion, "handshake_enum_companion.fst"),
+        (EnumMode::Replace, "handshake_enum_replace.fst"),
+    ] {
+        let rewriter = VcdRewriter::new(vcd_path, handshake_scopes(), out_path(name))
+            .unwrap()
+            .with_enum_mode(enum_mode);
+        let changes = read_fst_changes(&run_rewriter(rewriter));
+        assert_eq!(
+            changes["Handshake.state"],
+            original_changes["Handshake.state"]
+        );
+        assert!(!changes.contains_key("Handshake.state_variant"));
+    }
+}
+
+#[test]
+fn rewrite_in_memory() {
+    let input = std::fs::read("tests/inputs/waveform/handshake.vcd").unwrap();
+    let mut output = Vec::new();
+    {
+        let mut rewriter = VcdRewriter::from_io(input.as_slice(), &mut output, handshake_scopes());
+        rewriter.rewrite().expect("failed to rewrite");
+        assert_eq!(rewriter.get_final_file(), "");
+    }
+
+    let out_file = rewrite_handshake("handshake_in_memory.vcd", None);
+    assert_eq!(
+        String::from_utf8(output).unwrap(),
+        std::fs::read_to_string(out_file).unwrap()
+    );
+}
+
+// Keep the changes between `start` and `end`: the value at `start` is the last value before it.
+fn window_changes(changes: &TraceChanges, start: u64, end: u64) -> TraceChanges {
+    let mut window = TraceChanges::new();
+    for (path, var_changes) in changes {
+        for (time, value) in var_changes {
+            if *time <= end {
+                push_change(&mut window, path, (*time).max(start), value.clone());
+            }
+        }
+    }
+    window
+}
+
+#[test]
+fn rewrite_time_window() {
+    let full_changes = read_vcd_changes(&rewrite_handshake("handshake_full.vcd", None));
+
+    let vcd_path = Path::new("tests/inputs/waveform/handshake.vcd");
+    let out_path = temp_path("handshake_window.vcd");
+    let rewriter = VcdRewriter::new(
+        vcd_path,
+        handshake_scopes(),
+        out_path.to_string_lossy().to_string(),
+    )
+    .unwrap()
+    .with_time_window(Some(22), Some(60));
+    let out_file = run_rewriter(rewriter);
+
+    let changes = read_vcd_changes(&out_file);
+    assert_eq!(changes, window_changes(&full_changes, 22, 60));
+    assert_eq!(
+        changes["Handshake.state"],
+        vec![(22, "01".to_string()), (60, "10".to_string())]
+    );
+    assert!(std::fs::read_to_string(&out_file)
+        .unwrap()
+        .contains("#22\n$dumpvars\n"));
+
+    // The real and string values at the start of the window
+    let vcd_path = Path::new("tests/inputs/waveform/values.vcd");
+    let out_path = temp_path("values_window.vcd");
+    let rewriter = VcdRewriter::new(
+        vcd_path,
+        sensor_scopes(),
+        out_path.to_string_lossy().to_string(),
+    )
+    .unwrap()
+    .with_time_window(Some(10), None);
+    let changes = read_vcd_changes(&run_rewriter(rewriter));
+    assert_eq!(
+        changes["Sensor.temperature"],
+        vec![(10, "21.25".to_string()), (15, "-3.5".to_string())]
+    );
+    assert_eq!(
+        changes["Sensor.status"],
+        vec![(10, "busy".to_string()), (15, "done".to_string())]
+    );
+}
+
+#[test_case(&["Handshake.core"], &["Handshake.core.clock", "Handshake.core.count"] ; "scope")]
+#[test_case(&["*.state"], &["Handshake.state"] ; "variable")]
+#[test_case(&["Handshake.?lock", "Handshake.core.count"], &["Handshake.clock", "Handshake.core.count"] ; "wildcards")]
+#[test_case(&["Handshake"], &["Handshake.clock", "Handshake.core.clock", "Handshake.core.count", "Handshake.io", "Handshake.reset", "Handshake.state"] ; "top")]
+#[test_case(&["Other"], &[] ; "no match")]
+fn rewrite_path_filter(patterns: &[&str], expected_paths: &[&str]) {
+    let vcd_path = Path::new("tests/inputs/waveform/handshake.vcd");
+    let out_name = format!(
+        "handshake_filter_{}.vcd",
+        patterns.join("_").replace('*', "x")
+    );
+    let out_path = temp_path(&out_name.replace('?', "x"));
+    let rewriter = VcdRewriter::new(
+        vcd_path,
+        handshake_scopes(),
+        out_path.to_string_lossy().to_string(),
+    )
+    .unwrap()
+    .with_path_filter(patterns.iter().copied());
+    let changes = read_vcd_changes(&run_rewriter(rewriter));
+    assert_eq!(changes.keys().collect::<Vec<_>>(), expected_paths);
+}
+
+// Build typed scopes of the bar trace, where some fields share the same id code.
+fn bar_alias_scopes() -> Vec<Scope> {
+    let ground = |name: &str, width: u128| {
+        Variable::new(
+            TraceValue::RefTraceName(name.to_string()),
+            name.to_string(),
+            TypeInfo::new(format!("UInt<{}>", width), vec![]),
+            VariableKind::Ground(width),
+        )
+    };
+    let pair = |name: &str, fields: Vec<Variable>| {
+        Variable::new(
+            TraceValue::RefTraceName(name.to_string()),
+            name.to_string(),
+            TypeInfo::new("Pair".to_string(), vec![]),
+            VariableKind::Struct { fields },
+        )
+    };
+    let type_info = TypeInfo::new("Bar".to_string(), vec![]);
+
+    let mut dut = Scope::empty(
+        "dut".to_string(),
+        "dut".to_string(),
+        type_info.clone(),
+        &["Bar".to_string()],
+    );
+    dut.variables = vec![
+        // `%` twice
+        pair("outs", vec![ground("io_out", 1), ground("wire_0", 1)]),
+        // `+` twice
+        pair(
+            "sums",
+            vec![ground("outputSum", 8), ground("_outputSum_output", 8)],
+        ),
+        ground("wire_0", 1),
+    ];
+    let mut bar = Scope::empty("Bar".to_string(), "Bar".to_string(), type_info, &[]);
+    bar.variables = vec![ground("outputSum", 8)];
+    bar.subscopes.insert(
+        "dut".to_string(),
+        std::sync::Arc::new(std::sync::RwLock::new(dut)),
+    );
+    vec![bar]
+}
+
+#[test]
+fn rewrite_aliased_id_codes() {
+    let trace_path = "examples/trace_bar.vcd";
+    let out_path = temp_path("bar_aliases.vcd");
+    let rewriter = VcdRewriter::new(
+        Path::new(trace_path),
+        bar_alias_scopes(),
+        out_path.to_string_lossy().to_string(),
+    )
+    .unwrap();
+    let changes = read_vcd_changes(&run_rewriter(rewriter));
+
+    let original_changes = read_vcd_changes(trace_path);
+    let doubled = |path: &str| {
+        original_changes[path]
+            .iter()
+            .map(|(time, value)| (*time, format!("{}{}", value, value)))
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(changes["Bar.dut.outs"], doubled("Bar.dut.io_out"));
+    assert_eq!(changes["Bar.dut.sums"], doubled("Bar.dut.outputSum"));
+    assert_eq!(
+        changes["Bar.dut.wire_0"],
+        original_changes["Bar.dut.wire_0"]
+    );
+    assert_eq!(changes["Bar.outputSum"], original_changes["Bar.outputSum"]);
+}
+
+// Build typed scopes of the sensor trace where `count` is narrower than in the trace.
+fn narrow_sensor_scopes() -> Vec<Scope> {
+    let ground = |name: &str, width: u128| {
+        Variable::new(
+            TraceValue::RefTraceName(name.to_string()),
+            name.to_string(),
+            TypeInfo::new(format!("UInt<{}>", width), vec![]),
+            VariableKind::Ground(width),
+        )
+    };
+    let mut scope = Scope::empty(
+        "Sensor".to_string(),
+        "Sensor".to_string(),
+        TypeInfo::new("Sensor".to_string(), vec![]),
+        &[],
+    );
+    scope.variables = vec![
+        ground("count", 4),
+        Variable::new(
+            TraceValue::RefTraceName("sample".to_string()),
+            "sample".to_string(),
+            TypeInfo::new("Sample".to_string(), vec![]),
+            VariableKind::Struct {
+                fields: vec![ground("clock", 1), ground("count", 4)],
+            },
+        ),
+    ];
+    vec![scope]
+}
+
+#[test_case(WidthMismatchPolicy::Resize, &[(0, "0000"), (5, "0001"), (15, "0010")] ; "resize")]
+#[test_case(WidthMismatchPolicy::MarkX, &[(0, "xxxx")] ; "mark x")]
+fn rewrite_width_mismatch(policy: WidthMismatchPolicy, expected_count: &[(u64, &str)]) {
+    let out_path = temp_path(&format!("values_mismatch_{:?}.vcd", policy));
+    let mut rewriter = VcdRewriter::new(
+        Path::new("tests/inputs/waveform/values.vcd"),
+        narrow_sensor_scopes(),
+        out_path.to_string_lossy().to_string(),
+    )
+    .unwrap()
+    .with_width_policy(policy);
+    rewriter.rewrite().expect("failed to rewrite");
+
+    let mismatch = |variable: &str| WidthMismatch {
+        variable: variable.to_string(),
+        id_code: "$".parse().unwrap(),
+        expected_width: 4,
+        actual_width: 8,
+        first_time: 0,
+        count: 3,
+    };
+    assert_eq!(
+        rewriter.get_width_mismatches(),
+        &[mismatch("Sensor.count"), mismatch("Sensor.sample")]
+    );
+
+    let changes = read_vcd_changes(rewriter.get_final_file());
+    let expected_count: Vec<(u64, String)> = expected_count
+        .iter()
+        .map(|(time, value)| (*time, value.to_string()))
+        .collect();
+    assert_eq!(changes["Sensor.count"], expected_count);
+    // The count is the least significant field of the sample
+    let (_, last_sample) = changes["Sensor.sample"].last().unwrap();
+    assert_eq!(last_sample[1..], expected_count.last().unwrap().1);
+}
+
+#[test_case("01", "000001" ; "zero extension")]
+#[test_case("x1", "xxxxx1" ; "x extension")]
+#[test_case("z0", "zzzzz0" ; "z extension")]
+#[test_case("1x01z0x1", "01z0x1" ; "truncation")]
+fn rewrite_width_mismatch_resize(value: &str, expected: &str) {
+    let mut source = IdCodeWithShift::create(
+        vcd::IdCode::FIRST,
+        vcd::Vector::from_str("000000").unwrap(),
+        false,
+    );
+    assert!(source
+        .update_value_with_policy(
+            vcd::Vector::from_str(value).unwrap(),
+            WidthMismatchPolicy::Resize
+        )
+        .is_ok());
+    assert_eq!(source.get_value().to_string(), expected);
+}
+
+#[test]
+fn rewrite_width_mismatch_abort() {
+    let out_path = temp_path("values_mismatch_abort.vcd");
+    let mut rewriter = VcdRewriter::new(
+        Path::new("tests/inputs/waveform/values.vcd"),
+        narrow_sensor_scopes(),
+        out_path.to_string_lossy().to_string(),
+    )
+    .unwrap();
+    assert!(matches!(
+        rewriter.rewrite(),
+        Err(VcdRewriteError::UpdateValueError {
+            len_diff: (8, 4),
+            ..
+        })
+    ));
+    assert!(rewriter.get_width_mismatches().is_empty());
+}
+
+#[test]
+fn rewrite_type_metadata() {
+    let tyvcd = handshake_tyvcd();
+
+    let vcd_path = Path::new("tests/inputs/waveform/handshake.vcd");
+    let out_path = temp_path("handshake_metadata.vcd");
+    let rewriter = VcdRewriter::new(
+        vcd_path,
+        handshake_scopes(),
+        out_path.to_string_lossy().to_string(),
+    )
+    .unwrap()
+    .with_type_metadata(true);
+    let out_file = run_rewriter(rewriter);
+
+    // The decoded types are linked to the variables of the rewritten trace
+    let read_tyvcd = metadata::read_tyvcd(Path::new(&out_file)).expect("failed to read the types");
+    assert_eq!(read_tyvcd.scopes.len(), tyvcd.scopes.len());
+    let original_db = WaveformDb::open(vcd_path, tyvcd.clone()).unwrap();
+    let rewritten_db = WaveformDb::open(Path::new(&out_file), read_tyvcd).unwrap();
+    let paths = signal_paths(&original_db);
+    assert!(paths.contains(&"Handshake.io".to_string()));
+    assert_eq!(signal_paths(&rewritten_db), paths);
+    assert_same_values(&original_db, &rewritten_db, &paths);
+
+    // The values are not affected by the comments
+    let plain_file = rewrite_handshake("handshake_no_metadata.vcd", None);
+    assert_eq!(read_vcd_changes(&out_file), read_vcd_changes(&plain_file));
+    assert!(matches!(
+        metadata::read_tyvcd(Path::new(&plain_file)),
+        Err(VcdRewriteError::Other(_))
+    ));
+    assert!(metadata::decode_scope("Any comment text.").is_none());
+
+    // Only the selected variables and scopes are embedded
+    let out_path = temp_path("handshake_metadata_filtered.vcd");
+    let rewriter = VcdRewriter::new(
+        vcd_path,
+        handshake_scopes(),
+        out_path.to_string_lossy().to_string(),
+    )
+    .unwrap()
+    .with_path_filter(["Handshake.io", "Handshake.state"])
+    .with_type_metadata(true);
+    let out_file = run_rewriter(rewriter);
+
+    let read_tyvcd = metadata::read_tyvcd(Path::new(&out_file)).expect("failed to read the types");
+    let rewritten_db = WaveformDb::open(Path::new(&out_file), read_tyvcd).unwrap();
+    let paths = signal_paths(&rewritten_db);
+    assert_eq!(paths, ["Handshake.io", "Handshake.state"]);
+    assert!(matches!(
+        rewritten_db.value_at("Handshake.core.count", 0),
+        Err(WaveformError::PathNotFound(_))
+    ));
+    assert_same_values(&original_db, &rewritten_db, &paths);
+}
+
+#[test]
+fn rewrite_type_metadata_hierarchy() {
+    let vcd_path = Path::new("tests/inputs/waveform/handshake.vcd");
+    let out_path = temp_path("handshake_metadata_hierarchy.vcd");
+    let rewriter = VcdRewriter::new(
+        vcd_path,
+        handshake_scopes(),
+        out_path.to_string_lossy().to_string(),
+    )
+    .unwrap()
+    .with_aggregate_mode(AggregateMode::Hierarchy)
+    .with_type_metadata(true);
+    let out_file = run_rewriter(rewriter);
+
+    // The fields of the structs and vectors are read from their struct scopes
+    let read_tyvcd = metadata::read_tyvcd(Path::new(&out_file)).expect("failed to read the types");
+    let original_db = WaveformDb::open(vcd_path, handshake_tyvcd()).unwrap();
+    let rewritten_db = WaveformDb::open(Path::new(&out_file), read_tyvcd).unwrap();
+    let paths = signal_paths(&original_db);
+    assert_eq!(signal_paths(&rewritten_db), paths);
+    assert_same_values(&original_db, &rewritten_db, &paths);
+    assert_eq!(
+        rewritten_db.value_at("Handshake.io.vec[1]", 30).unwrap(),
+        original_db.value_at("Handshake.io.vec[1]", 30).unwrap()
+    );
+}
+
+// Return the typed paths of all the signals of a trace, sorted.
+fn signal_paths(db: &WaveformDb) -> Vec<String> {
+    let mut paths: Vec<String> = db
+        .scope_paths()
+        .iter()
+        .flat_map(|scope_path| db.signals_in_scope(scope_path).unwrap())
+        .map(|signal| signal.path)
+        .collect();
+    paths.sort();
+    paths
+}
+
+// Assert that the signals at `paths` have the same decoded values in both traces.
+fn assert_same_values(expected: &WaveformDb, actual: &WaveformDb, paths: &[String]) {
+    for path in paths {
+        for time in (0..=90).step_by(5) {
+            assert_eq!(
+                actual.value_at(path, time).unwrap(),
+                expected.value_at(path, time).unwrap(),
+                "{} at {}",
+                path,
+                time
+            );
+        }
+    }
+}
+
+#[test]
+fn rewrite_timescale_and_offset() {
+    let vcd_path = Path::new("tests/inputs/waveform/handshake.vcd");
+    let out_path = temp_path("handshake_timescale.vcd");
+    let rewriter = VcdRewriter::new(
+        vcd_path,
+        handshake_scopes(),
+        out_path.to_string_lossy().to_string(),
+    )
+    .unwrap()
+    .with_timescale(100, vcd::TimescaleUnit::PS)
+    .with_time_offset(1000);
+    let out_file = run_rewriter(rewriter);
+
+    let mut parser = vcd::Parser::new(BufReader::new(File::open(&out_file).unwrap()));
+    let header = parser.parse_header().unwrap();
+    assert_eq!(header.timescale, Some((100, vcd::TimescaleUnit::PS)));
+
+    let changes = read_vcd_changes(&out_file);
+    let expected: Vec<(u64, String)> = [
+        (0, "xx"),
+        (1000, "00"),
+        (1200, "01"),
+        (1600, "10"),
+        (1700, "11"),
+        (1800, "00"),
+    ]
+    .iter()
+    .map(|(time, value)| (*time, value.to_string()))
+    .collect();
+    assert_eq!(changes["Handshake.state"], expected);
+
+    // The timestamps cannot be negative
+    let out_path = temp_path("handshake_negative_offset.vcd");
+    let mut rewriter = VcdRewriter::new(
+        vcd_path,
+        handshake_scopes(),
+        out_path.to_string_lossy().to_string(),
+    )
+    .unwrap()
+    .with_time_offset(-1);
+    assert!(matches!(rewriter.rewrite(), Err(VcdRewriteError::Other(_))));
+}
+
+#[test]
+fn rewrite_timescale_without_source_timescale() {
+    let vcd = std::fs::read_to_string("tests/inputs/waveform/handshake.vcd").unwrap();
+    let vcd: String = vcd
+        .lines()
+        .filter(|line| !line.contains("$timescale"))
+        .map(|line| format!("{}\n", line))
+        .collect();
+    let vcd_path = temp_path("handshake_no_timescale.vcd");
+    std::fs::write(&vcd_path, vcd).unwrap();
+
+    let out_path = temp_path("handshake_no_timescale_out.vcd");
+    let mut rewriter = VcdRewriter::new(
+        &vcd_path,
+        handshake_scopes(),
+        out_path.to_string_lossy().to_string(),
+    )
+    .unwrap()
+    .with_timescale(100, vcd::TimescaleUnit::PS);
+    assert!(matches!(rewriter.rewrite(), Err(VcdRewriteError::Other(_))));
+}
+
+#[test]
+fn rewrite_clock_cycles() {
+    let vcd_path = Path::new("tests/inputs/waveform/handshake.vcd");
+    let out_path = temp_path("handshake_cycles.vcd");
+    let rewriter = VcdRewriter::new(
+        vcd_path,
+        handshake_scopes(),
+        out_path.to_string_lossy().to_string(),
+    )
+    .unwrap()
+    .with_clock_cycles("Handshake.clock");
+    let out_file = run_rewriter(rewriter);
+    let changes = read_vcd_changes(&out_file);
+
+    // The clock rises at 5, 15, 25...
+    let expected: Vec<(u64, String)> = [(0, "00"), (2, "01"), (6, "10"), (7, "11"), (8, "00")]
+        .iter()
+        .map(|(time, value)| (*time, value.to_string()))
+        .collect();
+    assert_eq!(changes["Handshake.state"], expected);
+    let last_count = changes["Handshake.core.count"].last().unwrap();
+    assert_eq!(last_count, &(6, "0101".to_string()));
+    // One change per variable and cycle, with the last value of the cycle
+    let mut parser = vcd::Parser::new(BufReader::new(File::open(&out_file).unwrap()));
+    parser.parse_header().unwrap();
+    let mut cycle_ids = Vec::new();
+    for command in parser {
+        match command.unwrap() {
+            vcd::Command::Timestamp(_) => cycle_ids.clear(),
+            vcd::Command::ChangeScalar(id, _) | vcd::Command::ChangeVector(id, _) => {
+                assert!(!cycle_ids.contains(&id));
+                cycle_ids.push(id);
+            }
+            _ => {}
+        }
+    }
+    assert_eq!(changes["Handshake.clock"], vec![(0, "0".to_string())]);
+
+    let out_path = temp_path("handshake_no_clock.vcd");
+    let mut rewriter = VcdRewriter::new(
+        vcd_path,
+        handshake_scopes(),
+        out_path.to_string_lossy().to_string(),
+    )
+    .unwrap()
+    .with_clock_cycles("Handshake.missing");
+    assert!(matches!(rewriter.rewrite(), Err(VcdRewriteError::Other(_))));
+}
+
+fn signal(name: &str) -> TraceValue {
+    TraceValue::RefTraceName(name.to_string())
+}
+
+fn constant(bits: &str) -> TraceValue {
+    TraceValue::Constant(ConstValue::FourValue(
+        bits.as_bytes().to_vec(),
+        bits.len() as u32,
+    ))
+}
+
+fn operation(opcode: Opcode, operands: Vec<TraceValue>) -> TraceValue {
+    TraceValue::Operation(opcode, operands)
+}
+
+#[test_case(operation(Opcode::Add, vec![signal("count"), constant("11")]), 8, &[(0, "00000011"), (5, "00000100"), (15, "00000101")] ; "add")]
+#[test_case(operation(Opcode::Sub, vec![signal("count"), constant("1")]), 8, &[(0, "11111111"), (5, "00000000"), (15, "00000001")] ; "sub")]
+#[test_case(operation(Opcode::ShiftLeft, vec![signal("count"), constant("10")]), 8, &[(0, "00000000"), (5, "00000100"), (15, "00001000")] ; "shift left")]
+#[test_case(operation(Opcode::Mux, vec![signal("clock"), signal("count"), constant("11111111")]), 8, &[(0, "11111111"), (5, "00000001"), (10, "11111111"), (15, "00000010")] ; "mux")]
+#[test_case(operation(Opcode::Extract, vec![signal("count"), constant("1"), constant("0")]), 2, &[(0, "00"), (5, "01"), (15, "10")] ; "extract")]
+#[test_case(operation(Opcode::Concat, vec![signal("clock"), operation(Opcode::Extract, vec![signal("count"), constant("11"), constant("0")])]), 5, &[(0, "00000"), (5, "10001"), (10, "00001"), (15, "10010")] ; "concat")]
+#[test_case(operation(Opcode::Replicate, vec![constant("11"), signal("clock")]), 3, &[(0, "000"), (5, "111"), (10, "000"), (15, "111")] ; "replicate")]
+#[test_case(operation(Opcode::Replicate, vec![constant(&format!("1{}", "0".repeat(40))), signal("clock")]), 4, &[(0, "xxxx")] ; "replicate too wide")]
+#[test_case(operation(Opcode::Extract, vec![signal("count"), constant(&"1".repeat(100)), constant("0")]), 2, &[(0, "xx")] ; "extract too wide")]
+#[test_case(operation(Opcode::Eq, vec![signal("count"), constant("1")]), 1, &[(0, "0"), (5, "1"), (15, "0")] ; "eq")]
+#[test_case(operation(Opcode::UnaryOrXor, vec![signal("count")]), 1, &[(0, "0"), (5, "1")] ; "xor reduction")]
+fn rewrite_computed_signal(trace_value: TraceValue, width: u128, expected: &[(u64, &str)]) {
+    let mut scope = Scope::empty(
+        "Sensor".to_string(),
+        "Sensor".to_string(),
+        TypeInfo::new("Sensor".to_string(), vec![]),
+        &[],
+    );
+    scope.variables = vec![Variable::new(
+        trace_value,
+        "computed".to_string(),
+        TypeInfo::new(format!("UInt<{}>", width), vec![]),
+        VariableKind::Ground(width),
+    )];
+    let out_path = temp_path("values_computed.vcd");
+    let input = BufReader::new(File::open("tests/inputs/waveform/values.vcd").unwrap());
+    let mut output = Vec::new();
+    VcdRewriter::from_io(input, &mut output, vec![scope])
+        .rewrite()
+        .expect("failed to rewrite");
+    std::fs::write(&out_path, output).unwrap();
+
+    let changes = read_vcd_changes(&out_path.to_string_lossy());
+    let expected: Vec<(u64, String)> = expected
+        .iter()
+        .map(|(time, value)| (*time, value.to_string()))
+        .collect();
+    assert_eq!(changes["Sensor.computed"], expected);
+}
+
+#[test_case(AggregateMode::Flatten, EnumMode::Bits, "vcd", (Some(20), Some(70)) ; "flatten")]
+#[test_case(AggregateMode::Hierarchy, EnumMode::Bits, "vcd", (Some(20), Some(70)) ; "hierarchy")]
+#[test_case(AggregateMode::Hierarchy, EnumMode::Bits, "fst", (None, None) ; "hierarchy fst")]
+#[test_case(AggregateMode::Flatten, EnumMode::Replace, "vcd", (None, None) ; "enum names")]
+fn rewrite_verify(
+    aggregate_mode: AggregateMode,
+    enum_mode: EnumMode,
+    extension: &str,
+    (start, end): (Option<u64>, Option<u64>),
+) {
+    let vcd_path = Path::new("tests/inputs/waveform/handshake.vcd");
+    let out_path = temp_path(&format!(
+        "handshake_verify_{:?}_{:?}.{}",
+        aggregate_mode, enum_mode, extension
+    ));
+    let mut rewriter = VcdRewriter::new(
+        vcd_path,
+        handshake_scopes(),
+        out_path.to_string_lossy().to_string(),
+    )
+    .unwrap()
+    .with_aggregate_mode(aggregate_mode)
+    .with_enum_mode(enum_mode)
+    .with_time_window(start, end);
+    rewriter.rewrite().expect("failed to rewrite");
+
+    let report = rewriter.verify(vcd_path).expect("failed to verify");
+    assert!(report.is_empty(), "{}", report);
+}
+
+#[test]
+fn rewrite_verify_mismatches() {
+    let input = std::fs::read("tests/inputs/waveform/values.vcd").unwrap();
+    let mut output = Vec::new();
+    VcdRewriter::from_io(input.as_slice(), &mut output, sensor_scopes())
+        .rewrite()
+        .expect("failed to rewrite");
+    let scopes = sensor_scopes();
+    let check = RewriteCheck::new(&scopes);
+    assert!(check
+        .check(input.as_slice(), output.as_slice())
+        .unwrap()
+        .is_empty());
+
+    // Corrupt the count at 5 and the status at 10
+    let corrupted = String::from_utf8(output)
+        .unwrap()
+        .replacen("b00000001 ", "b00000011 ", 1)
+        .replacen("sbusy ", "sidle ", 1);
+    let report = check.check(input.as_slice(), corrupted.as_bytes()).unwrap();
+    let mismatch = |path: &str, time: u64, expected: &str, actual: &str| RewriteMismatch {
+        path: path.to_string(),
+        time,
+        expected: expected.to_string(),
+        actual: actual.to_string(),
+    };
+    assert_eq!(
+        report.mismatches,
+        vec![
+            mismatch("Sensor.status", 10, "busy", "idle"),
+            mismatch("Sensor.count", 5, "00000001", "00000011"),
+        ]
+    );
+    assert_eq!(
+        report.to_string().lines().next(),
+        Some("Sensor.status: at time 10 expected busy, found idle")
+    );
+}
+
+#[test]
+fn rewrite_verify_enum_names() {
+    let input = std::fs::read("tests/inputs/waveform/handshake.vcd").unwrap();
+    let mut output = Vec::new();
+    VcdRewriter::from_io(input.as_slice(), &mut output, handshake_scopes())
+        .with_enum_mode(EnumMode::Replace)
+        .rewrite()
+        .expect("failed to rewrite");
+    let scopes = handshake_scopes();
+    let check = RewriteCheck::new(&scopes);
+    assert!(check
+        .check(input.as_slice(), output.as_slice())
+        .unwrap()
+        .is_empty());
+
+    // The variant names are compared with the names of the original values
+    let corrupted = String::from_utf8(output)
+        .unwrap()
+        .replacen("ssBusy ", "ssDone ", 1);
+    let report = check.check(input.as_slice(), corrupted.as_bytes()).unwrap();
+    assert_eq!(
+        report.mismatches,
+        vec![RewriteMismatch {
+            path: "Handshake.state".to_string(),
+            time: 20,
+            expected: "sBusy".to_string(),
+            actual: "sDone".to_string(),
+        }]
+    );
+}
+
+#[test]
+fn rewrite_verify_converted_times() {
+    let vcd_path = Path::new("tests/inputs/waveform/handshake.vcd");
+    let out_path = temp_path("handshake_verify_offset.vcd");
+    let mut rewriter = VcdRewriter::new(
+        vcd_path,
+        handshake_scopes(),
+        out_path.to_string_lossy().to_string(),
+    )
+    .unwrap()
+    .with_time_offset(100);
+    rewriter.rewrite().expect("failed to rewrite");
+    assert!(matches!(
+        rewriter.verify(vcd_path),
+        Err(VcdRewriteError::Other(_))
+    ));
+}
+
+#[test]
+fn rewrite_invalid_extension() {
+    let out_path = temp_path("handshake_rewrite.txt");
+    let result = VcdRewriter::new(
+        Path::new("tests/inputs/waveform/handshake.vcd"),
+        handshake_scopes(),
+        out_path.to_string_lossy().to_string(),
+    );
+    assert!(matches!(
+        result,
+        Err(VcdRewriteError::InvalidFileExtension(_))
+    ));
+}
 
 #[test]
 #[rustfmt::skip]
@@ -38,6 +1322,116 @@ fn vcd_rewrite_variable() {
     );
 }
 
+#[test]
+fn vcd_rewrite_variable_unknown_expression() {
+    let vcd = "$scope module Top $end\n$var wire 4 ! a $end\n$upscope $end\n$enddefinitions $end\n";
+    let header = vcd::Parser::new(vcd.as_bytes()).parse_header().unwrap();
+    let ground = |trace_value: TraceValue, name: &str, width: u128| {
+        Variable::new(
+            trace_value,
+            name.to_string(),
+            TypeInfo::new(format!("UInt<{}>", width), vec![]),
+            VariableKind::Ground(width),
+        )
+    };
+    let trace_name = |name: &str| TraceValue::RefTraceName(name.to_string());
+    let variable = Variable::new(
+        TraceValue::RefTraceValues(vec![]),
+        "s".to_string(),
+        TypeInfo::new("S".to_string(), vec![]),
+        VariableKind::Struct {
+            fields: vec![
+                // The signal of the expression is not in the trace
+                ground(
+                    TraceValue::Operation(
+                        Opcode::Add,
+                        vec![trace_name("missing"), trace_name("a")],
+                    ),
+                    "sum",
+                    2,
+                ),
+                ground(trace_name("a"), "a", 4),
+            ],
+        },
+    );
+
+    let mut rewrite_variable = VcdRewriteVariable::create(
+        vcd::IdCode::FIRST,
+        6,
+        &variable,
+        &["Top".to_string()],
+        &header,
+    );
+    assert!(rewrite_variable
+        .update_value(
+            &"!".parse().unwrap(),
+            &vcd::Vector::from_str("0101").unwrap()
+        )
+        .is_ok());
+    assert_eq!(
+        rewrite_variable.get_value(),
+        vcd::Vector::from_str("xx0101").unwrap()
+    );
+}
+
+#[test]
+fn vcd_rewrite_variable_wider_sources() {
+    let id_code_a = vcd::IdCode::FIRST.next();
+    let id_code_b = id_code_a.next();
+    let variable = VcdRewriteVariable::new(
+        vcd::IdCode::FIRST,
+        4,
+        vec![
+            IdCodeWithShift::create(id_code_a, vcd::Vector::from_str("000").unwrap(), false),
+            IdCodeWithShift::create(id_code_b, vcd::Vector::from_str("11").unwrap(), false),
+        ],
+    );
+    // Only the least significant bit of the last source fits
+    assert_eq!(variable.get_value(), vcd::Vector::from_str("1000").unwrap());
+}
+
+#[test]
+fn vcd_rewrite_variable_aliases() {
+    let id_code = vcd::IdCode::FIRST;
+    let id_code_a = id_code.next();
+
+    // The constant uses the id code of the new variable, which is also an original id code.
+    // The first source is the least significant one.
+    let mut variable = VcdRewriteVariable::new(
+        id_code,
+        6,
+        vec![
+            IdCodeWithShift::create(id_code, vcd::Vector::from_str("1").unwrap(), true),
+            IdCodeWithShift::create(id_code_a, vcd::Vector::from_str("00").unwrap(), false),
+            IdCodeWithShift::create(id_code, vcd::Vector::from_str("0").unwrap(), false),
+            IdCodeWithShift::create(id_code_a, vcd::Vector::from_str("00").unwrap(), false),
+        ],
+    );
+    assert_eq!(
+        variable.get_value(),
+        vcd::Vector::from_str("000001").unwrap()
+    );
+
+    assert!(variable
+        .update_value(&id_code_a, &vcd::Vector::from_str("11").unwrap())
+        .unwrap());
+    assert_eq!(
+        variable.get_value(),
+        vcd::Vector::from_str("110111").unwrap()
+    );
+
+    assert!(variable
+        .update_value(&id_code, &vcd::Vector::from_str("0").unwrap())
+        .is_ok_and(|changed| !changed));
+    assert!(variable
+        .update_value(&id_code, &vcd::Vector::from_str("1").unwrap())
+        .unwrap());
+    assert_eq!(
+        variable.get_value(),
+        vcd::Vector::from_str("111111").unwrap()
+    );
+}
+
 #[test]
 fn vcd_rewrite_failure() {}
 
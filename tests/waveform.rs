@@ -0,0 +1,1035 @@
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::{Arc, RwLock};
+
+use test_case::test_case;
+
+use tywaves_rs::hgldd;
+use tywaves_rs::hgldd::spec::Opcode;
+use tywaves_rs::tyvcd::{
+    builder::GenericBuilder,
+    builder::TyVcdBuilder,
+    spec::{Scope, TyVcd, TypeInfo, Variable, VariableKind},
+    trace_pointer::{TraceGetter, TraceValue},
+    value::DecodedValue,
+};
+use tywaves_rs::waveform::coverage::{
+    BitToggle, CoverageReport, CoverageSummary, EnumValueCoverage,
+};
+use tywaves_rs::waveform::db::{split_path, WaveformDb, WaveformError};
+use tywaves_rs::waveform::diff::{Alignment, ValueDifference, WaveformDiff};
+use tywaves_rs::waveform::export::{ExportFormat, TraceExporter};
+use tywaves_rs::waveform::query::{Expr, Interval, Query, QueryError};
+use tywaves_rs::waveform::sampling::{ClockSampler, CycleSnapshot, SamplingPoint, SignalSelector};
+use tywaves_rs::waveform::source::{self, TraceFormat, WaveformSource};
+use tywaves_rs::waveform::transactions::{
+    HandshakeBundle, HandshakeKind, HandshakeStats, Transaction, TransactionAnalyzer,
+};
+
+use pretty_assertions::assert_eq;
+
+// Load the handshake trace with its typed information.
+fn load_handshake() -> WaveformDb {
+    load_handshake_vcd("tests/inputs/waveform/handshake.vcd")
+}
+
+// Load a trace of the handshake design with its typed information.
+fn load_handshake_vcd(vcd_path: &str) -> WaveformDb {
+    WaveformDb::open(Path::new(vcd_path), handshake_tyvcd()).expect("failed to load the waveform")
+}
+
+// Build the typed information of the handshake design.
+fn handshake_tyvcd() -> TyVcd {
+    let hgldd = hgldd::reader::parse_hgldd_file(Path::new("tests/inputs/waveform/handshake.dd"))
+        .expect("error parsing hgldd");
+    let mut builder = TyVcdBuilder::init(hgldd);
+    builder.build().expect("build failed");
+    builder.get_copy().unwrap()
+}
+
+// Add a packed struct `Handshake.packed {hi, lo}` stored in the single signal `io_in_bits_data`.
+fn packed_handshake_tyvcd() -> TyVcd {
+    let tyvcd = handshake_tyvcd();
+    let nibble = |name: &str| {
+        Variable::new(
+            TraceValue::RefTraceValues(Vec::new()),
+            name.to_string(),
+            TypeInfo::new("UInt<4>".to_string(), Vec::new()),
+            VariableKind::Ground(4),
+        )
+    };
+    let packed = Variable::new(
+        TraceValue::RefTraceName("io_in_bits_data".to_string()),
+        "packed".to_string(),
+        TypeInfo::new("Packed".to_string(), Vec::new()),
+        VariableKind::Struct {
+            fields: vec![nibble("hi"), nibble("lo")],
+        },
+    );
+    for scope in tyvcd.scopes.values() {
+        let mut scope = scope.write().unwrap();
+        if scope.get_trace_name().map(String::as_str) == Some("Handshake") {
+            scope.variables.push(packed.clone());
+        }
+    }
+    tyvcd
+}
+
+fn enum_val(name: &str) -> DecodedValue {
+    DecodedValue::Enum(name.to_string())
+}
+
+#[test_case("Top.dut.io.bits.data[3]", &["Top", "dut", "io", "bits", "data", "3"]; "Test fields and index")]
+#[test_case("Top.v[1][0]", &["Top", "v", "1", "0"]; "Test multi dim index")]
+#[test_case("Top.v.1", &["Top", "v", "1"]; "Test index as field")]
+fn test_split_path(path: &str, expected: &[&str]) {
+    assert_eq!(split_path(path), expected);
+}
+
+#[test]
+fn test_value_at() {
+    let db = load_handshake();
+    assert_eq!(db.end_time(), 90);
+
+    let bits = |op: &str, data: i128| {
+        DecodedValue::Struct(vec![
+            ("op".to_string(), enum_val(op)),
+            ("data".to_string(), DecodedValue::Signed(data)),
+        ])
+    };
+    assert_eq!(
+        db.value_at("Handshake.io.in.bits", 25).unwrap(),
+        bits("ADD", 5)
+    );
+    assert_eq!(
+        db.value_at("Handshake.io.in.bits", 30).unwrap(),
+        bits("SUB", -3)
+    );
+    assert_eq!(
+        db.value_at("Handshake.io.in.bits.data", 39).unwrap(),
+        DecodedValue::Signed(-3)
+    );
+    assert_eq!(
+        db.value_at("Handshake.io.vec[1]", 30).unwrap(),
+        DecodedValue::Unsigned(2)
+    );
+    assert_eq!(
+        db.value_at("Handshake.io.out", 50).unwrap(),
+        DecodedValue::Struct(vec![
+            ("valid".to_string(), DecodedValue::Unsigned(1)),
+            ("bits".to_string(), DecodedValue::Unsigned(2)),
+        ])
+    );
+    assert_eq!(
+        db.value_at("Handshake.state", 60).unwrap(),
+        enum_val("sDone")
+    );
+    // Unknown encodings are not enum variants
+    assert_eq!(
+        db.value_at("Handshake.state", 70).unwrap(),
+        DecodedValue::Unsigned(3)
+    );
+    // Subscopes
+    assert_eq!(
+        db.value_at("Handshake.core.count", 55).unwrap(),
+        DecodedValue::Unsigned(4)
+    );
+
+    // Whole variable
+    let io = db.value_at("Handshake.io", 50).unwrap();
+    assert_eq!(
+        io.to_string(),
+        "{in: {ready: 1, valid: 1, bits: {op: MUL, data: 7}}, out: {valid: 1, bits: 2}, vec: [3, 2]}"
+    );
+}
+
+#[test_case(80; "Test real wider than 64 bits")]
+#[test_case(64; "Test real of 64 bits")]
+#[test_case(32; "Test real narrower than 64 bits")]
+fn test_real_signal_bits(width: u32) {
+    let vcd = format!(
+        "$scope module Sensor $end\n$var real {} ! temperature $end\n$upscope $end\n\
+         $enddefinitions $end\n#0\nr20.5 !\n",
+        width
+    );
+    let mut scope = Scope::empty(
+        "Sensor".to_string(),
+        "Sensor".to_string(),
+        TypeInfo::new("Sensor".to_string(), Vec::new()),
+        &[],
+    );
+    scope.variables.push(Variable::new(
+        TraceValue::RefTraceName("temperature".to_string()),
+        "temperature".to_string(),
+        TypeInfo::new(format!("UInt<{}>", width), Vec::new()),
+        VariableKind::Ground(width as u128),
+    ));
+    let tyvcd = TyVcd {
+        scopes: HashMap::from([("Sensor".to_string(), Arc::new(RwLock::new(scope)))]),
+    };
+    let db = WaveformDb::from_reader(vcd.as_bytes(), tyvcd).unwrap();
+
+    // The IEEE 754 bits of the real value, zero-extended or truncated to the declared width
+    let real_bits = format!("{:0>80b}", 20.5f64.to_bits());
+    let signal = db.find_signal("Sensor.temperature").unwrap();
+    assert_eq!(
+        db.signal_bits_at(&signal, 0),
+        real_bits[80 - width as usize..]
+    );
+}
+
+#[test_case("Handshake.unknown"; "Test unknown variable")]
+#[test_case("Handshake.io.in.bits.addr"; "Test unknown field")]
+#[test_case("Handshake.io.vec[2]"; "Test index out of range")]
+#[test_case("Unknown.io"; "Test unknown scope")]
+#[test_case(""; "Test empty path")]
+fn test_path_not_found(path: &str) {
+    let db = load_handshake();
+    assert!(matches!(
+        db.value_at(path, 0),
+        Err(WaveformError::PathNotFound(_))
+    ));
+}
+
+#[test]
+fn test_values_between() {
+    let db = load_handshake();
+    let values = db.values_between("Handshake.io.in.valid", 0, 90).unwrap();
+    assert_eq!(
+        values,
+        vec![
+            (0, DecodedValue::Unsigned(0)),
+            (10, DecodedValue::Unsigned(1)),
+            (40, DecodedValue::Unsigned(0)),
+            (50, DecodedValue::Unsigned(1)),
+            (60, DecodedValue::Unsigned(0)),
+        ]
+    );
+
+    // Aggregates change when any of their fields changes
+    let values = db.values_between("Handshake.io.in.bits", 15, 45).unwrap();
+    let times: Vec<u64> = values.iter().map(|(t, _)| *t).collect();
+    assert_eq!(times, vec![15, 30]);
+
+    // The clock is shared with the subscope
+    let clock = db.values_between("Handshake.core.clock", 0, 20).unwrap();
+    assert_eq!(clock.len(), 5);
+}
+
+#[test]
+fn test_typed_changes() {
+    let db = load_handshake();
+    let signal = db.find_signal("Handshake.io.in").unwrap();
+    let changes: Vec<(u64, DecodedValue)> = db.changes(&signal).collect();
+
+    // One change per time where any field changes
+    let times: Vec<u64> = changes.iter().map(|(t, _)| *t).collect();
+    assert_eq!(times, vec![0, 10, 20, 30, 40, 50, 60]);
+    assert_eq!(
+        changes[3].1.to_string(),
+        "{ready: 1, valid: 1, bits: {op: SUB, data: -3}}"
+    );
+
+    // The clock toggles do not generate changes in variables that do not depend on it
+    let state: Vec<(u64, DecodedValue)> = db
+        .changes(&db.find_signal("Handshake.state").unwrap())
+        .collect();
+    assert_eq!(
+        state,
+        vec![
+            (0, enum_val("sIdle")),
+            (20, enum_val("sBusy")),
+            (60, enum_val("sDone")),
+            (70, DecodedValue::Unsigned(3)),
+            (80, enum_val("sIdle")),
+        ]
+    );
+
+    // Same result as the range query
+    let expected = db
+        .values_between("Handshake.io.in", 0, db.end_time())
+        .unwrap();
+    assert_eq!(changes, expected);
+}
+
+#[test]
+fn test_variable_changes() {
+    let db = load_handshake();
+    let scope = db.get_tyvcd().scopes.get("Handshake").unwrap().clone();
+    let scope = scope.read().unwrap();
+    let core = scope.subscopes.get("core").unwrap().read().unwrap();
+    let count = core.variables.iter().find(|v| v.name == "count").unwrap();
+
+    let scope_path = vec!["Handshake".to_string(), "core".to_string()];
+    let mut changes = db.variable_changes(count, &scope_path);
+    assert_eq!(changes.get_signal().path, "Handshake.core.count");
+    let values: Vec<DecodedValue> = changes.by_ref().map(|(_, v)| v).collect();
+    let expected: Vec<DecodedValue> = (0..=5).map(DecodedValue::Unsigned).collect();
+    assert_eq!(values, expected);
+    assert!(changes.next().is_none());
+}
+
+#[test_case(SignalSelector::Clock("Handshake".to_string()), "Handshake.clock"; "Test clock by type")]
+#[test_case(SignalSelector::Path("Handshake.clock".to_string()), "Handshake.clock"; "Test clock by path")]
+#[test_case(SignalSelector::Reset("Handshake".to_string()), "Handshake.reset"; "Test reset by name")]
+#[test_case(SignalSelector::Type { scope: "Handshake.core".to_string(), type_name: "UInt<4>".to_string() }, "Handshake.core.count"; "Test by base type name")]
+fn test_signal_selector(selector: SignalSelector, expected_path: &str) {
+    let db = load_handshake();
+    let signal = selector.find(&db).expect("signal not found");
+    assert_eq!(signal.path, expected_path);
+}
+
+#[test]
+fn test_clock_sampler_invalid_clock() {
+    let db = load_handshake();
+    let clock = SignalSelector::Path("Handshake.state".to_string());
+    assert!(matches!(
+        ClockSampler::new(&db, &clock),
+        Err(WaveformError::InvalidSignal(_))
+    ));
+}
+
+#[test]
+fn test_clock_sampler() {
+    let db = load_handshake();
+    let sampler = ClockSampler::new(&db, &SignalSelector::Clock("Handshake".to_string()))
+        .unwrap()
+        .with_reset(&SignalSelector::Reset("Handshake".to_string()), true)
+        .unwrap()
+        .with_variable(&SignalSelector::Path("Handshake.state".to_string()))
+        .unwrap()
+        .with_variable(&SignalSelector::Path("Handshake.core.count".to_string()))
+        .unwrap();
+
+    assert_eq!(
+        sampler.rising_edges(),
+        vec![5, 15, 25, 35, 45, 55, 65, 75, 85]
+    );
+
+    let snapshots = sampler.sample();
+    assert_eq!(snapshots.len(), 9);
+    assert_eq!(
+        snapshots[0],
+        CycleSnapshot {
+            cycle: 0,
+            time: 5,
+            in_reset: true,
+            values: vec![
+                ("Handshake.state".to_string(), enum_val("sIdle")),
+                (
+                    "Handshake.core.count".to_string(),
+                    DecodedValue::Unsigned(0)
+                ),
+            ],
+        }
+    );
+    assert!(snapshots[1..].iter().all(|s| !s.in_reset));
+
+    let states: Vec<_> = snapshots
+        .iter()
+        .map(|s| s.get("Handshake.state").unwrap().to_string())
+        .collect();
+    assert_eq!(
+        states,
+        // 3 is not a valid encoding of the enum
+        vec!["sIdle", "sIdle", "sBusy", "sBusy", "sBusy", "sBusy", "sDone", "3", "sIdle"]
+    );
+    assert_eq!(
+        snapshots[4].get("Handshake.core.count"),
+        Some(&DecodedValue::Unsigned(3))
+    );
+}
+
+#[test]
+fn test_clock_sampler_scope() {
+    let db = load_handshake();
+    let sampler = ClockSampler::new(&db, &SignalSelector::Clock("Handshake.core".to_string()))
+        .unwrap()
+        .with_scope("Handshake.core")
+        .unwrap()
+        .with_sampling_point(SamplingPoint::AtEdge);
+    let snapshots = sampler.sample();
+    assert_eq!(snapshots.len(), 9);
+    // Without a reset, no cycle is in reset
+    assert!(snapshots.iter().all(|s| !s.in_reset));
+    assert_eq!(
+        snapshots[3].get("Handshake.core.count"),
+        Some(&DecodedValue::Unsigned(2))
+    );
+}
+
+#[test]
+fn test_detect_handshake_bundles() {
+    let db = load_handshake();
+    let bundles: Vec<_> = HandshakeBundle::detect(&db)
+        .into_iter()
+        .map(|bundle| (bundle.path, bundle.kind))
+        .collect();
+    assert_eq!(
+        bundles,
+        vec![
+            ("Handshake.io.in".to_string(), HandshakeKind::Decoupled),
+            ("Handshake.io.out".to_string(), HandshakeKind::Valid),
+        ]
+    );
+}
+
+#[test]
+fn test_detect_handshake_bundles_by_fields() {
+    // A struct of an unknown type with the fields of a Valid bundle
+    let tyvcd = handshake_tyvcd();
+    let ground = |trace_name: &str, name: &str, type_name: &str, width: u128| {
+        Variable::new(
+            TraceValue::RefTraceName(trace_name.to_string()),
+            name.to_string(),
+            TypeInfo::new(type_name.to_string(), Vec::new()),
+            VariableKind::Ground(width),
+        )
+    };
+    let status = Variable::new(
+        TraceValue::RefTraceValues(Vec::new()),
+        "status".to_string(),
+        TypeInfo::new("Status".to_string(), Vec::new()),
+        VariableKind::Struct {
+            fields: vec![
+                ground("io_out_valid", "valid", "Bool", 1),
+                ground("io_out_bits", "bits", "UInt<8>", 8),
+            ],
+        },
+    );
+    for scope in tyvcd.scopes.values() {
+        scope.write().unwrap().variables.push(status.clone());
+    }
+    let db = WaveformDb::open(Path::new("tests/inputs/waveform/handshake.vcd"), tyvcd).unwrap();
+
+    let paths = |bundles: Vec<HandshakeBundle>| -> Vec<String> {
+        bundles.into_iter().map(|bundle| bundle.path).collect()
+    };
+    assert_eq!(
+        paths(HandshakeBundle::detect(&db)),
+        vec!["Handshake.io.in", "Handshake.io.out"]
+    );
+    assert_eq!(
+        paths(HandshakeBundle::detect_by_fields(&db)),
+        vec!["Handshake.io.in", "Handshake.io.out", "Handshake.status"]
+    );
+    // An explicit bundle can have any type
+    let bundle = HandshakeBundle::find(&db, "Handshake.status").unwrap();
+    assert_eq!(bundle.kind, HandshakeKind::Valid);
+}
+
+#[test]
+fn test_transaction_analyzer() {
+    let db = load_handshake();
+    let reports = TransactionAnalyzer::new(&db, &SignalSelector::Clock("Handshake".to_string()))
+        .unwrap()
+        .with_reset(&SignalSelector::Reset("Handshake".to_string()), true)
+        .unwrap()
+        .with_detected_bundles()
+        .analyze();
+    assert_eq!(reports.len(), 2);
+
+    let bits = |op: &str, data: i128| {
+        DecodedValue::Struct(vec![
+            ("op".to_string(), enum_val(op)),
+            ("data".to_string(), DecodedValue::Signed(data)),
+        ])
+    };
+    let decoupled = &reports[0];
+    assert_eq!(decoupled.path, "Handshake.io.in");
+    assert_eq!(
+        decoupled.transactions,
+        vec![
+            Transaction {
+                time: 25,
+                cycle: 2,
+                bits: bits("ADD", 5),
+            },
+            Transaction {
+                time: 35,
+                cycle: 3,
+                bits: bits("SUB", -3),
+            },
+            Transaction {
+                time: 55,
+                cycle: 5,
+                bits: bits("MUL", 7),
+            },
+        ]
+    );
+    assert_eq!(
+        decoupled.stats,
+        HandshakeStats {
+            cycles: 8,
+            fired: 3,
+            stalled: 1,
+            starved: 1,
+            idle: 3,
+            max_stall: 1,
+        }
+    );
+    assert_eq!(decoupled.stats.backpressure(), 0.25);
+
+    let valid = &reports[1];
+    assert_eq!(valid.path, "Handshake.io.out");
+    let fired: Vec<_> = valid
+        .transactions
+        .iter()
+        .map(|t| (t.cycle, t.bits.clone()))
+        .collect();
+    assert_eq!(
+        fired,
+        vec![
+            (4, DecodedValue::Unsigned(5)),
+            (5, DecodedValue::Unsigned(2)),
+            (6, DecodedValue::Unsigned(0x23)),
+        ]
+    );
+    // Without ready, the cycles without a transaction are idle
+    assert_eq!(
+        valid.stats,
+        HandshakeStats {
+            cycles: 8,
+            fired: 3,
+            stalled: 0,
+            starved: 0,
+            idle: 5,
+            max_stall: 0,
+        }
+    );
+}
+
+#[test]
+fn test_handshake_bundle_invalid() {
+    let db = load_handshake();
+    assert!(matches!(
+        HandshakeBundle::find(&db, "Handshake.io.vec"),
+        Err(WaveformError::InvalidSignal(_))
+    ));
+}
+
+#[test]
+fn test_diff_same_waveform() {
+    let db = load_handshake();
+    let report = WaveformDiff::new(&db, &db).compare().unwrap();
+    assert!(report.is_empty());
+    assert_eq!(report.to_string(), "");
+}
+
+#[test_case(Alignment::Time { offset: 20 }, (30, 50, None); "Test time offset")]
+#[test_case(Alignment::ClockCycles { clock: SignalSelector::Clock("Handshake".to_string()), offset: 2 }, (34, 54, Some(3)); "Test clock cycle alignment")]
+fn test_diff_delayed_waveform(alignment: Alignment, expected: (u64, u64, Option<u64>)) {
+    let left = load_handshake();
+    let right = load_handshake_vcd("tests/inputs/waveform/handshake_delayed.vcd");
+    let report = WaveformDiff::new(&left, &right)
+        .with_alignment(alignment)
+        .compare()
+        .unwrap();
+    assert!(report.missing.is_empty());
+
+    let divergences: Vec<_> = report
+        .divergences
+        .iter()
+        .map(|d| (d.path.as_str(), (d.left_time, d.right_time, d.cycle)))
+        .collect();
+    assert_eq!(divergences, vec![("Handshake.io", expected)]);
+    assert_eq!(
+        report.divergences[0].differences,
+        vec![
+            ValueDifference {
+                path: "Handshake.io.in.bits.data".to_string(),
+                left: DecodedValue::Signed(-3),
+                right: DecodedValue::Signed(-4),
+            },
+            ValueDifference {
+                path: "Handshake.io.vec[1]".to_string(),
+                left: DecodedValue::Unsigned(2),
+                right: DecodedValue::Unsigned(3),
+            },
+        ]
+    );
+}
+
+#[test]
+fn test_diff_report_output() {
+    let left = load_handshake();
+    let right = load_handshake_vcd("tests/inputs/waveform/handshake_delayed.vcd");
+    let report = WaveformDiff::new(&left, &right)
+        .with_alignment(Alignment::Time { offset: 20 })
+        .with_variable("Handshake.io.vec")
+        .with_variable("Handshake.state")
+        .compare()
+        .unwrap();
+
+    assert_eq!(
+        report.to_string(),
+        "Handshake.io.vec: diverges at time 30 (right 50)\n    Handshake.io.vec[1]: 2 != 3\n"
+    );
+    let json: serde_json::Value = serde_json::from_str(&report.to_json()).unwrap();
+    assert_eq!(
+        json,
+        serde_json::json!({
+            "divergences": [{
+                "path": "Handshake.io.vec",
+                "left_time": 30,
+                "right_time": 50,
+                "cycle": null,
+                "left": [1, 2],
+                "right": [1, 3],
+                "differences": [{"path": "Handshake.io.vec[1]", "left": 2, "right": 3}],
+            }],
+            "missing": [],
+            "added": [],
+        })
+    );
+}
+
+#[test]
+fn test_diff_missing_and_added_paths() {
+    let db = load_handshake();
+    let packed_db = WaveformDb::open(
+        Path::new("tests/inputs/waveform/handshake.vcd"),
+        packed_handshake_tyvcd(),
+    )
+    .unwrap();
+
+    let report = WaveformDiff::new(&db, &packed_db).compare().unwrap();
+    assert!(report.divergences.is_empty());
+    assert!(report.missing.is_empty());
+    assert_eq!(report.added, vec!["Handshake.packed"]);
+    assert_eq!(
+        report.to_string(),
+        "Handshake.packed: added in the right waveform\n"
+    );
+
+    let report = WaveformDiff::new(&packed_db, &db).compare().unwrap();
+    assert_eq!(report.missing, vec!["Handshake.packed"]);
+    assert!(report.added.is_empty());
+
+    // The selected variables are reported as well
+    let report = WaveformDiff::new(&db, &packed_db)
+        .with_variable("Handshake.packed")
+        .with_variable("Handshake.state")
+        .compare()
+        .unwrap();
+    assert!(report.divergences.is_empty());
+    assert_eq!(report.added, vec!["Handshake.packed"]);
+}
+
+#[test]
+fn test_coverage_report() {
+    let db = load_handshake();
+    let report = CoverageReport::compute(&db).unwrap();
+    let summary = |toggles, toggles_covered, enum_values, enum_values_covered| CoverageSummary {
+        toggles,
+        toggles_covered,
+        enum_values,
+        enum_values_covered,
+    };
+
+    // Ground leaves
+    let leaf = |path: &str| report.leaves.iter().find(|l| l.path == path).unwrap();
+    assert_eq!(report.leaves.len(), 13);
+    assert_eq!(
+        leaf("Handshake.reset").bits,
+        vec![BitToggle {
+            rise: false,
+            fall: true
+        }]
+    );
+    // 0 -> 1 -> 2 -> 3 -> 0: bit 2 never falls
+    assert_eq!(leaf("Handshake.core.count").summary, summary(8, 5, 0, 0));
+    assert!(!leaf("Handshake.core.count").bits[2].fall);
+    assert_eq!(leaf("Handshake.io.vec[1]").summary, summary(8, 1, 0, 0));
+
+    // Enum values: the invalid encoding 3 of the state is not an enum value
+    let enum_value = |value, name: &str| EnumValueCoverage {
+        value,
+        name: name.to_string(),
+        observed: true,
+    };
+    assert_eq!(
+        leaf("Handshake.state").enum_values,
+        vec![
+            enum_value(0, "sIdle"),
+            enum_value(1, "sBusy"),
+            enum_value(2, "sDone")
+        ]
+    );
+    assert_eq!(leaf("Handshake.state").summary, summary(4, 4, 3, 3));
+
+    // Aggregates
+    let structs: Vec<_> = report
+        .structs
+        .iter()
+        .map(|s| (s.name.as_str(), s.summary))
+        .collect();
+    assert_eq!(
+        structs,
+        vec![
+            ("Handshake.io", summary(58, 31, 3, 3)),
+            ("Handshake.io.in", summary(24, 20, 3, 3)),
+            ("Handshake.io.in.bits", summary(20, 16, 3, 3)),
+            ("Handshake.io.out", summary(18, 8, 0, 0)),
+            ("Handshake.io.vec", summary(16, 3, 0, 0)),
+        ]
+    );
+    let modules: Vec<_> = report
+        .modules
+        .iter()
+        .map(|m| (m.name.as_str(), m.summary))
+        .collect();
+    assert_eq!(
+        modules,
+        vec![
+            ("Core", summary(10, 7, 0, 0)),
+            ("Handshake", summary(66, 38, 6, 6))
+        ]
+    );
+    assert_eq!(report.scopes[1].name, "Handshake.core");
+    assert_eq!(report.scopes[1].type_name, "Core");
+    assert_eq!(report.total, summary(76, 45, 6, 6));
+}
+
+#[test]
+fn test_coverage_report_json() {
+    let db = load_handshake();
+    let json = CoverageReport::compute(&db).unwrap().to_json();
+    // The report is stable across runs
+    assert_eq!(json, CoverageReport::compute(&db).unwrap().to_json());
+
+    let json: serde_json::Value = serde_json::from_str(&json).unwrap();
+    assert_eq!(
+        json["total"],
+        serde_json::json!({
+            "toggles": 76,
+            "toggles_covered": 45,
+            "enum_values": 6,
+            "enum_values_covered": 6,
+        })
+    );
+    assert_eq!(json["leaves"][0]["path"], "Handshake.clock");
+    assert_eq!(
+        json["leaves"][0]["bits"],
+        serde_json::json!([{"rise": true, "fall": true}])
+    );
+}
+
+#[test]
+fn test_parse_query_expr() {
+    let ident = |name: &str| Box::new(Expr::Ident(name.to_string()));
+    assert_eq!(
+        Expr::parse("dut.io.in.valid && dut.io.in.bits.op == ADD").unwrap(),
+        Expr::LogicAnd(
+            ident("dut.io.in.valid"),
+            Box::new(Expr::Op(
+                Opcode::Eq,
+                vec![*ident("dut.io.in.bits.op"), *ident("ADD")]
+            ))
+        )
+    );
+    assert_eq!(
+        Expr::parse("!a.v[1][3:2] + 0x10 * 2").unwrap(),
+        Expr::Op(
+            Opcode::Add,
+            vec![
+                Expr::Not(Box::new(Expr::Op(
+                    Opcode::Extract,
+                    vec![*ident("a.v[1]"), Expr::Const(3), Expr::Const(2)]
+                ))),
+                Expr::Op(Opcode::Mul, vec![Expr::Const(16), Expr::Const(2)]),
+            ]
+        )
+    );
+    assert_eq!(
+        Expr::parse("c ? a : b ? 1 : 2").unwrap(),
+        Expr::Op(
+            Opcode::Mux,
+            vec![
+                *ident("c"),
+                *ident("a"),
+                Expr::Op(
+                    Opcode::Mux,
+                    vec![*ident("b"), Expr::Const(1), Expr::Const(2)]
+                ),
+            ]
+        )
+    );
+}
+
+#[test_case("Handshake.state ==", 18; "Test missing operand")]
+#[test_case("(Handshake.state == 1", 21; "Test missing parenthesis")]
+#[test_case("Handshake.state @ 1", 16; "Test invalid character")]
+#[test_case("Handshake.state === 1", 16; "Test case equality")]
+#[test_case("Handshake.state !=? 1", 16; "Test wildcard inequality")]
+#[test_case("Handshake.core.count[128:0]", 20; "Test bit range too wide")]
+#[test_case("Handshake.core.count[0:1]", 20; "Test reversed bit range")]
+#[test_case("Handshake.core.count[170141183460469231731687303715884105727:0]", 20; "Test huge bit range")]
+fn test_parse_query_error(expr: &str, expected_position: usize) {
+    match Expr::parse(expr) {
+        Err(QueryError::ParseError { position, .. }) => assert_eq!(position, expected_position),
+        other => panic!("expected a parse error, found {:?}", other),
+    }
+}
+
+#[test_case("Handshake.io.in.valid && Handshake.io.in.bits.op == ADD", &[(10, 30)]; "Test enum variant")]
+#[test_case("Handshake.io.in.valid && Handshake.io.in.ready", &[(20, 40), (50, 60)]; "Test fire")]
+#[test_case("Handshake.state == sBusy || Handshake.state == sDone", &[(20, 70)]; "Test state enum")]
+#[test_case("Handshake.io.in.bits.data < 0", &[(30, 50)]; "Test signed comparison")]
+#[test_case("Handshake.core.count[2]", &[(50, 90)]; "Test extract bit")]
+#[test_case("Handshake.core.count[126:0] == 5", &[(60, 90)]; "Test extract 127 bits")]
+#[test_case("Handshake.core.count[127:0] == 5", &[(60, 90)]; "Test extract 128 bits")]
+#[test_case("(Handshake.reset ? 0 : Handshake.core.count) == 5", &[(60, 90)]; "Test mux")]
+#[test_case("Handshake.io.in.bits.data >> 1 == 126", &[(30, 50)]; "Test logical shift of a signed value")]
+#[test_case("Handshake.io.in.bits.data >> 7 == 1", &[(30, 50)]; "Test logical shift of the sign")]
+#[test_case("Handshake.io.vec[0] + Handshake.io.vec[1] >= 4", &[(50, 90)]; "Test vector elements")]
+fn test_query_intervals(expr: &str, expected: &[(u64, u64)]) {
+    let db = load_handshake();
+    let query = Query::new(&db, expr).unwrap();
+    let expected: Vec<_> = expected
+        .iter()
+        .map(|(start, end)| Interval {
+            start: *start,
+            end: *end,
+        })
+        .collect();
+    assert_eq!(query.intervals(), expected);
+}
+
+#[test]
+fn test_query_cycles() {
+    let db = load_handshake();
+    let sampler = ClockSampler::new(&db, &SignalSelector::Clock("Handshake".to_string())).unwrap();
+    let query = Query::new(&db, "Handshake.io.in.valid && Handshake.io.in.ready").unwrap();
+    assert_eq!(query.cycles(&sampler), vec![(2, 25), (3, 35), (5, 55)]);
+}
+
+#[test]
+fn test_query_unknown_identifier() {
+    let db = load_handshake();
+    assert!(matches!(
+        Query::new(&db, "Handshake.state == FOO"),
+        Err(QueryError::UnknownIdentifier(ident)) if ident == "FOO"
+    ));
+}
+
+// Export the handshake trace and return the output.
+fn export_handshake(exporter: TraceExporter) -> (u64, String) {
+    let vcd = std::fs::File::open("tests/inputs/waveform/handshake.vcd").unwrap();
+    let mut output = Vec::new();
+    let rows = exporter
+        .export(handshake_tyvcd(), std::io::BufReader::new(vcd), &mut output)
+        .unwrap();
+    (rows, String::from_utf8(output).unwrap())
+}
+
+#[test]
+fn test_export_csv_per_change() {
+    let exporter = TraceExporter::new(ExportFormat::Csv)
+        .with_variable("Handshake.io.out")
+        .with_variable("Handshake.state");
+    let (rows, csv) = export_handshake(exporter);
+    assert_eq!(rows, 7);
+    assert_eq!(
+        csv,
+        "time,Handshake.io.out.valid,Handshake.io.out.bits,Handshake.state\n\
+         0,0,0,sIdle\n\
+         20,0,0,sBusy\n\
+         40,1,5,sBusy\n\
+         50,1,2,sBusy\n\
+         60,1,35,sDone\n\
+         70,0,35,3\n\
+         80,0,35,sIdle\n"
+    );
+}
+
+#[test]
+fn test_export_json_lines_per_cycle() {
+    let exporter = TraceExporter::new(ExportFormat::JsonLines)
+        .with_variable("Handshake.io.in.bits")
+        .with_variable("Handshake.io.vec")
+        .with_clock(SignalSelector::Clock("Handshake".to_string()));
+    let (rows, jsonl) = export_handshake(exporter);
+    assert_eq!(rows, 9);
+
+    let lines: Vec<&str> = jsonl.lines().collect();
+    assert_eq!(lines.len(), 9);
+    assert_eq!(
+        lines[3],
+        r#"{"time":35,"cycle":3,"Handshake.io.in.bits.op":"SUB","Handshake.io.in.bits.data":-3,"Handshake.io.vec[0]":1,"Handshake.io.vec[1]":2}"#
+    );
+    for line in lines {
+        let row: serde_json::Value = serde_json::from_str(line).unwrap();
+        assert!(row["Handshake.io.in.bits.op"].is_string());
+    }
+}
+
+#[test]
+fn test_export_format_from_path() {
+    assert_eq!(
+        ExportFormat::from_path(Path::new("out.csv")),
+        Some(ExportFormat::Csv)
+    );
+    assert_eq!(
+        ExportFormat::from_path(Path::new("out.jsonl")),
+        Some(ExportFormat::JsonLines)
+    );
+    assert_eq!(ExportFormat::from_path(Path::new("out.vcd")), None);
+}
+
+#[test]
+fn test_fst_source() {
+    let vcd_db = load_handshake();
+    let fst_db = load_handshake_vcd("tests/inputs/waveform/handshake.fst");
+    assert_eq!(fst_db.end_time(), 90);
+    assert_eq!(
+        fst_db.value_at("Handshake.io.in.bits.data", 39).unwrap(),
+        DecodedValue::Signed(-3)
+    );
+    // The clock of the submodule is an alias of the top clock
+    assert_eq!(
+        fst_db.value_at("Handshake.core.clock", 25).unwrap(),
+        DecodedValue::Unsigned(1)
+    );
+
+    let report = WaveformDiff::new(&vcd_db, &fst_db).compare().unwrap();
+    assert!(report.is_empty(), "{}", report);
+}
+
+#[test]
+fn test_fst_source_partial_read() {
+    let mut vcd = source::open(Path::new("tests/inputs/waveform/handshake.vcd")).unwrap();
+    let mut fst = source::open(Path::new("tests/inputs/waveform/handshake.fst")).unwrap();
+    vcd.parse_header().unwrap();
+    fst.parse_header().unwrap();
+    let timestamps = |source: &mut dyn WaveformSource| {
+        source
+            .filter_map(|command| match command.unwrap() {
+                vcd::Command::Timestamp(ts) => Some(ts),
+                _ => None,
+            })
+            .take(3)
+            .collect::<Vec<_>>()
+    };
+    // The source can be dropped before the end of the trace
+    assert_eq!(timestamps(fst.as_mut()), timestamps(vcd.as_mut()));
+    drop(fst);
+}
+
+#[test_case("tests/inputs/waveform/handshake.vcd", TraceFormat::Vcd; "Test vcd extension")]
+#[test_case("tests/inputs/waveform/handshake.fst", TraceFormat::Fst; "Test fst extension")]
+#[test_case("tests/inputs/waveform/handshake.dd", TraceFormat::Vcd; "Test unknown extension")]
+fn test_trace_format_detect(path: &str, expected: TraceFormat) {
+    assert_eq!(TraceFormat::detect(Path::new(path)).unwrap(), expected);
+}
+
+#[test]
+fn test_export_fst() {
+    let exporter = || {
+        TraceExporter::new(ExportFormat::Csv)
+            .with_variable("Handshake.io.in")
+            .with_clock(SignalSelector::Clock("Handshake".to_string()))
+    };
+    let (_, expected) = export_handshake(exporter());
+
+    let source = source::open(Path::new("tests/inputs/waveform/handshake.fst")).unwrap();
+    let mut output = Vec::new();
+    exporter()
+        .export_source(handshake_tyvcd(), source, &mut output)
+        .unwrap();
+    assert_eq!(String::from_utf8(output).unwrap(), expected);
+}
+
+#[test]
+fn test_export_packed_struct() {
+    let exporter = TraceExporter::new(ExportFormat::Csv).with_variable("Handshake.packed");
+    let vcd = std::fs::File::open("tests/inputs/waveform/handshake.vcd").unwrap();
+    let mut output = Vec::new();
+    let rows = exporter
+        .export(
+            packed_handshake_tyvcd(),
+            std::io::BufReader::new(vcd),
+            &mut output,
+        )
+        .unwrap();
+    assert_eq!(rows, 4);
+    assert_eq!(
+        String::from_utf8(output).unwrap(),
+        "time,Handshake.packed.hi,Handshake.packed.lo\n\
+         0,0,0\n\
+         10,0,5\n\
+         30,15,13\n\
+         50,0,7\n"
+    );
+}
+
+#[test]
+fn test_value_at_packed_field() {
+    let db = WaveformDb::open(
+        Path::new("tests/inputs/waveform/handshake.vcd"),
+        packed_handshake_tyvcd(),
+    )
+    .unwrap();
+    assert_eq!(
+        db.value_at("Handshake.packed.hi", 30).unwrap(),
+        DecodedValue::Unsigned(15)
+    );
+    assert_eq!(
+        db.value_at("Handshake.packed.lo", 30).unwrap(),
+        DecodedValue::Unsigned(13)
+    );
+}
+
+#[test]
+fn test_export_file_fst() {
+    let exporter = || {
+        TraceExporter::new(ExportFormat::JsonLines)
+            .with_variable("Handshake.io")
+            .with_variable("Handshake.state")
+    };
+    let (expected_rows, expected) = export_handshake(exporter());
+
+    let output_path = std::env::temp_dir().join(format!(
+        "tywaves_export_file_fst_{}.jsonl",
+        std::process::id()
+    ));
+    let rows = exporter()
+        .export_file(
+            handshake_tyvcd(),
+            Path::new("tests/inputs/waveform/handshake.fst"),
+            &output_path,
+        )
+        .unwrap();
+    assert_eq!(rows, expected_rows);
+    assert_eq!(std::fs::read_to_string(&output_path).unwrap(), expected);
+    std::fs::remove_file(output_path).unwrap();
+}
+
+#[test]
+fn test_coverage_packed_struct() {
+    let db = WaveformDb::open(
+        Path::new("tests/inputs/waveform/handshake.vcd"),
+        packed_handshake_tyvcd(),
+    )
+    .unwrap();
+    let report = CoverageReport::compute(&db).unwrap();
+    let summary = |toggles, toggles_covered| CoverageSummary {
+        toggles,
+        toggles_covered,
+        enum_values: 0,
+        enum_values_covered: 0,
+    };
+    let leaf = |path: &str| report.leaves.iter().find(|l| l.path == path).unwrap();
+
+    // hi: 0 -> 0 -> 15 -> 0, lo: 0 -> 5 -> 13 -> 7
+    assert_eq!(leaf("Handshake.packed.hi").summary, summary(8, 8));
+    assert_eq!(leaf("Handshake.packed.lo").summary, summary(8, 5));
+    assert!(leaf("Handshake.packed.lo").bits[3].fall);
+    assert!(!leaf("Handshake.packed.lo").bits[0].fall);
+    let packed = report
+        .structs
+        .iter()
+        .find(|s| s.name == "Handshake.packed")
+        .unwrap();
+    assert_eq!(packed.summary, summary(16, 13));
+}